@@ -1,16 +1,21 @@
+use anchor_client::solana_sdk::instruction::{AccountMeta, Instruction};
+use anchor_client::solana_sdk::message::Message;
 use anchor_client::solana_sdk::pubkey::Pubkey;
-use anchor_client::solana_sdk::signature::{Keypair, Signer};
-use anchor_client::solana_sdk::system_instruction;
-use anchor_client::solana_client::rpc_client::RpcClient;
+use anchor_client::solana_sdk::signature::{read_keypair_file, Keypair, Signature, Signer};
+use anchor_client::solana_sdk::transaction::Transaction;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig, RpcSimulateTransactionConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
 use anchor_client::{Client, Cluster};
-use clap::{Args, Parser, Subcommand};
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use base64::Engine;
+use chrono::TimeZone;
+use chrono_tz::Tz;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use solana_sdk::commitment_config::CommitmentConfig;
 use std::rc::Rc;
 use std::str::FromStr;
 
-// Import the IDL (this will be generated after building)
-// For now, we'll define the basic structure
-
 #[derive(Parser)]
 #[command(name = "escrow-cli")]
 #[command(about = "A CLI for interacting with the Solana Escrow Engine")]
@@ -25,22 +30,60 @@ pub struct Cli {
     /// Path to keypair file
     #[arg(long, default_value = "~/.config/solana/id.json")]
     pub keypair: String,
+
+    /// Retries for transient RPC failures (expired blockhash, rate limits)
+    #[arg(long, default_value = "3")]
+    pub max_retries: u32,
+
+    /// IANA timezone timestamps are shown in, e.g. Europe/Berlin
+    #[arg(long, default_value = "UTC", value_parser = parse_timezone)]
+    pub timezone: Tz,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     /// Create a new escrow
-    Create(CreateArgs),
+    Create(Box<CreateArgs>),
+    /// Create a new escrow with the terms of an existing one
+    Clone(CloneArgs),
     /// Accept an existing escrow
     Accept(AcceptArgs),
     /// Confirm delivery and release funds
     Confirm(ConfirmArgs),
-    /// Cancel an escrow before acceptance
+    /// Cancel an escrow before acceptance, or the unvested rest of a vesting one
     Cancel(CancelArgs),
     /// Resolve timeout for an escrow
     ResolveTimeout(ResolveTimeoutArgs),
     /// Get escrow details
     Info(InfoArgs),
+    /// Get a summary table for several escrows in one round trip
+    InfoMany(InfoManyArgs),
+    /// Find a buyer's escrows whose description contains some text
+    Find(FindArgs),
+    /// Export a wallet's escrows as CSV
+    Export(ExportArgs),
+    /// Scan every escrow and flag vaults whose balance drifted from the accounting
+    Audit,
+    /// List escrows whose timeout can be resolved now, highest priority first
+    Scan(ScanArgs),
+    /// Release a single milestone to the seller
+    ReleaseMilestone(ReleaseMilestoneArgs),
+    /// Check whether a seller may accept an escrow
+    CanAccept(CanAcceptArgs),
+    /// Show an escrow's lifecycle timestamps
+    Timeline(TimelineArgs),
+    /// Estimate the SOL needed to create an escrow
+    EstimateCost(EstimateCostArgs),
+    /// Show the vault's live balance against the escrow's accounting
+    VaultBalance(VaultBalanceArgs),
+    /// Preview where the vault's tokens go if the buyer confirms now
+    Preview(PreviewArgs),
+    /// Ask the buyer to confirm delivery (as seller)
+    RequestConfirmation(RequestConfirmationArgs),
+    /// Show a settled escrow's permanent receipt, which survives closing it
+    Receipt(ReceiptArgs),
+    /// Show the deployment's config: authority, emergency mode, fees and limits
+    ConfigInfo,
 }
 
 #[derive(Args)]
@@ -59,7 +102,168 @@ pub struct CreateArgs {
     
     /// Description of the escrow
     #[arg(long)]
-    pub description: String,
+    pub description: Option<String>,
+    
+    /// Milestone as LABEL:AMOUNT (repeatable; amounts must sum to --amount)
+    #[arg(long = "milestone", value_parser = parse_milestone)]
+    pub milestones: Vec<(String, u64)>,
+    
+    /// Arbiter for disputes (defaults to the config's default arbiter)
+    #[arg(long)]
+    pub arbiter: Option<String>,
+    
+    /// Seconds before the timeout at which an expiry warning event fires (0 disables)
+    #[arg(long, default_value = "0")]
+    pub warn_lead: i64,
+    
+    /// Refund the buyer on timeout instead of paying the seller
+    #[arg(long)]
+    pub refund_on_timeout: bool,
+    
+    /// Existing token account owned by the escrow PDA to use as the vault
+    #[arg(long)]
+    pub vault: Option<String>,
+    
+    /// Collateral the seller must deposit on acceptance
+    #[arg(long, default_value = "0")]
+    pub collateral: u64,
+    
+    /// Only let the buyer or seller resolve the timeout, not any keeper
+    #[arg(long)]
+    pub parties_only_timeout: bool,
+    
+    /// Refuse to create the escrow if the mint has a freeze authority
+    #[arg(long)]
+    pub reject_freezable: bool,
+    
+    /// Seconds the seller has to mark delivery before the buyer may claim a refund (0 disables)
+    #[arg(long, default_value = "0")]
+    pub delivery_window: i64,
+    
+    /// Seconds confirmed funds vest to the seller over instead of paying out at once (0 disables)
+    #[arg(long, default_value = "0")]
+    pub vesting_duration: i64,
+    
+    /// Seconds after creation during which no seller may accept, leaving time to cancel (0 disables)
+    #[arg(long, default_value = "0", conflicts_with = "auto_accept_seller")]
+    pub accept_lock: i64,
+    
+    /// Seconds after the seller marks delivery before it counts as accepted and resolve-timeout pays the seller (0 disables)
+    #[arg(long, default_value = "0")]
+    pub dispute_window: i64,
+    
+    /// Unix time before which no seller may accept; the timeout runs from it (0 starts now)
+    #[arg(long, default_value = "0", conflicts_with = "auto_accept_seller")]
+    pub start_at: i64,
+    
+    /// Collateral share SECONDS:BPS the seller keeps for marking delivery within SECONDS of
+    /// acceptance (repeatable, up to 3, tightest first); past the last tier none comes back
+    #[arg(long = "sla-tier", value_parser = parse_sla_tier)]
+    pub sla_tiers: Vec<solana_escrow_engine::SlaTier>,
+    
+    /// Advisory urgency keepers' `scan` orders by, highest first; the program ignores it
+    #[arg(long, default_value = "0")]
+    pub priority: u8,
+    
+    /// Program whose per-seller payout PDA may also receive the seller's funds, e.g. a staking vault
+    #[arg(long)]
+    pub route_to_program: Option<String>,
+    
+    /// Longest the accepted seller may go between heartbeats before the buyer
+    /// may claim the escrow abandoned, in seconds (0 disables it)
+    #[arg(long, default_value = "0")]
+    pub heartbeat_interval: i64,
+    
+    /// Wallet that referred this escrow and shares the protocol fee
+    #[arg(long)]
+    pub referrer: Option<String>,
+    
+    /// Referrer's slice of the protocol fee, in basis points of each payout
+    #[arg(long, default_value = "0", requires = "referrer")]
+    pub referrer_bps: u16,
+    
+    /// How long a buyer's withdrawal request stays open for the seller to
+    /// contest before it may execute, in seconds (0 disables withdrawals)
+    #[arg(long, default_value = "0")]
+    pub withdraw_delay: i64,
+    
+    /// How long the buyer may stay inactive after the seller marks delivery
+    /// before the seller may claim the funds, in seconds (0 disables it)
+    #[arg(long, default_value = "0")]
+    pub buyer_inactivity_timeout: i64,
+    
+    /// Deposit nothing now and fill the amount over later deposits; no seller
+    /// may accept until it is fully funded
+    #[arg(long, conflicts_with = "auto_accept_seller")]
+    pub deferred_funding: bool,
+    
+    /// Close the escrow and vault in the transaction that settles it,
+    /// returning the rent without a separate close
+    #[arg(long)]
+    pub auto_close: bool,
+    
+    /// Seller who pre-authorized this wallet; the escrow opens already accepted by them
+    #[arg(long, conflicts_with = "vault")]
+    pub auto_accept_seller: Option<String>,
+    
+    /// Note recorded with the Memo program in the same transaction
+    #[arg(long)]
+    pub memo: Option<String>,
+}
+
+#[derive(Args)]
+pub struct CloneArgs {
+    /// Escrow whose terms to copy
+    #[arg(long)]
+    pub from: String,
+    
+    /// Amount of tokens to escrow (defaults to the source's)
+    #[arg(long)]
+    pub amount: Option<u64>,
+    
+    /// Token mint address (defaults to the source's)
+    #[arg(long)]
+    pub mint: Option<String>,
+    
+    /// Timeout duration in seconds (defaults to the source's current window)
+    #[arg(long)]
+    pub timeout: Option<i64>,
+    
+    /// Description of the escrow (defaults to the source's)
+    #[arg(long)]
+    pub description: Option<String>,
+    
+    /// Note recorded with the Memo program in the same transaction
+    #[arg(long)]
+    pub memo: Option<String>,
+}
+
+fn parse_timezone(s: &str) -> Result<Tz, String> {
+    s.parse::<Tz>()
+        .map_err(|_| format!("unknown timezone '{}', expected an IANA name like America/New_York", s))
+}
+
+fn parse_milestone(s: &str) -> Result<(String, u64), String> {
+    let (label, amount) = s
+        .rsplit_once(':')
+        .ok_or_else(|| format!("expected LABEL:AMOUNT, got '{}'", s))?;
+    let amount = amount
+        .parse::<u64>()
+        .map_err(|e| format!("invalid milestone amount '{}': {}", amount, e))?;
+    Ok((label.to_string(), amount))
+}
+
+fn parse_sla_tier(s: &str) -> Result<solana_escrow_engine::SlaTier, String> {
+    let (window, keep_bps) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected SECONDS:BPS, got '{}'", s))?;
+    let window = window
+        .parse::<i64>()
+        .map_err(|e| format!("invalid SLA window '{}': {}", window, e))?;
+    let keep_bps = keep_bps
+        .parse::<u16>()
+        .map_err(|e| format!("invalid SLA share '{}': {}", keep_bps, e))?;
+    Ok(solana_escrow_engine::SlaTier { window, keep_bps })
 }
 
 #[derive(Args)]
@@ -67,6 +271,21 @@ pub struct AcceptArgs {
     /// Escrow account address
     #[arg(long)]
     pub escrow: String,
+
+    /// Commit to delivering only this much of the escrow amount; the rest
+    /// refunds to the buyer on confirmation
+    #[arg(long)]
+    pub fulfilled_amount: Option<u64>,
+
+    /// Bind payouts to this token account of yours (or of your payout PDA under
+    /// the escrow's route program); confirm and resolve-timeout must then pay into it
+    #[arg(long)]
+    pub payout_account: Option<String>,
+
+    /// Bind payouts to your associated token account for the escrow mint,
+    /// creating it (at your expense) if it doesn't exist yet
+    #[arg(long, conflicts_with = "payout_account")]
+    pub create_payout_ata: bool,
 }
 
 #[derive(Args)]
@@ -78,6 +297,23 @@ pub struct ConfirmArgs {
     /// Seller's token account address
     #[arg(long)]
     pub seller_token_account: String,
+    
+    /// Bonus paid to the seller from your token account on top of the escrow
+    #[arg(long, default_value = "0")]
+    pub tip: u64,
+    
+    /// Wait until the release is finalized, and can no longer be rolled back,
+    /// before reporting success
+    #[arg(long)]
+    pub wait_finalized: bool,
+    
+    /// Seconds to wait for finalization with --wait-finalized
+    #[arg(long, default_value = "60")]
+    pub finalize_timeout: u64,
+    
+    /// Note recorded with the Memo program in the same transaction
+    #[arg(long)]
+    pub memo: Option<String>,
 }
 
 #[derive(Args)]
@@ -85,6 +321,10 @@ pub struct CancelArgs {
     /// Escrow account address
     #[arg(long)]
     pub escrow: String,
+    
+    /// Note recorded with the Memo program in the same transaction
+    #[arg(long)]
+    pub memo: Option<String>,
 }
 
 #[derive(Args)]
@@ -96,6 +336,10 @@ pub struct ResolveTimeoutArgs {
     /// Seller's token account address
     #[arg(long)]
     pub seller_token_account: String,
+    
+    /// Note recorded with the Memo program in the same transaction
+    #[arg(long)]
+    pub memo: Option<String>,
 }
 
 #[derive(Args)]
@@ -103,64 +347,486 @@ pub struct InfoArgs {
     /// Escrow account address
     #[arg(long)]
     pub escrow: String,
+    
+    /// Show the escrow as of this slot. Standard RPC only serves current
+    /// state, so this works only while the escrow hasn't changed since
+    #[arg(long)]
+    pub slot: Option<u64>,
+}
+
+#[derive(Args)]
+pub struct InfoManyArgs {
+    /// Comma-separated escrow account addresses
+    #[arg(long, value_delimiter = ',')]
+    pub escrows: Vec<String>,
+    
+    /// File with one escrow address per line
+    #[arg(long)]
+    pub file: Option<String>,
+}
+
+#[derive(Args)]
+pub struct FindArgs {
+    /// Text to look for in the description (case-insensitive)
+    #[arg(long)]
+    pub text: String,
+    
+    /// Buyer whose escrows to search (defaults to the wallet)
+    #[arg(long)]
+    pub buyer: Option<String>,
+}
+
+#[derive(Args)]
+pub struct ScanArgs {
+    /// Skip escrows below this priority
+    #[arg(long, default_value = "0")]
+    pub min_priority: u8,
+}
+
+/// Side of the escrows a wallet is searched for
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Role {
+    Buyer,
+    Seller,
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// Whether the wallet is the buyer or the seller of the escrows
+    #[arg(long, value_enum, default_value = "buyer")]
+    pub role: Role,
+    
+    /// Wallet whose escrows to export (defaults to the wallet)
+    #[arg(long)]
+    pub wallet: Option<String>,
+    
+    /// CSV file to write
+    #[arg(long)]
+    pub out: String,
+}
+
+#[derive(Args)]
+pub struct RequestConfirmationArgs {
+    /// Escrow account address
+    #[arg(long)]
+    pub escrow: String,
+}
+
+#[derive(Args)]
+pub struct EstimateCostArgs {
+    /// Description the escrow will be created with
+    #[arg(long)]
+    pub description: String,
+    
+    /// Number of milestones the escrow will have
+    #[arg(long, default_value = "0")]
+    pub milestones: usize,
+    
+    /// Number of collateral SLA tiers the escrow will have
+    #[arg(long, default_value = "0")]
+    pub sla_tiers: usize,
+}
+
+#[derive(Args)]
+pub struct VaultBalanceArgs {
+    /// Escrow account address
+    #[arg(long)]
+    pub escrow: String,
+}
+
+#[derive(Args)]
+pub struct PreviewArgs {
+    /// Escrow account address
+    #[arg(long)]
+    pub escrow: String,
+}
+
+#[derive(Args)]
+pub struct TimelineArgs {
+    /// Escrow account address
+    #[arg(long)]
+    pub escrow: String,
+}
+
+#[derive(Args)]
+pub struct ReceiptArgs {
+    /// Escrow account address; the escrow may already be closed
+    #[arg(long)]
+    pub escrow: String,
+
+    /// Mint the receipt first, paying its rent (the escrow must have settled
+    /// and not been closed yet)
+    #[arg(long)]
+    pub mint: bool,
+}
+
+#[derive(Args)]
+pub struct CanAcceptArgs {
+    /// Escrow account address
+    #[arg(long)]
+    pub escrow: String,
+    
+    /// Candidate seller wallet
+    #[arg(long)]
+    pub seller: String,
+}
+
+#[derive(Args)]
+pub struct ReleaseMilestoneArgs {
+    /// Escrow account address
+    #[arg(long)]
+    pub escrow: String,
+    
+    /// Seller's token account address
+    #[arg(long)]
+    pub seller_token_account: String,
+    
+    /// Index of the milestone to release
+    #[arg(long)]
+    pub index: u8,
 }
 
-const PROGRAM_ID: &str = "6ChaRcWmP5YJg21Z6AL6B6zxG8vNPJfx2EZhwFJUPeKt";
+/// Exit code for invalid command-line usage (`EX_USAGE`), kept clear of `CliError`'s codes
+const EXIT_USAGE: i32 = 64;
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
+async fn main() {
+    let cli = Cli::try_parse().unwrap_or_else(|err| {
+        if !err.use_stderr() {
+            // --help and --version
+            err.exit();
+        }
+        let _ = err.print();
+        std::process::exit(EXIT_USAGE);
+    });
     
+    if let Err(err) = run(cli).await {
+        let err = CliError::from(err);
+        eprintln!("Error: {}", err);
+        std::process::exit(err.exit_code());
+    }
+}
+
+async fn run(cli: Cli) -> anyhow::Result<()> {
     // Load keypair
     let keypair_path = shellexpand::tilde(&cli.keypair);
-    let keypair_bytes = std::fs::read(&*keypair_path)?;
-    let keypair = Keypair::from_bytes(&keypair_bytes)?;
+    let keypair = read_keypair_file(&*keypair_path)
+        .map_err(|err| anyhow::anyhow!("Failed to read keypair {}: {}", keypair_path, err))?;
     
     println!("Using wallet: {}", keypair.pubkey());
     println!("RPC URL: {}", cli.rpc_url);
     
     // Create client
     let client = Client::new_with_options(
-        Cluster::Custom(cli.rpc_url, cli.rpc_url.clone()),
+        Cluster::Custom(cli.rpc_url.clone(), cli.rpc_url.clone()),
         Rc::new(keypair),
         CommitmentConfig::confirmed(),
     );
     
-    let program = client.program(Pubkey::from_str(PROGRAM_ID)?)?;
+    let program = client.program(solana_escrow_engine::ID)?;
     
     match cli.command {
         Commands::Create(args) => {
             println!("Creating escrow...");
-            create_escrow(&program, args).await?;
+            create_escrow(&program, *args, cli.max_retries).await?;
+        }
+        Commands::Clone(args) => {
+            println!("Cloning escrow...");
+            clone_escrow(&program, args, cli.max_retries).await?;
         }
         Commands::Accept(args) => {
             println!("Accepting escrow...");
-            accept_escrow(&program, args).await?;
+            accept_escrow(&program, args, cli.max_retries).await?;
         }
         Commands::Confirm(args) => {
             println!("Confirming delivery...");
-            confirm_delivery(&program, args).await?;
+            confirm_delivery(&program, args, cli.max_retries).await?;
         }
         Commands::Cancel(args) => {
             println!("Cancelling escrow...");
-            cancel_escrow(&program, args).await?;
+            cancel_escrow(&program, args, cli.max_retries).await?;
         }
         Commands::ResolveTimeout(args) => {
             println!("Resolving timeout...");
-            resolve_timeout(&program, args).await?;
+            resolve_timeout(&program, args, cli.max_retries).await?;
         }
         Commands::Info(args) => {
             println!("Getting escrow info...");
-            get_escrow_info(&program, args).await?;
+            get_escrow_info(&program, args, cli.timezone).await?;
+        }
+        Commands::InfoMany(args) => {
+            get_many_escrow_info(&program, args).await?;
+        }
+        Commands::Find(args) => {
+            find_escrows(&program, args).await?;
+        }
+        Commands::Export(args) => {
+            export_escrows(&program, args, cli.timezone).await?;
+        }
+        Commands::Audit => {
+            audit_vaults(&program).await?;
+        }
+        Commands::Scan(args) => {
+            scan_timeouts(&program, args, cli.timezone).await?;
+        }
+        Commands::ReleaseMilestone(args) => {
+            println!("Releasing milestone...");
+            release_milestone(&program, args, cli.max_retries).await?;
+        }
+        Commands::CanAccept(args) => {
+            can_accept(&program, args).await?;
+        }
+        Commands::Timeline(args) => {
+            get_timeline(&program, args, cli.timezone).await?;
+        }
+        Commands::EstimateCost(args) => {
+            estimate_cost(&program, args).await?;
+        }
+        Commands::VaultBalance(args) => {
+            get_vault_balance(&program, args).await?;
+        }
+        Commands::Preview(args) => {
+            preview_settlement(&program, args).await?;
+        }
+        Commands::RequestConfirmation(args) => {
+            println!("Requesting delivery confirmation...");
+            request_confirmation(&program, args, cli.max_retries).await?;
+        }
+        Commands::Receipt(args) => {
+            get_receipt(&program, args, cli.max_retries).await?;
+        }
+        Commands::ConfigInfo => {
+            get_config_info(&program).await?;
         }
     }
     
     Ok(())
 }
 
-async fn create_escrow(program: &anchor_client::Program<Rc<Keypair>>, args: CreateArgs) -> anyhow::Result<()> {
+/// Failure categories a script can branch on, each with its own exit code
+#[derive(Debug, PartialEq)]
+enum CliError {
+    /// The escrow or another required account does not exist (exit 2)
+    NotFound(String),
+    /// A wallet or token account cannot cover the transfer or fee (exit 3)
+    InsufficientFunds(String),
+    /// The escrow program or another on-chain program rejected the transaction (exit 4)
+    Program(String),
+    /// The RPC node could not be reached or kept failing (exit 5)
+    Network(String),
+    /// Anything else, such as a bad argument value or keypair file (exit 1)
+    Other(String),
+}
+
+impl CliError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Other(_) => 1,
+            CliError::NotFound(_) => 2,
+            CliError::InsufficientFunds(_) => 3,
+            CliError::Program(_) => 4,
+            CliError::Network(_) => 5,
+        }
+    }
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::NotFound(message)
+            | CliError::InsufficientFunds(message)
+            | CliError::Program(message)
+            | CliError::Network(message)
+            | CliError::Other(message) => f.write_str(message),
+        }
+    }
+}
+
+/// Classifies by the error text, as RPC and program failures all arrive as
+/// client errors. Insufficient funds is checked first since the token
+/// program reports it as a program error.
+impl From<anyhow::Error> for CliError {
+    fn from(err: anyhow::Error) -> Self {
+        const NOT_FOUND: [&str; 4] = [
+            "Account not found",
+            "AccountNotFound",
+            "AccountNotInitialized",
+            "Escrow not found",
+        ];
+        const INSUFFICIENT_FUNDS: [&str; 3] = [
+            "insufficient funds",
+            "insufficient lamports",
+            "no record of a prior credit",
+        ];
+        const PROGRAM: [&str; 4] = [
+            "custom program error",
+            "Error processing Instruction",
+            "AnchorError",
+            "Simulation failed",
+        ];
+        const NETWORK: [&str; 5] = [
+            "error sending request",
+            "connection refused",
+            "connection closed",
+            "timed out",
+            "Too Many Requests",
+        ];
+        let message = format!("{:#}", err);
+        let mentions = |markers: &[&str]| markers.iter().any(|marker| message.contains(marker));
+        if mentions(&INSUFFICIENT_FUNDS) {
+            CliError::InsufficientFunds(message)
+        } else if mentions(&NOT_FOUND) {
+            CliError::NotFound(message)
+        } else if mentions(&PROGRAM) {
+            CliError::Program(message)
+        } else if mentions(&NETWORK) {
+            CliError::Network(message)
+        } else {
+            CliError::Other(message)
+        }
+    }
+}
+
+/// Delay before the first retry; doubles on each further attempt
+const RETRY_BASE_DELAY_MS: u64 = 500;
+
+/// Sends `request`, retrying transient failures with exponential backoff.
+/// Every attempt re-signs with a fresh blockhash; program errors fail at once.
+fn send_with_retry(
+    request: &anchor_client::RequestBuilder<'_, Rc<Keypair>, Box<dyn Signer + '_>>,
+    max_retries: u32,
+) -> anyhow::Result<Signature> {
+    let mut attempt = 0;
+    loop {
+        match request.send() {
+            Ok(signature) => return Ok(signature),
+            Err(err) if attempt < max_retries && is_retryable(&err.to_string()) => {
+                let delay = RETRY_BASE_DELAY_MS << attempt;
+                attempt += 1;
+                eprintln!("Transient error ({}), retry {}/{} in {}ms", err, attempt, max_retries, delay);
+                std::thread::sleep(std::time::Duration::from_millis(delay));
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// How often `wait_for_finalized` polls the signature status
+const FINALIZE_POLL_INTERVAL_MS: u64 = 2000;
+
+/// Polls until `signature` reaches `finalized` commitment. `send` returns once
+/// the transaction is `confirmed`, which a reorg can still roll back.
+fn wait_for_finalized(
+    program: &anchor_client::Program<Rc<Keypair>>,
+    signature: &Signature,
+    timeout_secs: u64,
+) -> anyhow::Result<()> {
+    let rpc = program.rpc();
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(timeout_secs);
+    loop {
+        if rpc.confirm_transaction_with_commitment(signature, CommitmentConfig::finalized())?.value {
+            return Ok(());
+        }
+        if std::time::Instant::now() >= deadline {
+            anyhow::bail!(
+                "Transaction {} is confirmed but not finalized after {}s; check it later with `solana confirm {}`",
+                signature,
+                timeout_secs,
+                signature
+            );
+        }
+        std::thread::sleep(std::time::Duration::from_millis(FINALIZE_POLL_INTERVAL_MS));
+    }
+}
+
+/// Whether a send error is transient: an expired blockhash, rate limiting or
+/// a network hiccup. Anything the program itself rejected is never retried.
+fn is_retryable(error: &str) -> bool {
+    const PROGRAM_ERRORS: [&str; 3] = ["custom program error", "Error processing Instruction", "AnchorError"];
+    const TRANSIENT_ERRORS: [&str; 7] = [
+        "BlockhashNotFound",
+        "Blockhash not found",
+        "429",
+        "Too Many Requests",
+        "timed out",
+        "connection closed",
+        "error sending request",
+    ];
+    !PROGRAM_ERRORS.iter().any(|marker| error.contains(marker))
+        && TRANSIENT_ERRORS.iter().any(|marker| error.contains(marker))
+}
+
+/// SPL Memo program (v2)
+const MEMO_PROGRAM_ID: Pubkey = anchor_client::solana_sdk::pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+
+/// Memo instruction signed by `signer`, so the note is attributable on-chain
+fn memo_instruction(memo: &str, signer: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: MEMO_PROGRAM_ID,
+        accounts: vec![AccountMeta::new_readonly(*signer, true)],
+        data: memo.as_bytes().to_vec(),
+    }
+}
+
+/// Prepends a memo instruction when `memo` is set. Instructions added to the
+/// builder run before its `accounts`/`args` instruction, in one transaction.
+fn with_memo<'a>(
+    request: anchor_client::RequestBuilder<'a, Rc<Keypair>, Box<dyn Signer + 'a>>,
+    memo: Option<&str>,
+    signer: &Pubkey,
+) -> anchor_client::RequestBuilder<'a, Rc<Keypair>, Box<dyn Signer + 'a>> {
+    match memo {
+        Some(memo) => request.instruction(memo_instruction(memo, signer)),
+        None => request,
+    }
+}
+
+/// Config PDA, if this deployment has initialized one
+fn config_if_exists(program: &anchor_client::Program<Rc<Keypair>>) -> Option<Pubkey> {
+    let (config, _bump) = Pubkey::find_program_address(&[b"config"], &program.id());
+    program.rpc().get_account(&config).ok().map(|_| config)
+}
+
+/// Fee vault for the escrow's mint, if the escrow charges a protocol fee
+fn fee_vault_for(program: &anchor_client::Program<Rc<Keypair>>, escrow: &solana_escrow_engine::Escrow) -> Option<Pubkey> {
+    (escrow.fee_bps > 0).then(|| {
+        Pubkey::find_program_address(&[b"fee-vault", escrow.mint.as_ref()], &program.id()).0
+    })
+}
+
+/// Referrer's token account (assumes ATA), if settling pays the referrer a slice of the fee
+fn referrer_account_for(escrow: &solana_escrow_engine::Escrow) -> Option<Pubkey> {
+    (escrow.referrer_bps.min(escrow.fee_bps) > 0).then(|| {
+        spl_associated_token_account::get_associated_token_address(&escrow.referrer, &escrow.mint)
+    })
+}
+
+/// Wallet an `auto_close` escrow returns its rent to when it settles
+fn rent_recipient_for(escrow: &solana_escrow_engine::Escrow) -> Option<Pubkey> {
+    escrow.auto_close.then(|| escrow.rent_recipient())
+}
+
+/// The buyer's active escrow counter, if they have opened one
+fn buyer_account_if_exists(program: &anchor_client::Program<Rc<Keypair>>, buyer: &Pubkey) -> Option<Pubkey> {
+    let (buyer_account, _bump) = Pubkey::find_program_address(&[b"buyer", buyer.as_ref()], &program.id());
+    program.rpc().get_account(&buyer_account).ok().map(|_| buyer_account)
+}
+
+/// Active escrow counter settling the escrow must decrement, if it was counted
+fn buyer_account_for(program: &anchor_client::Program<Rc<Keypair>>, escrow: &solana_escrow_engine::Escrow) -> Option<Pubkey> {
+    escrow.counted_for_buyer.then(|| {
+        Pubkey::find_program_address(&[b"buyer", escrow.seed_buyer().as_ref()], &program.id()).0
+    })
+}
+
+async fn create_escrow(program: &anchor_client::Program<Rc<Keypair>>, args: CreateArgs, retries: u32) -> anyhow::Result<()> {
     let mint = Pubkey::from_str(&args.mint)?;
     let buyer = program.payer();
+    let arbiter = args.arbiter.as_deref().map(Pubkey::from_str).transpose()?;
+    let route_to_program = args.route_to_program.as_deref().map(Pubkey::from_str).transpose()?;
+    let referrer = args.referrer.as_deref().map(Pubkey::from_str).transpose()?;
+    let existing_vault = args.vault.as_deref().map(Pubkey::from_str).transpose()?;
+    let auto_accept_seller = args.auto_accept_seller.as_deref().map(Pubkey::from_str).transpose()?;
     
     // Derive escrow PDA
     let (escrow, _bump) = Pubkey::find_program_address(
@@ -168,11 +834,10 @@ async fn create_escrow(program: &anchor_client::Program<Rc<Keypair>>, args: Crea
         &program.id(),
     );
     
-    // Derive vault PDA
-    let (vault_token_account, _vault_bump) = Pubkey::find_program_address(
-        &[b"vault", escrow.as_ref()],
-        &program.id(),
-    );
+    // Derive vault PDA unless an existing vault was supplied
+    let vault_token_account = existing_vault.unwrap_or_else(|| {
+        Pubkey::find_program_address(&[b"vault", escrow.as_ref()], &program.id()).0
+    });
     
     // Find buyer's token account (simplified - assumes ATA)
     let buyer_token_account = spl_associated_token_account::get_associated_token_address(
@@ -182,26 +847,91 @@ async fn create_escrow(program: &anchor_client::Program<Rc<Keypair>>, args: Crea
     
     println!("Escrow address: {}", escrow);
     println!("Vault address: {}", vault_token_account);
-    println!("Creating escrow for {} tokens...", args.amount);
+    if args.deferred_funding {
+        println!("Creating escrow for {} tokens, to be deposited later...", args.amount);
+    } else {
+        println!("Creating escrow for {} tokens...", args.amount);
+    }
     
-    let tx = program
-        .request()
-        .accounts(solana_escrow_engine::accounts::CreateEscrow {
-            buyer,
-            escrow,
-            mint,
-            buyer_token_account,
-            vault_token_account,
-            token_program: spl_token::ID,
-            system_program: solana_sdk::system_program::ID,
-            rent: solana_sdk::sysvar::rent::ID,
-        })
-        .args(solana_escrow_engine::instruction::CreateEscrow {
-            amount: args.amount,
-            timeout_duration: args.timeout,
-            description: args.description,
-        })
-        .send()?;
+    let milestones: Vec<_> = args
+        .milestones
+        .into_iter()
+        .map(|(label, amount)| solana_escrow_engine::MilestoneInput { label, amount })
+        .collect();
+    let timeout_beneficiary = if args.refund_on_timeout {
+        solana_escrow_engine::TimeoutBeneficiary::Buyer
+    } else {
+        solana_escrow_engine::TimeoutBeneficiary::Seller
+    };
+    let config = config_if_exists(program);
+    let buyer_account = buyer_account_if_exists(program, &buyer);
+    let seller_authorization = auto_accept_seller.map(|seller| {
+        Pubkey::find_program_address(&[b"seller-auth", buyer.as_ref(), seller.as_ref()], &program.id()).0
+    });
+    if let Some(seller) = auto_accept_seller {
+        println!("Auto-accepting for pre-authorized seller {}", seller);
+    }
+    
+    let params = solana_escrow_engine::CreateParams {
+        amount: args.amount,
+        timeout_duration: args.timeout,
+        description: args.description.unwrap_or_default(),
+        milestones,
+        arbiter,
+        warn_lead: args.warn_lead,
+        timeout_beneficiary,
+        required_collateral: args.collateral,
+        permissionless_timeout: !args.parties_only_timeout,
+        reject_freezable: args.reject_freezable,
+        delivery_window: args.delivery_window,
+        vesting_duration: args.vesting_duration,
+        accept_lock: args.accept_lock,
+        dispute_window: args.dispute_window,
+        start_at: args.start_at,
+        sla_tiers: args.sla_tiers,
+        priority: args.priority,
+        route_to_program,
+        heartbeat_interval: args.heartbeat_interval,
+        referrer,
+        referrer_bps: args.referrer_bps,
+        withdraw_delay: args.withdraw_delay,
+        buyer_inactivity_timeout: args.buyer_inactivity_timeout,
+        deferred_funding: args.deferred_funding,
+        auto_close: args.auto_close,
+    };
+    let request = with_memo(program.request(), args.memo.as_deref(), &buyer);
+    let request = if existing_vault.is_some() {
+        request
+            .accounts(solana_escrow_engine::accounts::CreateEscrowWithVault {
+                buyer,
+                escrow,
+                mint,
+                buyer_token_account,
+                vault_token_account,
+                config,
+                buyer_account,
+                token_program: spl_token::ID,
+                system_program: solana_sdk::system_program::ID,
+            })
+            .args(solana_escrow_engine::instruction::CreateEscrowWithVault { params })
+    } else {
+        request
+            .accounts(solana_escrow_engine::accounts::CreateEscrow {
+                buyer,
+                escrow,
+                mint,
+                buyer_token_account,
+                vault_token_account,
+                config,
+                seller_authorization,
+                buyer_account,
+                token_program: spl_token::ID,
+                system_program: solana_sdk::system_program::ID,
+                rent: solana_sdk::sysvar::rent::ID,
+            })
+            .args(solana_escrow_engine::instruction::CreateEscrow { params })
+    };
+    let tx = send_with_retry(&request, retries)?;
     
     println!("Transaction signature: {}", tx);
     println!("Escrow created successfully!");
@@ -209,126 +939,876 @@ async fn create_escrow(program: &anchor_client::Program<Rc<Keypair>>, args: Crea
     Ok(())
 }
 
-async fn accept_escrow(program: &anchor_client::Program<Rc<Keypair>>, args: AcceptArgs) -> anyhow::Result<()> {
-    let escrow = Pubkey::from_str(&args.escrow)?;
-    let seller = program.payer();
+async fn clone_escrow(program: &anchor_client::Program<Rc<Keypair>>, args: CloneArgs, retries: u32) -> anyhow::Result<()> {
+    let source = Pubkey::from_str(&args.from)?;
+    let source_data = fetch_escrow(program, &source)?;
+    let create_args = clone_create_args(&source_data, args)?;
     
-    println!("Seller {} accepting escrow {}", seller, escrow);
+    // A top-level escrow lives at [b"escrow", buyer, mint], so a wallet can't
+    // clone its own open escrow without switching mints
+    let mint = Pubkey::from_str(&create_args.mint)?;
+    let (escrow, _bump) = Pubkey::find_program_address(
+        &[b"escrow", program.payer().as_ref(), mint.as_ref()],
+        &program.id(),
+    );
+    if program.rpc().get_account(&escrow).is_ok() {
+        anyhow::bail!(
+            "You already have an escrow for mint {} at {}; close it or pass a different --mint",
+            mint,
+            escrow
+        );
+    }
     
-    let tx = program
-        .request()
-        .accounts(solana_escrow_engine::accounts::AcceptEscrow {
-            seller,
-            escrow,
-        })
-        .args(solana_escrow_engine::instruction::AcceptEscrow {})
-        .send()?;
+    create_escrow(program, create_args, retries).await
+}
+
+/// Create arguments carrying `source`'s terms, overridden by `args`. Anything
+/// tied to the source's counterparty or schedule isn't copied: the clone opens
+/// unaccepted, with a fresh vault and starting now, and its deadlines keep
+/// their length from the source's start. Milestones only carry over while the
+/// amount they sum to does.
+fn clone_create_args(source: &solana_escrow_engine::Escrow, args: CloneArgs) -> anyhow::Result<CreateArgs> {
+    if source.is_swap() {
+        anyhow::bail!("Swap escrows can't be cloned; create a new swap instead");
+    }
     
-    println!("Transaction signature: {}", tx);
-    println!("Escrow accepted successfully!");
+    let start = source.start_at.max(source.created_at());
+    let amount = args.amount.unwrap_or(source.amount);
+    let milestones = if amount == source.amount {
+        source
+            .milestones
+            .iter()
+            .map(|milestone| (milestone.label.clone(), milestone.amount))
+            .collect()
+    } else {
+        if !source.milestones.is_empty() {
+            println!("Not copying milestones: they sum to the source amount {}", source.amount);
+        }
+        Vec::new()
+    };
+    let optional_key = |key: Pubkey| (key != Pubkey::default()).then(|| key.to_string());
     
-    Ok(())
+    Ok(CreateArgs {
+        mint: args.mint.unwrap_or_else(|| source.mint.to_string()),
+        amount,
+        timeout: args.timeout.unwrap_or(source.timeout_at - start),
+        description: Some(args.description.unwrap_or_else(|| source.description.clone())),
+        milestones,
+        arbiter: optional_key(source.arbiter),
+        warn_lead: source.warn_lead,
+        refund_on_timeout: source.timeout_beneficiary == solana_escrow_engine::TimeoutBeneficiary::Buyer,
+        vault: None,
+        collateral: source.required_collateral,
+        parties_only_timeout: !source.permissionless_timeout,
+        reject_freezable: false,
+        delivery_window: if source.delivery_deadline > 0 { source.delivery_deadline - start } else { 0 },
+        vesting_duration: source.vesting_duration,
+        accept_lock: source.accept_lock,
+        dispute_window: source.dispute_window,
+        start_at: 0,
+        sla_tiers: source.sla_tiers.clone(),
+        priority: source.priority,
+        route_to_program: optional_key(source.route_to_program),
+        heartbeat_interval: source.heartbeat_interval,
+        referrer: optional_key(source.referrer),
+        referrer_bps: source.referrer_bps,
+        withdraw_delay: source.withdraw_delay,
+        buyer_inactivity_timeout: source.buyer_inactivity_timeout,
+        deferred_funding: false,
+        auto_close: source.auto_close,
+        auto_accept_seller: None,
+        memo: args.memo,
+    })
 }
 
-async fn confirm_delivery(program: &anchor_client::Program<Rc<Keypair>>, args: ConfirmArgs) -> anyhow::Result<()> {
+/// An escrow account decoded as far as its layout allows
+enum DecodedEscrow {
+    /// Every field, from a current-layout account
+    Current(solana_escrow_engine::Escrow),
+    /// An unmigrated v1 account, filled in the way `migrate_v2` would
+    V1(solana_escrow_engine::Escrow),
+    /// Fields read in order until one no longer decoded
+    Partial(PartialEscrow),
+}
+
+impl DecodedEscrow {
+    /// The full escrow, if every field decoded
+    fn full(self) -> Option<solana_escrow_engine::Escrow> {
+        match self {
+            DecodedEscrow::Current(escrow) | DecodedEscrow::V1(escrow) => Some(escrow),
+            DecodedEscrow::Partial(_) => None,
+        }
+    }
+}
+
+/// The leading escrow fields, each `None` once decoding stopped
+#[derive(Default)]
+struct PartialEscrow {
+    buyer: Option<Pubkey>,
+    seller: Option<Pubkey>,
+    mint: Option<Pubkey>,
+    amount: Option<u64>,
+    state: Option<solana_escrow_engine::EscrowState>,
+    timeout_at: Option<i64>,
+    description: Option<String>,
+}
+
+impl PartialEscrow {
+    /// Reads the fields in layout order, keeping those before the first
+    /// that fails
+    fn decode(mut data: &[u8]) -> Self {
+        let data = &mut data;
+        let mut partial = PartialEscrow::default();
+        let mut read = || -> Option<()> {
+            partial.buyer = Some(Pubkey::deserialize(data).ok()?);
+            partial.seller = Some(Pubkey::deserialize(data).ok()?);
+            partial.mint = Some(Pubkey::deserialize(data).ok()?);
+            partial.amount = Some(u64::deserialize(data).ok()?);
+            partial.state = Some(solana_escrow_engine::EscrowState::deserialize(data).ok()?);
+            Vec::<solana_escrow_engine::TimelineEntry>::deserialize(data).ok()?;
+            partial.timeout_at = Some(i64::deserialize(data).ok()?);
+            partial.description = Some(String::deserialize(data).ok()?);
+            Some(())
+        };
+        read();
+        partial
+    }
+}
+
+/// Decodes the escrow at `address` if `owner` is the program and the
+/// discriminator matches. Escrows carry no version byte, so the layout is
+/// picked by size the way `migrate_v2` does; an account that fails its
+/// layout still yields the fields that decode.
+fn decode_escrow_versioned(
+    program_id: &Pubkey,
+    address: &Pubkey,
+    owner: &Pubkey,
+    data: &[u8],
+) -> Option<DecodedEscrow> {
+    use solana_escrow_engine::Escrow;
+    
+    if owner != program_id || !data.starts_with(Escrow::DISCRIMINATOR) {
+        return None;
+    }
+    let body = &data[8..];
+    if body.len() != Escrow::LEN_V1 {
+        return Some(match Escrow::deserialize(&mut &body[..]) {
+            Ok(escrow) => DecodedEscrow::Current(escrow),
+            Err(_) => DecodedEscrow::Partial(PartialEscrow::decode(body)),
+        });
+    }
+    
+    // The v1 fields are a prefix of the current layout, so zero-padding
+    // decodes the added fields as their defaults
+    let mut padded = body.to_vec();
+    padded.resize(Escrow::LEN, 0);
+    Some(match Escrow::deserialize(&mut &padded[..]) {
+        Ok(mut escrow) => {
+            escrow.timeout_beneficiary = solana_escrow_engine::TimeoutBeneficiary::Seller;
+            escrow.vault = Pubkey::find_program_address(&[b"vault", address.as_ref()], program_id).0;
+            escrow.original_depositor = escrow.buyer;
+            escrow.permissionless_timeout = true;
+            DecodedEscrow::V1(escrow)
+        }
+        Err(_) => DecodedEscrow::Partial(PartialEscrow::decode(body)),
+    })
+}
+
+/// Decodes the escrow at `address` if every field of its layout decodes
+fn decode_escrow(
+    program_id: &Pubkey,
+    address: &Pubkey,
+    owner: &Pubkey,
+    data: &[u8],
+) -> Option<solana_escrow_engine::Escrow> {
+    decode_escrow_versioned(program_id, address, owner, data)?.full()
+}
+
+/// Fetches and decodes the escrow at `escrow`, failing with a clear message
+/// when it's missing, not an escrow, or only partially decodes
+fn fetch_escrow_versioned(
+    program: &anchor_client::Program<Rc<Keypair>>,
+    escrow: &Pubkey,
+) -> anyhow::Result<DecodedEscrow> {
+    let account = program
+        .rpc()
+        .get_multiple_accounts(&[*escrow])?
+        .pop()
+        .flatten()
+        .ok_or_else(|| anyhow::anyhow!("Escrow not found: no account exists at {}", escrow))?;
+    decode_escrow_versioned(&program.id(), escrow, &account.owner, &account.data)
+        .ok_or_else(|| anyhow::anyhow!("Account {} is not an escrow", escrow))
+}
+
+/// Fetches the escrow as it was at `slot`. Standard RPC only serves current
+/// state, so the account is read with `slot` as its minimum context slot and
+/// accepted only if no transaction has written it since; older state needs
+/// an archive endpoint.
+fn fetch_escrow_at_slot(
+    program: &anchor_client::Program<Rc<Keypair>>,
+    escrow: &Pubkey,
+    slot: u64,
+) -> anyhow::Result<DecodedEscrow> {
+    let rpc = program.rpc();
+    let config = RpcAccountInfoConfig {
+        encoding: Some(anchor_client::solana_account_decoder::UiAccountEncoding::Base64),
+        commitment: Some(rpc.commitment()),
+        min_context_slot: Some(slot),
+        data_slice: None,
+    };
+    let response = rpc
+        .get_account_with_config(escrow, config)
+        .map_err(|err| anyhow::anyhow!("Endpoint can't serve slot {}: {}", slot, err))?;
+    let account = response
+        .value
+        .ok_or_else(|| anyhow::anyhow!("Escrow not found: no account exists at {}", escrow))?;
+    
+    let history = GetConfirmedSignaturesForAddress2Config {
+        limit: Some(1),
+        commitment: Some(rpc.commitment()),
+        ..GetConfirmedSignaturesForAddress2Config::default()
+    };
+    let last_write = rpc
+        .get_signatures_for_address_with_config(escrow, history)?
+        .first()
+        .map(|signature| signature.slot);
+    check_unchanged_since(slot, last_write, response.context.slot)?;
+    
+    decode_escrow_versioned(&program.id(), escrow, &account.owner, &account.data)
+        .ok_or_else(|| anyhow::anyhow!("Account {} is not an escrow", escrow))
+}
+
+/// Whether state read at `context_slot` still holds at `slot`, given the
+/// slot of the last transaction that touched the account
+fn check_unchanged_since(slot: u64, last_write: Option<u64>, context_slot: u64) -> anyhow::Result<()> {
+    match last_write {
+        Some(changed) if changed <= slot => Ok(()),
+        Some(changed) => anyhow::bail!(
+            "Slot {} is too old for this endpoint: the escrow last changed at slot {} and standard RPC \
+             only serves its state as of slot {}; use an archive RPC for older state",
+            slot,
+            changed,
+            context_slot
+        ),
+        None => anyhow::bail!(
+            "Slot {} is too old for this endpoint: it has no transaction history for the escrow, so it \
+             can't tell whether the state as of slot {} still held then",
+            slot,
+            context_slot
+        ),
+    }
+}
+
+/// Like `fetch_escrow_versioned`, but only for escrows that fully decode
+fn fetch_escrow(
+    program: &anchor_client::Program<Rc<Keypair>>,
+    escrow: &Pubkey,
+) -> anyhow::Result<solana_escrow_engine::Escrow> {
+    fetch_escrow_versioned(program, escrow)?.full().ok_or_else(|| {
+        anyhow::anyhow!("Escrow {} doesn't decode with this CLI's layout; `info` shows what it can", escrow)
+    })
+}
+
+/// Fetches the escrow a command acts on, so a wrong address fails with a
+/// clear message rather than the transaction's deserialization error. Prints
+/// the current state and warns when `action` needs a different one.
+fn load_escrow(
+    program: &anchor_client::Program<Rc<Keypair>>,
+    escrow: &Pubkey,
+    action: &str,
+    required: solana_escrow_engine::EscrowState,
+) -> anyhow::Result<solana_escrow_engine::Escrow> {
+    let escrow_data = fetch_escrow(program, escrow)?;
+    
+    println!("Current state: {:?}", escrow_data.state);
+    if escrow_data.state != required {
+        println!(
+            "Warning: {} needs the escrow to be {:?}; the transaction will likely fail",
+            action, required
+        );
+    }
+    Ok(escrow_data)
+}
+
+async fn accept_escrow(program: &anchor_client::Program<Rc<Keypair>>, args: AcceptArgs, retries: u32) -> anyhow::Result<()> {
+    let escrow = Pubkey::from_str(&args.escrow)?;
+    let seller = program.payer();
+    let escrow_data = load_escrow(program, &escrow, "accept", solana_escrow_engine::EscrowState::Created)?;
+    
+    let mut seller_payout_account = args
+        .payout_account
+        .as_deref()
+        .map(Pubkey::from_str)
+        .transpose()?;
+    let seller_ata = spl_associated_token_account::get_associated_token_address(&seller, &escrow_data.mint);
+    
+    println!("Seller {} accepting escrow {}", seller, escrow);
+    
+    let accept_accounts = solana_escrow_engine::accounts::AcceptEscrow {
+        seller,
+        escrow,
+        seller_payout_account,
+        seller_payout_ata: args.create_payout_ata.then_some(seller_ata),
+        config: config_if_exists(program),
+        mint: args.create_payout_ata.then_some(escrow_data.mint),
+        token_program: args.create_payout_ata.then_some(spl_token::ID),
+        associated_token_program: args.create_payout_ata.then_some(spl_associated_token_account::ID),
+        system_program: args.create_payout_ata.then_some(solana_sdk::system_program::ID),
+    };
+    
+    // Escrows that require collateral take it from the seller's ATA
+    let request = if escrow_data.required_collateral > 0 {
+        println!("Depositing {} collateral", escrow_data.required_collateral);
+        // The collateral comes from the ATA, so it already exists to bind
+        if args.create_payout_ata {
+            seller_payout_account = Some(seller_ata);
+        }
+        program
+            .request()
+            .accounts(solana_escrow_engine::accounts::AcceptWithCollateral {
+                seller,
+                escrow,
+                vault_token_account: escrow_data.vault,
+                seller_token_account: seller_ata,
+                seller_payout_account,
+                config: config_if_exists(program),
+                token_program: spl_token::ID,
+            })
+            .args(solana_escrow_engine::instruction::AcceptWithCollateral {})
+    } else if let Some(fulfilled_amount) = args.fulfilled_amount {
+        println!("Committing to {} of {}", fulfilled_amount, escrow_data.amount);
+        program
+            .request()
+            .accounts(accept_accounts)
+            .args(solana_escrow_engine::instruction::PartialAccept { fulfilled_amount })
+    } else {
+        program
+            .request()
+            .accounts(accept_accounts)
+            .args(solana_escrow_engine::instruction::AcceptEscrow {})
+    };
+    let tx = send_with_retry(&request, retries)?;
+    
+    println!("Transaction signature: {}", tx);
+    println!("Escrow accepted successfully!");
+    
+    Ok(())
+}
+
+async fn request_confirmation(program: &anchor_client::Program<Rc<Keypair>>, args: RequestConfirmationArgs, retries: u32) -> anyhow::Result<()> {
+    let escrow = Pubkey::from_str(&args.escrow)?;
+    let seller = program.payer();
+    
+    let request = program
+        .request()
+        .accounts(solana_escrow_engine::accounts::RequestConfirmation {
+            seller,
+            escrow,
+        })
+        .args(solana_escrow_engine::instruction::RequestConfirmation {});
+    let tx = send_with_retry(&request, retries)?;
+    
+    let escrow_data = fetch_escrow(program, &escrow)?;
+    println!("Transaction signature: {}", tx);
+    println!("Confirmation requested from buyer {}", escrow_data.buyer);
+    if escrow_data.auto_confirm_after > 0 {
+        println!(
+            "Funds can be released via resolve-timeout after {}",
+            escrow_data.confirmation_requested_at + escrow_data.auto_confirm_after
+        );
+    }
+    
+    Ok(())
+}
+
+async fn confirm_delivery(program: &anchor_client::Program<Rc<Keypair>>, args: ConfirmArgs, retries: u32) -> anyhow::Result<()> {
     let escrow = Pubkey::from_str(&args.escrow)?;
     let seller_token_account = Pubkey::from_str(&args.seller_token_account)?;
     let buyer = program.payer();
+    let escrow_data = load_escrow(program, &escrow, "confirm", solana_escrow_engine::EscrowState::Accepted)?;
     
-    // Derive vault PDA
-    let (vault_token_account, _vault_bump) = Pubkey::find_program_address(
-        &[b"vault", escrow.as_ref()],
-        &program.id(),
-    );
+    let vault_token_account = escrow_data.vault;
     
-    let tx = program
-        .request()
+    // Partially accepted escrows refund the unfulfilled part to the buyer's
+    // ATA, which also receives any collateral forfeited under the SLA tiers
+    // and pays any tip
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs() as i64;
+    let refund = !matches!(escrow_data.unfulfilled_amount(), Ok(0))
+        || !matches!(escrow_data.collateral_penalty(now), Ok(0));
+    let buyer_token_account = (refund || args.tip > 0).then(|| {
+        spl_associated_token_account::get_associated_token_address(&escrow_data.buyer, &escrow_data.mint)
+    });
+    
+    let request = with_memo(program.request(), args.memo.as_deref(), &buyer)
         .accounts(solana_escrow_engine::accounts::ConfirmDelivery {
             buyer,
             escrow,
             vault_token_account,
             seller_token_account,
+            buyer_token_account,
+            fee_vault: fee_vault_for(program, &escrow_data),
+            referrer_token_account: referrer_account_for(&escrow_data),
+            config: config_if_exists(program),
+            buyer_account: buyer_account_for(program, &escrow_data),
+            rent_recipient: rent_recipient_for(&escrow_data),
             token_program: spl_token::ID,
         })
-        .args(solana_escrow_engine::instruction::ConfirmDelivery {})
-        .send()?;
+        .args(solana_escrow_engine::instruction::ConfirmDelivery { tip: args.tip });
+    let tx = send_with_retry(&request, retries)?;
     
     println!("Transaction signature: {}", tx);
-    println!("Delivery confirmed, funds released!");
+    if args.wait_finalized {
+        println!("Waiting for finalization...");
+        wait_for_finalized(program, &tx, args.finalize_timeout)?;
+        println!("Delivery finalized, funds released!");
+    } else {
+        println!("Delivery confirmed, funds released!");
+    }
+    if args.tip > 0 {
+        println!("Tipped the seller {}", args.tip);
+    }
     
     Ok(())
 }
 
-async fn cancel_escrow(program: &anchor_client::Program<Rc<Keypair>>, args: CancelArgs) -> anyhow::Result<()> {
+async fn cancel_escrow(program: &anchor_client::Program<Rc<Keypair>>, args: CancelArgs, retries: u32) -> anyhow::Result<()> {
     let escrow = Pubkey::from_str(&args.escrow)?;
     let buyer = program.payer();
     
-    // Get escrow data to find mint
-    let escrow_data: solana_escrow_engine::Escrow = program.account(escrow)?;
+    // Get escrow data to find mint; a vesting escrow cancels its unvested rest
+    let escrow_data = fetch_escrow(program, &escrow)?;
+    println!("Current state: {:?}", escrow_data.state);
+    let vesting = escrow_data.state == solana_escrow_engine::EscrowState::Vesting;
+    if !vesting && escrow_data.state != solana_escrow_engine::EscrowState::Created {
+        println!("Warning: cancel needs the escrow to be Created or Vesting; the transaction will likely fail");
+    }
     
-    // Derive vault PDA
-    let (vault_token_account, _vault_bump) = Pubkey::find_program_address(
-        &[b"vault", escrow.as_ref()],
-        &program.id(),
-    );
+    let vault_token_account = escrow_data.vault;
     
-    // Find buyer's token account (simplified - assumes ATA)
+    // Refunds go to the depositor's token account (assumes ATA)
     let buyer_token_account = spl_associated_token_account::get_associated_token_address(
-        &buyer,
+        &escrow_data.depositor(),
         &escrow_data.mint,
     );
     
-    let tx = program
-        .request()
+    let request = with_memo(program.request(), args.memo.as_deref(), &buyer)
         .accounts(solana_escrow_engine::accounts::CancelEscrow {
             buyer,
             escrow,
             vault_token_account,
             buyer_token_account,
+            config: config_if_exists(program),
+            buyer_account: buyer_account_for(program, &escrow_data),
+            rent_recipient: rent_recipient_for(&escrow_data),
             token_program: spl_token::ID,
         })
-        .args(solana_escrow_engine::instruction::CancelEscrow {})
-        .send()?;
+        .args(solana_escrow_engine::instruction::CancelEscrow {});
+    let tx = send_with_retry(&request, retries)?;
     
     println!("Transaction signature: {}", tx);
-    println!("Escrow cancelled, funds returned!");
+    if vesting {
+        println!("Vesting cancelled, unvested funds returned; the vested part stays claimable by the seller");
+    } else {
+        println!("Escrow cancelled, funds returned!");
+    }
     
     Ok(())
 }
 
-async fn resolve_timeout(program: &anchor_client::Program<Rc<Keypair>>, args: ResolveTimeoutArgs) -> anyhow::Result<()> {
+async fn resolve_timeout(program: &anchor_client::Program<Rc<Keypair>>, args: ResolveTimeoutArgs, retries: u32) -> anyhow::Result<()> {
     let escrow = Pubkey::from_str(&args.escrow)?;
     let seller_token_account = Pubkey::from_str(&args.seller_token_account)?;
     let resolver = program.payer();
+    let escrow_data = load_escrow(program, &escrow, "resolve-timeout", solana_escrow_engine::EscrowState::Accepted)?;
     
-    // Derive vault PDA
-    let (vault_token_account, _vault_bump) = Pubkey::find_program_address(
-        &[b"vault", escrow.as_ref()],
-        &program.id(),
-    );
+    let vault_token_account = escrow_data.vault;
     
-    let tx = program
-        .request()
+    // Buyer-favoring escrows refund to the buyer's ATA, as do the
+    // unfulfilled part of a partial acceptance and collateral forfeited under
+    // the SLA tiers. A lapsed dispute window pays the seller whatever the
+    // beneficiary.
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs() as i64;
+    let refund = escrow_data.timeout_payee(now) == solana_escrow_engine::TimeoutBeneficiary::Buyer;
+    let pays_buyer = refund
+        || !matches!(escrow_data.unfulfilled_amount(), Ok(0))
+        || !matches!(escrow_data.collateral_penalty(now), Ok(0));
+    let buyer_token_account = pays_buyer.then(|| {
+        spl_associated_token_account::get_associated_token_address(&escrow_data.buyer, &escrow_data.mint)
+    });
+    
+    let request = with_memo(program.request(), args.memo.as_deref(), &resolver)
         .accounts(solana_escrow_engine::accounts::ResolveTimeout {
             resolver,
             escrow,
             vault_token_account,
             seller_token_account,
+            buyer_token_account,
+            fee_vault: fee_vault_for(program, &escrow_data),
+            referrer_token_account: referrer_account_for(&escrow_data),
+            config: config_if_exists(program),
+            buyer_account: buyer_account_for(program, &escrow_data),
+            rent_recipient: rent_recipient_for(&escrow_data),
             token_program: spl_token::ID,
         })
-        .args(solana_escrow_engine::instruction::ResolveTimeout {})
-        .send()?;
+        .args(solana_escrow_engine::instruction::ResolveTimeout {});
+    let tx = send_with_retry(&request, retries)?;
     
     println!("Transaction signature: {}", tx);
-    println!("Timeout resolved, funds released to seller!");
+    if refund {
+        println!("Timeout resolved, funds refunded to buyer!");
+    } else {
+        println!("Timeout resolved, funds released to seller!");
+    }
     
     Ok(())
 }
 
-async fn get_escrow_info(program: &anchor_client::Program<Rc<Keypair>>, args: InfoArgs) -> anyhow::Result<()> {
+async fn release_milestone(program: &anchor_client::Program<Rc<Keypair>>, args: ReleaseMilestoneArgs, retries: u32) -> anyhow::Result<()> {
     let escrow = Pubkey::from_str(&args.escrow)?;
+    let seller_token_account = Pubkey::from_str(&args.seller_token_account)?;
+    let buyer = program.payer();
+    let escrow_data = fetch_escrow(program, &escrow)?;
     
-    let escrow_data: solana_escrow_engine::Escrow = program.account(escrow)?;
+    // A retried command whose first attempt landed finds the milestone
+    // already released; the program would refuse it anyway
+    let milestone = escrow_data
+        .milestones
+        .get(args.index as usize)
+        .ok_or_else(|| anyhow::anyhow!("Escrow {} has no milestone {}", escrow, args.index))?;
+    if milestone.released {
+        println!("Milestone {} ({}) was already released; nothing to do", args.index, milestone.label);
+        return Ok(());
+    }
+    
+    let vault_token_account = escrow_data.vault;
+    
+    // Collateral forfeited under the SLA tiers goes to the buyer's ATA
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs() as i64;
+    let buyer_token_account = (!matches!(escrow_data.collateral_penalty(now), Ok(0))).then(|| {
+        spl_associated_token_account::get_associated_token_address(&escrow_data.buyer, &escrow_data.mint)
+    });
+    
+    let request = program
+        .request()
+        .accounts(solana_escrow_engine::accounts::ReleaseMilestone {
+            buyer,
+            escrow,
+            vault_token_account,
+            seller_token_account,
+            buyer_token_account,
+            fee_vault: fee_vault_for(program, &escrow_data),
+            referrer_token_account: referrer_account_for(&escrow_data),
+            config: config_if_exists(program),
+            buyer_account: buyer_account_for(program, &escrow_data),
+            token_program: spl_token::ID,
+        })
+        .args(solana_escrow_engine::instruction::ReleaseMilestone { index: args.index });
+    let tx = send_with_retry(&request, retries)?;
+    
+    println!("Transaction signature: {}", tx);
+    println!("Milestone {} released!", args.index);
+    
+    Ok(())
+}
+
+/// Simulates `instructions` and decodes the program's return data as `T`
+fn simulate_view<T: AnchorDeserialize>(
+    program: &anchor_client::Program<Rc<Keypair>>,
+    instructions: Vec<Instruction>,
+) -> anyhow::Result<T> {
+    let message = Message::new(&instructions, Some(&program.payer()));
+    let tx = Transaction::new_unsigned(message);
+    let config = RpcSimulateTransactionConfig {
+        sig_verify: false,
+        replace_recent_blockhash: true,
+        ..RpcSimulateTransactionConfig::default()
+    };
+    let result = program.rpc().simulate_transaction_with_config(&tx, config)?.value;
+    
+    if let Some(err) = result.err {
+        anyhow::bail!("Simulation failed: {}", err);
+    }
+    let return_data = result
+        .return_data
+        .ok_or_else(|| anyhow::anyhow!("Program returned no data"))?;
+    let bytes = base64::engine::general_purpose::STANDARD.decode(return_data.data.0)?;
+    
+    Ok(T::try_from_slice(&bytes)?)
+}
+
+async fn can_accept(program: &anchor_client::Program<Rc<Keypair>>, args: CanAcceptArgs) -> anyhow::Result<()> {
+    let escrow = Pubkey::from_str(&args.escrow)?;
+    let seller = Pubkey::from_str(&args.seller)?;
+    
+    let instructions = program
+        .request()
+        .accounts(solana_escrow_engine::accounts::CheckCanAccept { escrow })
+        .args(solana_escrow_engine::instruction::CheckCanAccept { seller })
+        .instructions()?;
+    let check: solana_escrow_engine::AcceptCheck = simulate_view(program, instructions)?;
+    
+    if check.allowed {
+        println!("Seller {} can accept escrow {}", seller, escrow);
+    } else {
+        println!("Seller {} cannot accept escrow {} (error code {})", seller, escrow, check.reason);
+    }
+    
+    Ok(())
+}
+
+async fn get_timeline(program: &anchor_client::Program<Rc<Keypair>>, args: TimelineArgs, tz: Tz) -> anyhow::Result<()> {
+    let escrow = Pubkey::from_str(&args.escrow)?;
+    
+    let instructions = program
+        .request()
+        .accounts(solana_escrow_engine::accounts::GetTimeline { escrow })
+        .args(solana_escrow_engine::instruction::GetTimeline {})
+        .instructions()?;
+    let timeline: solana_escrow_engine::Timeline = simulate_view(program, instructions)?;
+    
+    let show = |label: &str, timestamp: i64| {
+        if timestamp > 0 {
+            println!("{:<24} {}", label, format_timestamp(timestamp, tz));
+        } else {
+            println!("{:<24} -", label);
+        }
+    };
+    println!("=== Timeline for {} ===", escrow);
+    println!("{:<24} {:?}", "State:", timeline.state);
+    show("Created:", timeline.created_at);
+    show("Accepted:", timeline.accepted_at);
+    show("Confirmation requested:", timeline.confirmation_requested_at);
+    show("Disputed:", timeline.disputed_at);
+    show("Completed:", timeline.completed_at);
+    show("Cancelled:", timeline.cancelled_at);
+    show("Timeout at:", timeline.timeout_at);
+    show("Timeout cap:", timeline.max_timeout_at);
+    show("Release deadline:", timeline.release_deadline);
+    
+    Ok(())
+}
+
+async fn get_config_info(program: &anchor_client::Program<Rc<Keypair>>) -> anyhow::Result<()> {
+    let (config, _bump) = Pubkey::find_program_address(&[b"config"], &program.id());
+    if program.rpc().get_account(&config).is_err() {
+        println!("No config at {}: this deployment is permissionless, with no fees, limits or default arbiter", config);
+        return Ok(());
+    }
+    let config_data: solana_escrow_engine::Config = program.account(config)?;
+    
+    println!("=== Config {} ===", config);
+    println!("Authority: {}", config_data.authority);
+    if config_data.pending_authority != Pubkey::default() {
+        println!("Pending authority: {}", config_data.pending_authority);
+    }
+    println!("Emergency mode: {}", if config_data.emergency_mode { "ON (only refunds allowed)" } else { "off" });
+    if config_data.default_arbiter != Pubkey::default() {
+        println!("Default arbiter: {}", config_data.default_arbiter);
+    }
+    println!("Protocol fee: {} bps", config_data.fee_bps);
+    for buyer in &config_data.fee_exempt {
+        println!("  Fee-exempt buyer: {}", buyer);
+    }
+    println!("Rounding: {:?}", config_data.rounding_policy);
+    if config_data.min_collateral_bps > 0 {
+        println!("Minimum collateral: {} bps of the amount", config_data.min_collateral_bps);
+    }
+    if config_data.auto_confirm_after > 0 {
+        println!("Auto-confirm after: {}s", config_data.auto_confirm_after);
+    }
+    println!("Active escrows: {}", config_data.active_escrows);
+    if config_data.max_active_per_buyer > 0 {
+        println!("Max active per buyer: {}", config_data.max_active_per_buyer);
+    }
+    if config_data.relayer_allowlist.is_empty() {
+        println!("Relayers: any");
+    }
+    for relayer in &config_data.relayer_allowlist {
+        println!("  Allowed relayer: {}", relayer);
+    }
+    
+    Ok(())
+}
+
+async fn get_receipt(program: &anchor_client::Program<Rc<Keypair>>, args: ReceiptArgs, retries: u32) -> anyhow::Result<()> {
+    let escrow = Pubkey::from_str(&args.escrow)?;
+    let (receipt, _bump) = Pubkey::find_program_address(&[b"receipt", escrow.as_ref()], &program.id());
+    
+    if args.mint {
+        let request = program
+            .request()
+            .accounts(solana_escrow_engine::accounts::MintReceipt {
+                payer: program.payer(),
+                escrow,
+                receipt,
+                system_program: solana_sdk::system_program::ID,
+            })
+            .args(solana_escrow_engine::instruction::MintReceipt {});
+        let tx = send_with_retry(&request, retries)?;
+        println!("Receipt minted: {}", tx);
+    }
+    
+    let receipt_data: solana_escrow_engine::Receipt = program.account(receipt).map_err(|err| {
+        anyhow::anyhow!("Receipt not found for escrow {} ({}); mint one with --mint before closing it", escrow, err)
+    })?;
+    
+    println!("=== Receipt for {} ===", receipt_data.escrow);
+    println!("Receipt: {}", receipt);
+    println!("Final state: {:?}", receipt_data.final_state);
+    println!("Buyer: {}", receipt_data.buyer);
+    println!("Seller: {}", receipt_data.seller);
+    println!("Mint: {}", receipt_data.mint);
+    println!("Amount: {}", receipt_data.amount);
+    println!("Released: {}", receipt_data.released_amount);
+    println!("Fee: {} bps", receipt_data.fee_bps);
+    println!("Created: {}", receipt_data.created_at);
+    if receipt_data.accepted_at > 0 {
+        println!("Accepted: {}", receipt_data.accepted_at);
+    }
+    println!("Settled: {}", receipt_data.settled_at);
+    println!("Minted: {}", receipt_data.minted_at);
+    
+    Ok(())
+}
+
+async fn get_vault_balance(program: &anchor_client::Program<Rc<Keypair>>, args: VaultBalanceArgs) -> anyhow::Result<()> {
+    let escrow = Pubkey::from_str(&args.escrow)?;
+    let escrow_data = fetch_escrow(program, &escrow)?;
+    
+    let instructions = program
+        .request()
+        .accounts(solana_escrow_engine::accounts::GetVaultBalance {
+            escrow,
+            vault_token_account: escrow_data.vault,
+        })
+        .args(solana_escrow_engine::instruction::GetVaultBalance {})
+        .instructions()?;
+    let balance: solana_escrow_engine::VaultBalance = simulate_view(program, instructions)?;
+    
+    println!("Vault balance: {}", balance.vault_balance);
+    println!("Escrow amount: {}", balance.amount);
+    println!("Released: {}", balance.released_amount);
+    println!("Unreleased: {}", balance.amount - balance.released_amount);
+    
+    Ok(())
+}
+
+async fn preview_settlement(program: &anchor_client::Program<Rc<Keypair>>, args: PreviewArgs) -> anyhow::Result<()> {
+    let escrow = Pubkey::from_str(&args.escrow)?;
+    
+    let instructions = program
+        .request()
+        .accounts(solana_escrow_engine::accounts::PreviewSettlement { escrow })
+        .args(solana_escrow_engine::instruction::PreviewSettlement {})
+        .instructions()?;
+    let breakdown: solana_escrow_engine::SettlementBreakdown = simulate_view(program, instructions)?;
+    
+    println!("=== Settlement preview for {} ===", escrow);
+    println!("{:<24} {}", "Seller payout:", breakdown.seller_payout);
+    println!("{:<24} {}", "Protocol fee:", breakdown.protocol_fee);
+    println!("{:<24} {}", "Referrer fee:", breakdown.referrer_fee);
+    println!("{:<24} {}", "Buyer refund:", breakdown.buyer_refund);
+    println!("{:<24} {}", "Collateral returned:", breakdown.collateral_returned);
+    println!("{:<24} {}", "Collateral penalty:", breakdown.collateral_penalty);
+    
+    Ok(())
+}
+
+async fn estimate_cost(program: &anchor_client::Program<Rc<Keypair>>, args: EstimateCostArgs) -> anyhow::Result<()> {
+    use solana_sdk::native_token::LAMPORTS_PER_SOL;
+    use solana_sdk::program_pack::Pack;
+    
+    // The description's full reserve is allocated up front, so it only has
+    // to fit; only the milestone and SLA tier slots change the rent
+    if args.description.len() > solana_escrow_engine::Escrow::MAX_DESCRIPTION_LEN {
+        anyhow::bail!(
+            "Description is {} bytes; the maximum is {}",
+            args.description.len(),
+            solana_escrow_engine::Escrow::MAX_DESCRIPTION_LEN
+        );
+    }
+    
+    if args.milestones > solana_escrow_engine::Escrow::MAX_MILESTONES
+        || args.sla_tiers > solana_escrow_engine::Escrow::MAX_SLA_TIERS
+    {
+        anyhow::bail!(
+            "An escrow has at most {} milestones and {} SLA tiers",
+            solana_escrow_engine::Escrow::MAX_MILESTONES,
+            solana_escrow_engine::Escrow::MAX_SLA_TIERS
+        );
+    }
+    
+    let rpc = program.rpc();
+    let escrow_size = solana_escrow_engine::Escrow::space_for(&solana_escrow_engine::CreateParams {
+        milestones: vec![
+            solana_escrow_engine::MilestoneInput { label: String::new(), amount: 0 };
+            args.milestones
+        ],
+        sla_tiers: vec![solana_escrow_engine::SlaTier { window: 0, keep_bps: 0 }; args.sla_tiers],
+        ..Default::default()
+    });
+    let escrow_rent = rpc.get_minimum_balance_for_rent_exemption(escrow_size)?;
+    let vault_rent = rpc.get_minimum_balance_for_rent_exemption(spl_token::state::Account::LEN)?;
+    // `create` is signed by the buyer alone
+    let fee = rpc.get_fee_for_message(&Message::new(&[], Some(&program.payer())))?;
+    let total = escrow_rent + vault_rent + fee;
+    
+    let sol = |lamports: u64| lamports as f64 / LAMPORTS_PER_SOL as f64;
+    println!("Escrow account rent ({} bytes): {:.9} SOL", escrow_size, sol(escrow_rent));
+    println!("Vault account rent ({} bytes): {:.9} SOL", spl_token::state::Account::LEN, sol(vault_rent));
+    println!("Transaction fee: {:.9} SOL", sol(fee));
+    println!("Total: {:.9} SOL", sol(total));
+    println!("Rent is returned to the buyer when the escrow is closed");
+    
+    Ok(())
+}
+
+/// Prints whatever fields an escrow that fails its layout still yields
+fn print_partial_escrow(escrow: &Pubkey, partial: &PartialEscrow, tz: Tz) {
+    fn or_unknown<T: std::fmt::Display>(field: Option<T>) -> String {
+        field.map_or_else(|| "<undecodable>".to_string(), |value| value.to_string())
+    }
+    
+    println!("Note: escrow doesn't decode with this CLI's layout; showing the fields that do");
+    println!("=== Escrow Information ===");
+    println!("Address: {}", escrow);
+    println!("Buyer: {}", or_unknown(partial.buyer));
+    println!("Seller: {}", or_unknown(partial.seller));
+    println!("Mint: {}", or_unknown(partial.mint));
+    println!("Amount: {}", or_unknown(partial.amount));
+    println!("State: {}", or_unknown(partial.state.as_ref().map(|state| format!("{:?}", state))));
+    println!("Timeout: {}", or_unknown(partial.timeout_at.map(|ts| format_timestamp(ts, tz))));
+    println!("Description: {}", or_unknown(partial.description.as_ref()));
+}
+
+async fn get_escrow_info(program: &anchor_client::Program<Rc<Keypair>>, args: InfoArgs, tz: Tz) -> anyhow::Result<()> {
+    let escrow = Pubkey::from_str(&args.escrow)?;
+    
+    let decoded = match args.slot {
+        Some(slot) => {
+            let decoded = fetch_escrow_at_slot(program, &escrow, slot)?;
+            println!("As of slot: {}", slot);
+            decoded
+        }
+        None => fetch_escrow_versioned(program, &escrow)?,
+    };
+    let escrow_data = match decoded {
+        DecodedEscrow::Current(escrow_data) => escrow_data,
+        DecodedEscrow::V1(escrow_data) => {
+            println!("Note: v1 escrow, not yet migrated; defaults shown for the newer fields");
+            escrow_data
+        }
+        DecodedEscrow::Partial(partial) => {
+            print_partial_escrow(&escrow, &partial, tz);
+            return Ok(());
+        }
+    };
     
     println!("=== Escrow Information ===");
     println!("Address: {}", escrow);
@@ -336,124 +1816,864 @@ async fn get_escrow_info(program: &anchor_client::Program<Rc<Keypair>>, args: In
     println!("Seller: {}", escrow_data.seller);
     println!("Mint: {}", escrow_data.mint);
     println!("Amount: {}", escrow_data.amount);
+    if escrow_data.swap_mint != Pubkey::default() {
+        println!("Swap mint: {}", escrow_data.swap_mint);
+        println!("Swap amount: {}", escrow_data.swap_amount);
+        println!("Swap min receive: {}", escrow_data.swap_min_receive);
+        println!("Swap valid until: {}", format_timestamp(escrow_data.swap_valid_until, tz));
+    }
     println!("State: {:?}", escrow_data.state);
+    if let Some(reason) = escrow_data.decline_reason {
+        println!("Declined by a previous seller: {:?}", reason);
+    }
     println!("Description: {}", escrow_data.description);
-    println!("Created at: {}", escrow_data.created_at);
-    println!("Timeout at: {}", escrow_data.timeout_at);
+    if escrow_data.parent != Pubkey::default() {
+        println!("Split from: {}", escrow_data.parent);
+    }
+    if escrow_data.arbiter != Pubkey::default() {
+        println!("Arbiter: {}", escrow_data.arbiter);
+    }
+    if escrow_data.evidence_requested {
+        println!("Evidence: requested by the arbiter");
+    }
+    if !escrow_data.evidence_uri.is_empty() {
+        println!("Evidence URI: {}", escrow_data.evidence_uri);
+    }
+    if escrow_data.rent_payer != Pubkey::default() {
+        println!("Rent sponsored by: {}", escrow_data.rent_payer);
+    }
+    if escrow_data.fee_bps > 0 {
+        println!("Protocol fee: {} bps", escrow_data.fee_bps);
+    }
+    if escrow_data.referrer != Pubkey::default() {
+        println!("Referrer: {} ({} bps of the fee)", escrow_data.referrer, escrow_data.referrer_bps);
+    }
+    println!("Rounding: {:?}", escrow_data.rounding);
+    if escrow_data.required_collateral > 0 {
+        println!("Seller collateral: {}", escrow_data.required_collateral);
+    }
+    println!("Timeout at: {}", format_timestamp(escrow_data.timeout_at, tz));
+    println!("Timeout cap: {}", format_timestamp(escrow_data.max_timeout_at, tz));
+    println!("Timeout beneficiary: {:?}", escrow_data.timeout_beneficiary);
+    if escrow_data.delivery_deadline > 0 {
+        println!("Delivery deadline: {}", format_timestamp(escrow_data.delivery_deadline, tz));
+        if escrow_data.delivered_at > 0 {
+            println!("Marked delivered at: {}", format_timestamp(escrow_data.delivered_at, tz));
+        }
+    }
+    if !escrow_data.permissionless_timeout {
+        println!("Timeout resolvable by: buyer or seller only");
+    }
+    if escrow_data.seller_payout_account != Pubkey::default() {
+        println!("Seller payout account: {}", escrow_data.seller_payout_account);
+    }
+    if escrow_data.start_at > escrow_data.created_at() {
+        println!("Starts at: {}", format_timestamp(escrow_data.start_at, tz));
+    }
+    if escrow_data.accept_lock > 0 {
+        println!(
+            "Accept lock ends: {}",
+            format_timestamp(escrow_data.created_at() + escrow_data.accept_lock, tz)
+        );
+    }
+    if escrow_data.dispute_window > 0 {
+        println!("Dispute window: {}s after delivery", escrow_data.dispute_window);
+        if let Some(deemed_accepted_at) = escrow_data.deemed_accepted_at() {
+            println!("Deemed accepted at: {}", format_timestamp(deemed_accepted_at, tz));
+        }
+    }
+    for tier in &escrow_data.sla_tiers {
+        println!(
+            "SLA tier: {} bps of collateral back if delivered within {}s of acceptance",
+            tier.keep_bps, tier.window
+        );
+    }
+    if escrow_data.seller_is_multisig {
+        println!("Seller treasury (multisig seller): {}", escrow_data.seller_treasury);
+    }
+    if escrow_data.route_to_program != Pubkey::default() {
+        println!("Payout route program: {}", escrow_data.route_to_program);
+        if escrow_data.seller != Pubkey::default() {
+            let (route, _) = Pubkey::find_program_address(
+                &[solana_escrow_engine::PAYOUT_ROUTE_SEED, escrow_data.seller.as_ref()],
+                &escrow_data.route_to_program,
+            );
+            println!("Seller payout PDA: {}", route);
+        }
+    }
+    if escrow_data.priority > 0 {
+        println!("Priority: {}", escrow_data.priority);
+    }
+    if escrow_data.heartbeat_interval > 0 {
+        println!("Heartbeat interval: {}s", escrow_data.heartbeat_interval);
+        if let Ok(Some(due_at)) = escrow_data.heartbeat_due_at() {
+            println!("Next heartbeat due: {}", format_timestamp(due_at, tz));
+        }
+    }
+    if escrow_data.withdraw_delay > 0 {
+        println!("Withdraw delay: {}s", escrow_data.withdraw_delay);
+        if let Ok(Some(executable_at)) = escrow_data.withdrawal_executable_at() {
+            println!("Withdrawal executable at: {}", format_timestamp(executable_at, tz));
+        }
+    }
+    if escrow_data.auto_close {
+        println!("Closes automatically when settled");
+    }
+    if escrow_data.bid_reveal_at > 0 {
+        println!("Sealed bidding, bids reveal from: {}", format_timestamp(escrow_data.bid_reveal_at, tz));
+    }
+    if escrow_data.unfunded_amount > 0 {
+        println!("Awaiting deposits: {} of {}", escrow_data.unfunded_amount, escrow_data.amount);
+    }
+    if escrow_data.buyer_inactivity_timeout > 0 {
+        println!("Buyer inactivity timeout: {}s after delivery", escrow_data.buyer_inactivity_timeout);
+        if let Ok(Some(inactive_at)) = escrow_data.buyer_inactive_at() {
+            println!("Seller may claim from: {}", format_timestamp(inactive_at, tz));
+        }
+    }
+    if escrow_data.fulfilled_amount > 0 {
+        println!("Fulfilled: {} of {}", escrow_data.fulfilled_amount, escrow_data.amount);
+    }
+    if escrow_data.vesting_duration > 0 {
+        println!("Vesting duration: {}s", escrow_data.vesting_duration);
+        if escrow_data.vesting_start > 0 {
+            println!(
+                "Vesting since: {} ({} claimed)",
+                format_timestamp(escrow_data.vesting_start, tz),
+                escrow_data.claimed_amount
+            );
+        }
+    }
+    
+    if !escrow_data.milestones.is_empty() {
+        println!("Released: {} / {}", escrow_data.released_amount, escrow_data.amount);
+        println!("Milestones:");
+        for (i, milestone) in escrow_data.milestones.iter().enumerate() {
+            let status = if milestone.released { "released" } else { "pending" };
+            println!("  [{}] {} - {} ({})", i, milestone.label, milestone.amount, status);
+        }
+    }
     
-    if escrow_data.accepted_at > 0 {
-        println!("Accepted at: {}", escrow_data.accepted_at);
+    println!("Timeline:");
+    for entry in &escrow_data.timeline {
+        println!("  {:?} at {}", entry.event, format_timestamp(entry.timestamp, tz));
     }
     
-    if escrow_data.completed_at > 0 {
-        println!("Completed at: {}", escrow_data.completed_at);
+    Ok(())
+}
+
+/// Largest batch `getMultipleAccounts` accepts
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+async fn get_many_escrow_info(program: &anchor_client::Program<Rc<Keypair>>, args: InfoManyArgs) -> anyhow::Result<()> {
+    let mut addresses = args.escrows;
+    if let Some(path) = &args.file {
+        let contents = std::fs::read_to_string(path)?;
+        addresses.extend(
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(String::from),
+        );
     }
+    anyhow::ensure!(!addresses.is_empty(), "pass --escrows or --file");
     
-    if escrow_data.cancelled_at > 0 {
-        println!("Cancelled at: {}", escrow_data.cancelled_at);
+    let escrows = addresses
+        .iter()
+        .map(|address| Pubkey::from_str(address))
+        .collect::<Result<Vec<_>, _>>()?;
+    
+    println!(
+        "{:<44}  {:<10}  {:>20}  {:<44}  {:<44}",
+        "ESCROW", "STATE", "AMOUNT", "BUYER", "SELLER"
+    );
+    for chunk in escrows.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let accounts = program.rpc().get_multiple_accounts(chunk)?;
+        for (escrow, account) in chunk.iter().zip(accounts) {
+            let account = match account {
+                Some(account) => account,
+                None => {
+                    println!("{:<44}  not found", escrow);
+                    continue;
+                }
+            };
+            match decode_escrow_versioned(&program.id(), escrow, &account.owner, &account.data) {
+                Some(DecodedEscrow::Current(escrow_data) | DecodedEscrow::V1(escrow_data)) => println!(
+                    "{:<44}  {:<10}  {:>20}  {:<44}  {:<44}",
+                    escrow,
+                    format!("{:?}", escrow_data.state),
+                    escrow_data.amount,
+                    escrow_data.buyer,
+                    escrow_data.seller
+                ),
+                Some(DecodedEscrow::Partial(partial)) => println!(
+                    "{:<44}  {:<10}  {:>20}  {:<44}  {:<44}",
+                    escrow,
+                    partial.state.as_ref().map_or_else(|| "?".to_string(), |state| format!("{:?}", state)),
+                    partial.amount.map_or_else(|| "?".to_string(), |amount| amount.to_string()),
+                    partial.buyer.map_or_else(|| "?".to_string(), |buyer| buyer.to_string()),
+                    partial.seller.map_or_else(|| "?".to_string(), |seller| seller.to_string())
+                ),
+                None => println!("{:<44}  not an escrow", escrow),
+            }
+        }
     }
     
     Ok(())
 }
 
-// Placeholder module structure - this will be replaced by generated IDL
-mod solana_escrow_engine {
-    use anchor_lang::prelude::*;
-    
-    #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
-    pub struct Escrow {
-        pub buyer: Pubkey,
-        pub seller: Pubkey,
-        pub mint: Pubkey,
-        pub amount: u64,
-        pub state: EscrowState,
-        pub created_at: i64,
-        pub accepted_at: i64,
-        pub completed_at: i64,
-        pub cancelled_at: i64,
-        pub timeout_at: i64,
-        pub description: String,
-        pub bump: u8,
-    }
-    
-    #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
-    pub enum EscrowState {
-        Created,
-        Accepted,
-        Completed,
-        Cancelled,
-        TimedOut,
-    }
-    
-    pub mod accounts {
-        use super::*;
-        
-        #[derive(Accounts)]
-        pub struct CreateEscrow {
-            pub buyer: Pubkey,
-            pub escrow: Pubkey,
-            pub mint: Pubkey,
-            pub buyer_token_account: Pubkey,
-            pub vault_token_account: Pubkey,
-            pub token_program: Pubkey,
-            pub system_program: Pubkey,
-            pub rent: Pubkey,
+/// Escrows where `wallet` holds `role`. The RPC filters on the account
+/// discriminator and the buyer or seller field; unmigrated v1 escrows are
+/// included, and accounts that only partially decode are skipped.
+fn wallet_escrows(
+    program: &anchor_client::Program<Rc<Keypair>>,
+    role: Role,
+    wallet: &Pubkey,
+) -> anyhow::Result<Vec<(Pubkey, solana_escrow_engine::Escrow)>> {
+    // `buyer` then `seller` are the first fields after the discriminator
+    let offset = match role {
+        Role::Buyer => 8,
+        Role::Seller => 8 + 32,
+    };
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![
+            RpcFilterType::Memcmp(Memcmp::new_base58_encoded(0, solana_escrow_engine::Escrow::DISCRIMINATOR)),
+            RpcFilterType::Memcmp(Memcmp::new_base58_encoded(offset, wallet.as_ref())),
+        ]),
+        ..RpcProgramAccountsConfig::default()
+    };
+    let accounts = program.rpc().get_program_accounts_with_config(&program.id(), config)?;
+    
+    Ok(accounts
+        .into_iter()
+        .filter_map(|(address, account)| {
+            let escrow = decode_escrow(&program.id(), &address, &account.owner, &account.data)?;
+            Some((address, escrow))
+        })
+        .collect())
+}
+
+/// Case-insensitive substring match on an escrow description
+fn description_matches(description: &str, text: &str) -> bool {
+    description.to_lowercase().contains(&text.to_lowercase())
+}
+
+async fn find_escrows(program: &anchor_client::Program<Rc<Keypair>>, args: FindArgs) -> anyhow::Result<()> {
+    let buyer = match &args.buyer {
+        Some(buyer) => Pubkey::from_str(buyer)?,
+        None => program.payer(),
+    };
+    
+    // Descriptions are variable-length, so they can't be memcmp-filtered on the RPC side
+    let mut matches: Vec<_> = wallet_escrows(program, Role::Buyer, &buyer)?
+        .into_iter()
+        .filter(|(_, escrow)| description_matches(&escrow.description, &args.text))
+        .collect();
+    matches.sort_by_key(|(address, _)| *address);
+    
+    if matches.is_empty() {
+        println!("No escrows of {} mention \"{}\"", buyer, args.text);
+        return Ok(());
+    }
+    println!("{:<44}  {:<10}  {:>20}  DESCRIPTION", "ESCROW", "STATE", "AMOUNT");
+    for (address, escrow) in &matches {
+        println!(
+            "{:<44}  {:<10}  {:>20}  {}",
+            address,
+            format!("{:?}", escrow.state),
+            escrow.amount,
+            escrow.description
+        );
+    }
+    
+    Ok(())
+}
+
+/// `amount` in whole tokens, e.g. 1500000 with 6 decimals is "1.500000"
+fn format_amount(amount: u64, decimals: u8) -> String {
+    if decimals == 0 {
+        return amount.to_string();
+    }
+    let scale = 10u128.pow(decimals as u32);
+    let amount = amount as u128;
+    format!("{}.{:0width$}", amount / scale, amount % scale, width = decimals as usize)
+}
+
+/// `timestamp` as a datetime in `tz`, e.g. "2024-03-01 14:30:00 CET"; raw
+/// epoch seconds if it is out of chrono's range
+fn format_timestamp(timestamp: i64, tz: Tz) -> String {
+    match tz.timestamp_opt(timestamp, 0).single() {
+        Some(datetime) => datetime.format("%Y-%m-%d %H:%M:%S %Z").to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+/// `timestamp` as an RFC 3339 datetime in `tz`, which spreadsheets and
+/// scripts parse back without knowing the zone
+fn format_rfc3339(timestamp: i64, tz: Tz) -> String {
+    match tz.timestamp_opt(timestamp, 0).single() {
+        Some(datetime) => datetime.to_rfc3339(),
+        None => timestamp.to_string(),
+    }
+}
+
+/// Time of the first `event` in the escrow's timeline, blank if it never happened
+fn event_time(escrow: &solana_escrow_engine::Escrow, event: solana_escrow_engine::TimelineEvent, tz: Tz) -> String {
+    escrow
+        .timeline
+        .iter()
+        .find(|entry| entry.event == event)
+        .map(|entry| format_rfc3339(entry.timestamp, tz))
+        .unwrap_or_default()
+}
+
+const EXPORT_HEADER: &str = "address,counterparty,mint,amount,state,created_at,accepted_at,disputed_at,completed_at,cancelled_at,timed_out_at,resolved_at,timeout_at";
+
+async fn export_escrows(program: &anchor_client::Program<Rc<Keypair>>, args: ExportArgs, tz: Tz) -> anyhow::Result<()> {
+    use solana_escrow_engine::TimelineEvent;
+    
+    let wallet = match &args.wallet {
+        Some(wallet) => Pubkey::from_str(wallet)?,
+        None => program.payer(),
+    };
+    let mut escrows = wallet_escrows(program, args.role, &wallet)?;
+    escrows.sort_by_key(|(_, escrow)| escrow.timeline.first().map_or(0, |entry| entry.timestamp));
+    
+    // One lookup per distinct mint for its decimals
+    let mut mints: Vec<Pubkey> = escrows.iter().map(|(_, escrow)| escrow.mint).collect();
+    mints.sort();
+    mints.dedup();
+    let mut decimals = std::collections::HashMap::new();
+    for chunk in mints.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        for (mint, account) in chunk.iter().zip(program.rpc().get_multiple_accounts(chunk)?) {
+            let account = account.ok_or_else(|| anyhow::anyhow!("Account not found: mint {}", mint))?;
+            let mint_data = <spl_token::state::Mint as spl_token::solana_program::program_pack::Pack>::unpack(&account.data)?;
+            decimals.insert(*mint, mint_data.decimals);
         }
-        
-        #[derive(Accounts)]
-        pub struct AcceptEscrow {
-            pub seller: Pubkey,
-            pub escrow: Pubkey,
+    }
+    
+    let mut csv = String::from(EXPORT_HEADER);
+    csv.push('\n');
+    for (address, escrow) in &escrows {
+        let counterparty = match args.role {
+            Role::Buyer => escrow.seller,
+            Role::Seller => escrow.buyer,
+        };
+        let counterparty = if counterparty == Pubkey::default() {
+            String::new()
+        } else {
+            counterparty.to_string()
+        };
+        let row = [
+            address.to_string(),
+            counterparty,
+            escrow.mint.to_string(),
+            format_amount(escrow.amount, decimals[&escrow.mint]),
+            format!("{:?}", escrow.state),
+            event_time(escrow, TimelineEvent::Created, tz),
+            event_time(escrow, TimelineEvent::Accepted, tz),
+            event_time(escrow, TimelineEvent::Disputed, tz),
+            event_time(escrow, TimelineEvent::Completed, tz),
+            event_time(escrow, TimelineEvent::Cancelled, tz),
+            event_time(escrow, TimelineEvent::TimedOut, tz),
+            event_time(escrow, TimelineEvent::Resolved, tz),
+            format_rfc3339(escrow.timeout_at, tz),
+        ];
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    std::fs::write(&args.out, csv)?;
+    
+    println!("Wrote {} escrows to {}", escrows.len(), args.out);
+    Ok(())
+}
+
+/// What an escrow's vault should hold: the unreleased amount plus any
+/// collateral while live, and nothing once settled. Accounting that doesn't
+/// add up expects nothing, so any balance in that vault gets flagged.
+fn expected_vault_balance(escrow: &solana_escrow_engine::Escrow) -> u64 {
+    if escrow.is_terminal() {
+        0
+    } else {
+        escrow.held_amount().unwrap_or_default()
+    }
+}
+
+/// Vault balance minus the expected one, or `None` when they agree. A
+/// missing or undecodable vault counts as holding nothing.
+fn vault_drift(escrow: &solana_escrow_engine::Escrow, vault_balance: Option<u64>) -> Option<i128> {
+    let delta = vault_balance.unwrap_or(0) as i128 - expected_vault_balance(escrow) as i128;
+    (delta != 0 || (vault_balance.is_none() && !escrow.is_terminal())).then_some(delta)
+}
+
+async fn audit_vaults(program: &anchor_client::Program<Rc<Keypair>>) -> anyhow::Result<()> {
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+            0,
+            solana_escrow_engine::Escrow::DISCRIMINATOR,
+        ))]),
+        ..RpcProgramAccountsConfig::default()
+    };
+    let mut escrows: Vec<(Pubkey, solana_escrow_engine::Escrow)> = program
+        .rpc()
+        .get_program_accounts_with_config(&program.id(), config)?
+        .into_iter()
+        .filter_map(|(address, account)| Some((address, decode_escrow(&program.id(), &address, &account.owner, &account.data)?)))
+        .collect();
+    escrows.sort_by_key(|(address, _)| *address);
+    
+    println!(
+        "{:<44}  {:<10}  {:>20}  {:>20}  {:>21}",
+        "ESCROW", "STATE", "EXPECTED", "ACTUAL", "DELTA"
+    );
+    let mut drifted = 0;
+    for chunk in escrows.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let vaults: Vec<Pubkey> = chunk.iter().map(|(_, escrow)| escrow.vault).collect();
+        for ((address, escrow), vault) in chunk.iter().zip(program.rpc().get_multiple_accounts(&vaults)?) {
+            let vault_balance = vault
+                .and_then(|vault| {
+                    <spl_token::state::Account as spl_token::solana_program::program_pack::Pack>::unpack(&vault.data).ok()
+                })
+                .map(|vault| vault.amount);
+            let Some(delta) = vault_drift(escrow, vault_balance) else {
+                continue;
+            };
+            drifted += 1;
+            println!(
+                "{:<44}  {:<10}  {:>20}  {:>20}  {:>+21}",
+                address,
+                format!("{:?}", escrow.state),
+                expected_vault_balance(escrow),
+                vault_balance.map_or_else(|| "missing".to_string(), |balance| balance.to_string()),
+                delta
+            );
         }
+    }
+    println!("{} of {} escrows drifted", drifted, escrows.len());
+    
+    Ok(())
+}
+
+/// Orders a keeper's work: highest priority first, then the longest-overdue
+/// deadline, then the largest amount
+fn sort_by_urgency(escrows: &mut [(Pubkey, solana_escrow_engine::Escrow)]) {
+    escrows.sort_by(|(a_address, a), (b_address, b)| {
+        b.priority
+            .cmp(&a.priority)
+            .then(a.release_deadline().cmp(&b.release_deadline()))
+            .then(b.amount.cmp(&a.amount))
+            .then(a_address.cmp(b_address))
+    });
+}
+
+async fn scan_timeouts(program: &anchor_client::Program<Rc<Keypair>>, args: ScanArgs, tz: Tz) -> anyhow::Result<()> {
+    // `priority` follows the variable-length fields, so it has no fixed
+    // offset to filter on server-side; decode and filter here instead
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+            0,
+            solana_escrow_engine::Escrow::DISCRIMINATOR,
+        ))]),
+        ..RpcProgramAccountsConfig::default()
+    };
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs() as i64;
+    let mut escrows: Vec<(Pubkey, solana_escrow_engine::Escrow)> = program
+        .rpc()
+        .get_program_accounts_with_config(&program.id(), config)?
+        .into_iter()
+        .filter_map(|(address, account)| Some((address, decode_escrow(&program.id(), &address, &account.owner, &account.data)?)))
+        .filter(|(_, escrow)| {
+            escrow.state == solana_escrow_engine::EscrowState::Accepted
+                && !escrow.is_swap()
+                && now >= escrow.release_deadline()
+                && escrow.priority >= args.min_priority
+        })
+        .collect();
+    sort_by_urgency(&mut escrows);
+    
+    println!(
+        "{:<44}  {:>8}  {:>20}  {:<27}  {:<6}",
+        "ESCROW", "PRIORITY", "AMOUNT", "DEADLINE", "PAYEE"
+    );
+    for (address, escrow) in &escrows {
+        println!(
+            "{:<44}  {:>8}  {:>20}  {:<27}  {:<6}",
+            address,
+            escrow.priority,
+            escrow.amount,
+            format_timestamp(escrow.release_deadline(), tz),
+            format!("{:?}", escrow.timeout_payee(now))
+        );
+    }
+    println!("{} escrows ready to resolve", escrows.len());
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retries_transient_rpc_errors() {
+        assert!(is_retryable("RPC response error -32002: Transaction simulation failed: Blockhash not found"));
+        assert!(is_retryable("Transaction error: BlockhashNotFound"));
+        assert!(is_retryable("HTTP status client error (429 Too Many Requests) for url (https://api.devnet.solana.com/)"));
+        assert!(is_retryable("error sending request for url (https://api.devnet.solana.com/): operation timed out"));
+    }
+
+    #[test]
+    fn does_not_retry_program_errors() {
+        assert!(!is_retryable(
+            "RPC response error -32002: Transaction simulation failed: Error processing Instruction 0: custom program error: 0x1770"
+        ));
+        assert!(!is_retryable("AnchorError occurred. Error Code: InvalidState. Error Number: 6000."));
+        assert!(!is_retryable("Account not found"));
+    }
+
+    #[test]
+    fn classifies_errors_by_exit_code() {
+        let code = |message: &str| CliError::from(anyhow::anyhow!(message.to_string())).exit_code();
+        assert_eq!(code("Account not found"), 2);
+        assert_eq!(code("Escrow not found: no account exists at 11111111111111111111111111111111"), 2);
+        assert_eq!(
+            code("RPC response error -32002: Transaction simulation failed: Error processing Instruction 0: custom program error: 0x1. Program log: Error: insufficient funds"),
+            3
+        );
+        assert_eq!(code("Transaction simulation failed: Attempt to debit an account but found no record of a prior credit."), 3);
+        assert_eq!(code("AnchorError occurred. Error Code: InvalidState. Error Number: 6000."), 4);
+        assert_eq!(code("error sending request for url (http://localhost:8899/): connection refused"), 5);
+        assert_eq!(code("Invalid Base58 string"), 1);
+    }
+
+    #[test]
+    fn not_found_takes_priority_over_the_program_error_wrapping_it() {
+        let err = anyhow::anyhow!("AnchorError caused by account: escrow. Error Code: AccountNotInitialized. Error Number: 3012.");
+        assert!(matches!(CliError::from(err), CliError::NotFound(_)));
+    }
+
+    #[test]
+    fn sorts_keeper_work_by_priority_then_deadline() {
+        let escrow = |priority: u8, timeout_at: i64, amount: u64| {
+            let mut escrow =
+                solana_escrow_engine::Escrow::deserialize(&mut &[0u8; solana_escrow_engine::Escrow::LEN][..]).unwrap();
+            escrow.priority = priority;
+            escrow.timeout_at = timeout_at;
+            escrow.amount = amount;
+            (Pubkey::new_unique(), escrow)
+        };
+        let mut escrows = vec![
+            escrow(0, 100, 5),
+            escrow(9, 300, 1),
+            escrow(9, 200, 1),
+            escrow(0, 100, 50),
+        ];
+        sort_by_urgency(&mut escrows);
         
-        #[derive(Accounts)]
-        pub struct ConfirmDelivery {
-            pub buyer: Pubkey,
-            pub escrow: Pubkey,
-            pub vault_token_account: Pubkey,
-            pub seller_token_account: Pubkey,
-            pub token_program: Pubkey,
-        }
+        let order: Vec<(u8, i64, u64)> = escrows
+            .iter()
+            .map(|(_, escrow)| (escrow.priority, escrow.timeout_at, escrow.amount))
+            .collect();
+        assert_eq!(order, vec![(9, 200, 1), (9, 300, 1), (0, 100, 50), (0, 100, 5)]);
+    }
+
+    #[test]
+    fn parses_sla_tiers() {
+        let tier = parse_sla_tier("3600:5000").unwrap();
+        assert_eq!(tier, solana_escrow_engine::SlaTier { window: 3600, keep_bps: 5000 });
+        assert!(parse_sla_tier("3600").is_err());
+        assert!(parse_sla_tier("3600:70000").is_err());
+    }
+
+    #[test]
+    fn decodes_only_program_owned_escrow_accounts() {
+        let program_id = solana_escrow_engine::ID;
+        let mut data = solana_escrow_engine::Escrow::DISCRIMINATOR.to_vec();
+        data.extend(vec![0u8; solana_escrow_engine::Escrow::LEN]);
         
-        #[derive(Accounts)]
-        pub struct CancelEscrow {
-            pub buyer: Pubkey,
-            pub escrow: Pubkey,
-            pub vault_token_account: Pubkey,
-            pub buyer_token_account: Pubkey,
-            pub token_program: Pubkey,
-        }
+        let address = Pubkey::new_unique();
         
-        #[derive(Accounts)]
-        pub struct ResolveTimeout {
-            pub resolver: Pubkey,
-            pub escrow: Pubkey,
-            pub vault_token_account: Pubkey,
-            pub seller_token_account: Pubkey,
-            pub token_program: Pubkey,
-        }
+        let escrow = decode_escrow(&program_id, &address, &program_id, &data).unwrap();
+        assert_eq!(escrow.state, solana_escrow_engine::EscrowState::Created);
+        assert!(decode_escrow(&program_id, &address, &spl_token::ID, &data).is_none());
+        
+        // A config account owned by the program is still not an escrow
+        let mut config = solana_escrow_engine::Config::DISCRIMINATOR.to_vec();
+        config.extend(vec![0u8; solana_escrow_engine::Config::LEN]);
+        assert!(decode_escrow(&program_id, &address, &program_id, &config).is_none());
+        assert!(decode_escrow(&program_id, &address, &program_id, &[]).is_none());
     }
-    
-    pub mod instruction {
-        use super::*;
+
+    /// An escrow blob of `len` bytes after the discriminator, with only v1
+    /// fields set so the newer fields serialize as zeros
+    fn escrow_blob(buyer: Pubkey, len: usize) -> Vec<u8> {
+        use anchor_lang::AnchorSerialize;
         
-        #[derive(AnchorSerialize, AnchorDeserialize)]
-        pub struct CreateEscrow {
-            pub amount: u64,
-            pub timeout_duration: i64,
-            pub description: String,
-        }
+        let mut escrow =
+            solana_escrow_engine::Escrow::deserialize(&mut &[0u8; solana_escrow_engine::Escrow::LEN][..]).unwrap();
+        escrow.buyer = buyer;
+        escrow.amount = 1_000;
+        escrow.state = solana_escrow_engine::EscrowState::Accepted;
+        escrow.description = "Laptop".to_string();
+        
+        let mut data = solana_escrow_engine::Escrow::DISCRIMINATOR.to_vec();
+        escrow.serialize(&mut data).unwrap();
+        data.resize(8 + len, 0);
+        data
+    }
+
+    #[test]
+    fn decodes_v1_and_v2_escrow_layouts() {
+        use solana_escrow_engine::Escrow;
+        let program_id = solana_escrow_engine::ID;
+        let address = Pubkey::new_unique();
+        let buyer = Pubkey::new_unique();
         
-        #[derive(AnchorSerialize, AnchorDeserialize)]
-        pub struct AcceptEscrow {}
+        let v2 = escrow_blob(buyer, Escrow::LEN);
+        let Some(DecodedEscrow::Current(escrow)) = decode_escrow_versioned(&program_id, &address, &program_id, &v2) else {
+            panic!("v2 blob should decode with the current layout");
+        };
+        assert_eq!(escrow.buyer, buyer);
+        assert_eq!(escrow.amount, 1_000);
+        assert_eq!(escrow.description, "Laptop");
         
-        #[derive(AnchorSerialize, AnchorDeserialize)]
-        pub struct ConfirmDelivery {}
+        // v1 gets the values `migrate_v2` would write
+        let v1 = escrow_blob(buyer, Escrow::LEN_V1);
+        let Some(DecodedEscrow::V1(escrow)) = decode_escrow_versioned(&program_id, &address, &program_id, &v1) else {
+            panic!("v1 blob should decode as v1");
+        };
+        assert_eq!(escrow.buyer, buyer);
+        assert_eq!(escrow.state, solana_escrow_engine::EscrowState::Accepted);
+        assert_eq!(escrow.description, "Laptop");
+        assert_eq!(escrow.timeout_beneficiary, solana_escrow_engine::TimeoutBeneficiary::Seller);
+        assert_eq!(escrow.original_depositor, buyer);
+        assert!(escrow.permissionless_timeout);
+        assert_eq!(
+            escrow.vault,
+            Pubkey::find_program_address(&[b"vault", address.as_ref()], &program_id).0
+        );
+        assert!(decode_escrow(&program_id, &address, &program_id, &v1).is_some());
+    }
+
+    #[test]
+    fn falls_back_to_the_fields_that_decode() {
+        let program_id = solana_escrow_engine::ID;
+        let address = Pubkey::new_unique();
+        let buyer = Pubkey::new_unique();
         
-        #[derive(AnchorSerialize, AnchorDeserialize)]
-        pub struct CancelEscrow {}
+        // An unknown state variant stops decoding right after `amount`
+        let mut data = escrow_blob(buyer, solana_escrow_engine::Escrow::LEN);
+        data[8 + 32 + 32 + 32 + 8] = u8::MAX;
+        let Some(DecodedEscrow::Partial(partial)) = decode_escrow_versioned(&program_id, &address, &program_id, &data) else {
+            panic!("corrupt blob should only partially decode");
+        };
+        assert_eq!(partial.buyer, Some(buyer));
+        assert_eq!(partial.amount, Some(1_000));
+        assert!(partial.state.is_none());
+        assert!(partial.description.is_none());
+        assert!(decode_escrow(&program_id, &address, &program_id, &data).is_none());
         
-        #[derive(AnchorSerialize, AnchorDeserialize)]
-        pub struct ResolveTimeout {}
+        // A truncated account keeps only the whole fields
+        let Some(DecodedEscrow::Partial(partial)) = decode_escrow_versioned(&program_id, &address, &program_id, &data[..8 + 40]) else {
+            panic!("truncated blob should only partially decode");
+        };
+        assert_eq!(partial.buyer, Some(buyer));
+        assert!(partial.seller.is_none());
+    }
+
+    #[test]
+    fn flags_vaults_that_drift_from_the_escrow_accounting() {
+        let mut escrow =
+            solana_escrow_engine::Escrow::deserialize(&mut &[0u8; solana_escrow_engine::Escrow::LEN][..]).unwrap();
+        escrow.amount = 1_000;
+        escrow.released_amount = 400;
+        escrow.state = solana_escrow_engine::EscrowState::Accepted;
+        escrow.required_collateral = 50;
+
+        assert_eq!(vault_drift(&escrow, Some(650)), None);
+        // Dust sent straight to the vault
+        assert_eq!(vault_drift(&escrow, Some(651)), Some(1));
+        assert_eq!(vault_drift(&escrow, Some(600)), Some(-50));
+        assert_eq!(vault_drift(&escrow, None), Some(-650));
+
+        // Settled escrows should have emptied their vault
+        escrow.state = solana_escrow_engine::EscrowState::Cancelled;
+        assert_eq!(vault_drift(&escrow, Some(0)), None);
+        assert_eq!(vault_drift(&escrow, None), None);
+        assert_eq!(vault_drift(&escrow, Some(7)), Some(7));
+    }
+
+    #[test]
+    fn matches_descriptions_case_insensitively() {
+        assert!(description_matches("MacBook Pro 16-inch LAPTOP delivery", "laptop"));
+        assert!(description_matches("Laptop", "LAPTOP"));
+        assert!(!description_matches("Desktop tower", "laptop"));
+        assert!(description_matches("", ""));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn formats_amounts_with_the_mint_decimals() {
+        assert_eq!(format_amount(1_500_000, 6), "1.500000");
+        assert_eq!(format_amount(42, 6), "0.000042");
+        assert_eq!(format_amount(u64::MAX, 9), "18446744073.709551615");
+        assert_eq!(format_amount(7, 0), "7");
+    }
+
+    #[test]
+    fn formats_timestamps_in_the_requested_timezone() {
+        let berlin = parse_timezone("Europe/Berlin").unwrap();
+        assert_eq!(format_timestamp(1_700_000_000, Tz::UTC), "2023-11-14 22:13:20 UTC");
+        assert_eq!(format_timestamp(1_700_000_000, berlin), "2023-11-14 23:13:20 CET");
+        assert_eq!(format_rfc3339(1_700_000_000, berlin), "2023-11-14T23:13:20+01:00");
+        assert_eq!(format_timestamp(i64::MAX, Tz::UTC), i64::MAX.to_string());
+        assert!(parse_timezone("Mars/Olympus_Mons").is_err());
+    }
+
+    #[test]
+    fn account_metas_follow_the_program_declaration_order() {
+        use anchor_lang::ToAccountMetas;
+
+        let buyer = Pubkey::new_unique();
+        let escrow = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let buyer_token_account = Pubkey::new_unique();
+        let vault_token_account = Pubkey::new_unique();
+        let metas = solana_escrow_engine::accounts::CreateEscrow {
+            buyer,
+            escrow,
+            mint,
+            buyer_token_account,
+            vault_token_account,
+            config: None,
+            seller_authorization: None,
+            buyer_account: None,
+            token_program: spl_token::ID,
+            system_program: solana_sdk::system_program::ID,
+            rent: solana_sdk::sysvar::rent::ID,
+        }
+        .to_account_metas(None);
+
+        let keys: Vec<Pubkey> = metas.iter().map(|meta| meta.pubkey).collect();
+        // Omitted optional accounts are passed as the program ID
+        assert_eq!(
+            keys,
+            vec![
+                buyer,
+                escrow,
+                mint,
+                buyer_token_account,
+                vault_token_account,
+                solana_escrow_engine::ID,
+                solana_escrow_engine::ID,
+                solana_escrow_engine::ID,
+                spl_token::ID,
+                solana_sdk::system_program::ID,
+                solana_sdk::sysvar::rent::ID,
+            ]
+        );
+        assert!(metas[0].is_signer && metas[0].is_writable);
+    }
+
+    #[test]
+    fn historical_reads_need_an_unchanged_account() {
+        assert!(check_unchanged_since(100, Some(90), 200).is_ok());
+        assert!(check_unchanged_since(100, Some(100), 200).is_ok());
+
+        let err = check_unchanged_since(100, Some(150), 200).unwrap_err().to_string();
+        assert!(err.contains("too old") && err.contains("slot 150"));
+        assert!(check_unchanged_since(100, None, 200).is_err());
+    }
+
+    #[test]
+    fn memo_runs_before_the_escrow_instruction() {
+        let payer = Keypair::new();
+        let buyer = payer.pubkey();
+        let client = Client::new_with_options(Cluster::Localnet, Rc::new(payer), CommitmentConfig::confirmed());
+        let program = client.program(solana_escrow_engine::ID).unwrap();
+        let escrow = Pubkey::new_unique();
+
+        let request = with_memo(program.request(), Some("order #1234"), &buyer)
+            .accounts(solana_escrow_engine::accounts::CancelEscrow {
+                buyer,
+                escrow,
+                vault_token_account: Pubkey::new_unique(),
+                buyer_token_account: Pubkey::new_unique(),
+                config: None,
+                buyer_account: None,
+                rent_recipient: None,
+                token_program: spl_token::ID,
+            })
+            .args(solana_escrow_engine::instruction::CancelEscrow {});
+        let instructions = request.instructions().unwrap();
+
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(instructions[0].program_id, MEMO_PROGRAM_ID);
+        assert_eq!(instructions[0].data, b"order #1234");
+        assert_eq!(instructions[0].accounts, vec![AccountMeta::new_readonly(buyer, true)]);
+        assert_eq!(instructions[1].program_id, program.id());
+    }
+
+    #[test]
+    fn no_memo_leaves_the_request_unchanged() {
+        let client = Client::new_with_options(Cluster::Localnet, Rc::new(Keypair::new()), CommitmentConfig::confirmed());
+        let program = client.program(solana_escrow_engine::ID).unwrap();
+
+        let request = with_memo(program.request(), None, &program.payer());
+        assert!(request.instructions().unwrap().is_empty());
+    }
+
+    fn clone_args(amount: Option<u64>) -> CloneArgs {
+        CloneArgs {
+            from: Pubkey::new_unique().to_string(),
+            amount,
+            mint: None,
+            timeout: None,
+            description: None,
+            memo: None,
+        }
+    }
+
+    #[test]
+    fn clones_terms_but_not_the_source_schedule_or_counterparty() {
+        let mut source =
+            solana_escrow_engine::Escrow::deserialize(&mut &[0u8; solana_escrow_engine::Escrow::LEN][..]).unwrap();
+        source.mint = Pubkey::new_unique();
+        source.seller = Pubkey::new_unique();
+        source.amount = 1_000;
+        source.description = "Logo design".to_string();
+        source.start_at = 5_000;
+        source.timeout_at = 5_000 + 86_400;
+        source.delivery_deadline = 5_000 + 3_600;
+        source.permissionless_timeout = true;
+        source.required_collateral = 100;
+        source.referrer = Pubkey::new_unique();
+        source.referrer_bps = 50;
+        source.milestones = vec![solana_escrow_engine::Milestone { label: "Draft".to_string(), amount: 1_000, released: true }];
+
+        let args = clone_create_args(&source, clone_args(None)).unwrap();
+        assert_eq!(args.mint, source.mint.to_string());
+        assert_eq!(args.amount, 1_000);
+        assert_eq!(args.timeout, 86_400);
+        assert_eq!(args.delivery_window, 3_600);
+        assert_eq!(args.start_at, 0);
+        assert_eq!(args.description.as_deref(), Some("Logo design"));
+        assert_eq!(args.milestones, vec![("Draft".to_string(), 1_000)]);
+        assert_eq!(args.collateral, 100);
+        assert!(!args.parties_only_timeout);
+        assert_eq!(args.arbiter, None);
+        assert_eq!(args.referrer, Some(source.referrer.to_string()));
+        assert_eq!(args.referrer_bps, 50);
+        assert_eq!(args.auto_accept_seller, None);
+
+        // A new amount no longer matches the milestones
+        let args = clone_create_args(&source, clone_args(Some(2_000))).unwrap();
+        assert_eq!(args.amount, 2_000);
+        assert!(args.milestones.is_empty());
+
+        source.swap_mint = Pubkey::new_unique();
+        assert!(clone_create_args(&source, clone_args(None)).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+//! Events the program emits, in a versioned layout indexers can decode
+//! without the IDL.
+//!
+//! Each event is logged by `emit!` as a `Program data: <base64>` line in the
+//! transaction's log messages. The decoded bytes are the event's 8-byte
+//! discriminator, the first 8 bytes of `sha256("event:<EventName>")`,
+//! followed by its fields in Borsh. Every event opens with the same header:
+//!
+//! | Offset | Size | Field            |
+//! |--------|------|------------------|
+//! | 0      | 8    | discriminator    |
+//! | 8      | 1    | `schema_version` |
+//! | 9      | 32   | `escrow`         |
+//! | 41     | 32   | `buyer`          |
+//! | 73     | 32   | `seller`         |
+//!
+//! Event-specific fields follow from offset `HEADER_LEN`. Integers are
+//! little-endian, and enums are a single variant-index byte. `seller` is the
+//! default pubkey while no seller has accepted.
+//!
+//! Within a `schema_version` the layout only ever grows: new fields are
+//! appended, so consumers should ignore trailing bytes they don't know.
+//! Reordering, removing or retyping a field bumps `EVENT_SCHEMA_VERSION`.
+
+use anchor_lang::prelude::*;
+
+use crate::DeclineReason;
+
+/// Layout version every event carries in `schema_version`
+pub const EVENT_SCHEMA_VERSION: u8 = 1;
+
+/// Bytes before an event's own fields: discriminator, version and the
+/// escrow, buyer and seller keys
+pub const HEADER_LEN: usize = 8 + 1 + 32 + 32 + 32;
+
+/// Emitted once when an escrow comes within `warn_lead` of its timeout, so
+/// keepers can remind the buyer before auto-resolution. Body: `timeout_at`
+/// (i64) at `HEADER_LEN`.
+#[event]
+pub struct EscrowExpiringSoon {
+    pub schema_version: u8,
+    pub escrow: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub timeout_at: i64,
+}
+
+/// Emitted when a seller backs out of an escrow they accepted. Body:
+/// `reason` (u8 variant index) at `HEADER_LEN`.
+#[event]
+pub struct EscrowDeclined {
+    pub schema_version: u8,
+    pub escrow: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub reason: DeclineReason,
+}
+
+/// Emitted when the seller asks the buyer to confirm delivery. Body:
+/// `release_deadline` (i64) at `HEADER_LEN`.
+#[event]
+pub struct ConfirmationRequested {
+    pub schema_version: u8,
+    pub escrow: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    /// When `resolve_timeout` becomes callable
+    pub release_deadline: i64,
+}
+
+/// Emitted when the arbiter asks the parties for more evidence. Body:
+/// `arbiter` (32 bytes) at `HEADER_LEN`.
+#[event]
+pub struct EvidenceRequested {
+    pub schema_version: u8,
+    pub escrow: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub arbiter: Pubkey,
+}
+
+/// Emitted when a party submits evidence. Body: `submitter` (32 bytes) at
+/// `HEADER_LEN`, then `evidence_uri` (u32 length and UTF-8 bytes).
+#[event]
+pub struct EvidenceSubmitted {
+    pub schema_version: u8,
+    pub escrow: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub submitter: Pubkey,
+    pub evidence_uri: String,
+}
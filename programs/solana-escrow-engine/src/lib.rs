@@ -1,5 +1,13 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+use anchor_lang::solana_program::program_option::COption;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, CloseAccount, Mint, Token, TokenAccount, Transfer};
+use solana_instructions_sysvar::{load_current_index_checked, load_instruction_at_checked};
+use solana_sdk_ids::{ed25519_program, sysvar::instructions as instructions_sysvar};
+
+pub mod events;
+
+pub use events::*;
 
 declare_id!("DgS6gJZToqri3RN6LmvMYNxAMKNnipHdEDAVyU5QFE6t");
 
@@ -7,103 +15,751 @@ declare_id!("DgS6gJZToqri3RN6LmvMYNxAMKNnipHdEDAVyU5QFE6t");
 pub mod solana_escrow_engine {
     use super::*;
 
-    /// Creates a new escrow with buyer depositing funds
-    pub fn create_escrow(
-        ctx: Context<CreateEscrow>,
+    /// Creates a new escrow with the buyer depositing `params.amount`; see
+    /// `CreateParams` for the terms. The deposit may come from an account the
+    /// buyer owns or one whose owner approved the buyer as delegate for at
+    /// least the amount; refunds always go to the buyer. Passing a
+    /// `seller_authorization` opens the escrow already accepted by that seller.
+    pub fn create_escrow(ctx: Context<CreateEscrow>, params: CreateParams) -> Result<()> {
+        let bump = ctx.bumps.escrow;
+        let accounts = ctx.accounts;
+        open_escrow(
+            &mut accounts.escrow,
+            bump,
+            &accounts.buyer,
+            &accounts.mint,
+            &accounts.buyer_token_account,
+            &accounts.vault_token_account,
+            &mut accounts.config,
+            &mut accounts.buyer_account,
+            &accounts.token_program,
+            params,
+        )?;
+
+        if let Some(authorization) = &accounts.seller_authorization {
+            accept_preauthorized(&mut accounts.escrow, authorization)?;
+        }
+        Ok(())
+    }
+
+    /// `create_escrow` into a vault the caller already created, e.g. the
+    /// escrow PDA's associated token account, instead of a fresh vault PDA.
+    /// The vault must be an empty account of `mint` whose owner is the escrow
+    /// PDA, with no delegate or close authority, so only this program can move
+    /// or close its funds.
+    pub fn create_escrow_with_vault(ctx: Context<CreateEscrowWithVault>, params: CreateParams) -> Result<()> {
+        let bump = ctx.bumps.escrow;
+        let accounts = ctx.accounts;
+        open_escrow(
+            &mut accounts.escrow,
+            bump,
+            &accounts.buyer,
+            &accounts.mint,
+            &accounts.buyer_token_account,
+            &accounts.vault_token_account,
+            &mut accounts.config,
+            &mut accounts.buyer_account,
+            &accounts.token_program,
+            params,
+        )
+    }
+
+    /// `create_escrow` for a deal already agreed off-chain: buyer and seller
+    /// both sign, and the escrow opens in `Accepted` with the seller set, so
+    /// nobody else can accept in between. Any required collateral is taken
+    /// from `seller_token_account` in the same instruction.
+    pub fn create_and_accept(ctx: Context<CreateAndAccept>, params: CreateParams) -> Result<()> {
+        let bump = ctx.bumps.escrow;
+        let accounts = ctx.accounts;
+        let required_collateral = params.required_collateral;
+        open_escrow(
+            &mut accounts.escrow,
+            bump,
+            &accounts.buyer,
+            &accounts.mint,
+            &accounts.buyer_token_account,
+            &accounts.vault_token_account,
+            &mut accounts.config,
+            &mut accounts.buyer_account,
+            &accounts.token_program,
+            params,
+        )?;
+
+        let escrow = &mut accounts.escrow;
+        let seller = accounts.seller.key();
+        let clock = Clock::get()?;
+        escrow.can_accept(&seller, clock.unix_timestamp)?;
+
+        if required_collateral > 0 {
+            let cpi_accounts = Transfer {
+                from: accounts.seller_token_account.to_account_info(),
+                to: accounts.vault_token_account.to_account_info(),
+                authority: accounts.seller.to_account_info(),
+            };
+            let cpi_program = accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            transfer_tokens(cpi_ctx, required_collateral)?;
+        }
+
+        escrow.seller = seller;
+        escrow.state = EscrowState::Accepted;
+        escrow.record(TimelineEvent::Accepted, clock.unix_timestamp)?;
+        warn_if_expiring(escrow, clock.unix_timestamp);
+
+        msg!("Escrow created and accepted by seller: {}", seller);
+        Ok(())
+    }
+
+    /// Marketplace publishes standard escrow terms under `name`, at
+    /// `[b"template", authority, name]`, for `create_escrow_from_template`
+    pub fn create_template(ctx: Context<CreateTemplate>, name: String, terms: TemplateTerms) -> Result<()> {
+        require!(
+            !name.is_empty() && name.len() <= Template::MAX_NAME_LEN,
+            EscrowError::InvalidTemplate
+        );
+        require!(terms.fee_bps <= Config::MAX_FEE_BPS, EscrowError::InvalidTemplate);
+
+        let template = &mut ctx.accounts.template;
+        template.authority = ctx.accounts.authority.key();
+        template.name = name;
+        template.bump = ctx.bumps.template;
+        template.terms = terms;
+
+        msg!("Template {} created by {}", template.name, template.authority);
+        Ok(())
+    }
+
+    /// `create_escrow` with a template's terms; the buyer picks only the
+    /// amount, description and, through a `seller_authorization`, the
+    /// counterparty. The template's `fee_bps` is the escrow's fee, capped at
+    /// what the config would charge the buyer.
+    pub fn create_escrow_from_template(
+        ctx: Context<CreateEscrowFromTemplate>,
         amount: u64,
-        timeout_duration: i64,
-        description: String,
+        description: Option<String>,
     ) -> Result<()> {
+        let bump = ctx.bumps.escrow;
+        let accounts = ctx.accounts;
+        let terms = accounts.template.terms.clone();
+        open_escrow(
+            &mut accounts.escrow,
+            bump,
+            &accounts.buyer,
+            &accounts.mint,
+            &accounts.buyer_token_account,
+            &accounts.vault_token_account,
+            &mut accounts.config,
+            &mut accounts.buyer_account,
+            &accounts.token_program,
+            CreateParams {
+                amount,
+                timeout_duration: terms.timeout_duration,
+                description: description.unwrap_or_default(),
+                arbiter: terms.arbiter,
+                timeout_beneficiary: terms.timeout_beneficiary,
+                required_collateral: terms.required_collateral,
+                permissionless_timeout: terms.permissionless_timeout,
+                reject_freezable: terms.reject_freezable,
+                delivery_window: terms.delivery_window,
+                dispute_window: terms.dispute_window,
+                auto_close: terms.auto_close,
+                ..Default::default()
+            },
+        )?;
+        let escrow = &mut accounts.escrow;
+        escrow.fee_bps = escrow.fee_bps.min(terms.fee_bps);
+
+        if let Some(authorization) = &accounts.seller_authorization {
+            accept_preauthorized(&mut accounts.escrow, authorization)?;
+        }
+        msg!("Escrow created from template {}", accounts.template.name);
+        Ok(())
+    }
+
+    /// Seller accepts the escrow and commits to delivery. Passing a
+    /// `seller_payout_account` binds every later payout to that account, so
+    /// whoever settles can't route the seller's funds anywhere else.
+    pub fn accept_escrow(ctx: Context<AcceptEscrow>) -> Result<()> {
         let escrow = &mut ctx.accounts.escrow;
         let clock = Clock::get()?;
         
-        // Initialize escrow account
-        escrow.buyer = ctx.accounts.buyer.key();
-        escrow.seller = Pubkey::default(); // Will be set when seller accepts
-        escrow.mint = ctx.accounts.mint.key();
-        escrow.amount = amount;
-        escrow.created_at = clock.unix_timestamp;
-        escrow.timeout_at = clock.unix_timestamp + timeout_duration;
-        escrow.state = EscrowState::Created;
-        escrow.description = description;
-        escrow.bump = ctx.bumps.escrow;
+        require_operational_for(&ctx.accounts.config, escrow)?;
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.required_collateral == 0, EscrowError::CollateralRequired);
+        escrow.can_accept(&ctx.accounts.seller.key(), clock.unix_timestamp)?;
+        
+        escrow.seller = ctx.accounts.seller.key();
+        escrow.state = EscrowState::Accepted;
+        if let Some(payout) = bound_payout(&ctx.accounts.seller_payout_account, &ctx.accounts.seller_payout_ata)? {
+            escrow.seller_payout_account = payout;
+        }
+        
+        escrow.record(TimelineEvent::Accepted, clock.unix_timestamp)?;
+        warn_if_expiring(escrow, clock.unix_timestamp);
+        
+        msg!("Escrow accepted by seller: {}", ctx.accounts.seller.key());
+        Ok(())
+    }
+
+    /// Seller accepts up to `MAX_BATCH_ACCEPT` escrows in one transaction,
+    /// passed as writable `remaining_accounts`. Escrows `accept_escrow` would
+    /// refuse (not open to this seller, swaps, or requiring collateral) are
+    /// skipped rather than failing the batch. Returns how many were accepted.
+    pub fn accept_escrows_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, AcceptEscrowsBatch<'info>>,
+    ) -> Result<u8> {
+        require_operational(&ctx.accounts.config)?;
+        let batch_len = ctx.remaining_accounts.len();
+        require!(batch_len <= MAX_BATCH_ACCEPT, EscrowError::InvalidAcceptBatch);
+        let clock = Clock::get()?;
+        let seller = ctx.accounts.seller.key();
+        let mut accepted = 0u8;
+
+        for info in ctx.remaining_accounts {
+            let Ok(mut escrow) = Account::<Escrow>::try_from(info) else {
+                continue;
+            };
+            let eligible = info.is_writable
+                && (ctx.accounts.config.is_some() || !escrow.counted_in_config)
+                && !escrow.is_swap()
+                && escrow.required_collateral == 0
+                && escrow.can_accept(&seller, clock.unix_timestamp).is_ok();
+            if !eligible {
+                continue;
+            }
+
+            escrow.seller = seller;
+            escrow.state = EscrowState::Accepted;
+            escrow.record(TimelineEvent::Accepted, clock.unix_timestamp)?;
+            warn_if_expiring(&mut escrow, clock.unix_timestamp);
+            escrow.exit(&crate::ID)?;
+            accepted += 1;
+        }
+
+        msg!("Seller {} accepted {} of {} escrows", seller, accepted, batch_len);
+        Ok(accepted)
+    }
+
+    /// Seller accepts an escrow that requires collateral, depositing
+    /// `required_collateral` of the escrow's mint into its vault. The
+    /// collateral goes back to the seller when the escrow settles, unless a
+    /// timeout refunds the buyer, who then keeps it.
+    pub fn accept_with_collateral(ctx: Context<AcceptWithCollateral>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let clock = Clock::get()?;
+
+        require_operational_for(&ctx.accounts.config, escrow)?;
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        escrow.can_accept(&ctx.accounts.seller.key(), clock.unix_timestamp)?;
 
-        // Transfer funds to escrow vault
         let cpi_accounts = Transfer {
-            from: ctx.accounts.buyer_token_account.to_account_info(),
+            from: ctx.accounts.seller_token_account.to_account_info(),
             to: ctx.accounts.vault_token_account.to_account_info(),
-            authority: ctx.accounts.buyer.to_account_info(),
+            authority: ctx.accounts.seller.to_account_info(),
         };
         let cpi_program = ctx.accounts.token_program.to_account_info();
         let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        token::transfer(cpi_ctx, amount)?;
+        transfer_tokens(cpi_ctx, escrow.required_collateral)?;
+
+        escrow.seller = ctx.accounts.seller.key();
+        escrow.state = EscrowState::Accepted;
+        if let Some(payout) = &ctx.accounts.seller_payout_account {
+            escrow.seller_payout_account = payout.key();
+        }
 
-        msg!("Escrow created: {} tokens deposited", amount);
+        escrow.record(TimelineEvent::Accepted, clock.unix_timestamp)?;
+        warn_if_expiring(escrow, clock.unix_timestamp);
+
+        msg!(
+            "Escrow accepted by seller {} with {} collateral",
+            ctx.accounts.seller.key(),
+            escrow.required_collateral
+        );
         Ok(())
     }
 
-    /// Seller accepts the escrow and commits to delivery
-    pub fn accept_escrow(ctx: Context<AcceptEscrow>) -> Result<()> {
+    /// Seller accepts a bulk order but commits to delivering only
+    /// `fulfilled_amount` of `escrow.amount`. Confirmation pays the seller
+    /// that much and refunds the unfulfilled remainder to the buyer.
+    pub fn partial_accept(ctx: Context<AcceptEscrow>, fulfilled_amount: u64) -> Result<()> {
         let escrow = &mut ctx.accounts.escrow;
-        
-        require!(escrow.state == EscrowState::Created, EscrowError::InvalidState);
-        require!(escrow.seller == Pubkey::default(), EscrowError::AlreadyAccepted);
-        
+        let clock = Clock::get()?;
+
+        require_operational_for(&ctx.accounts.config, escrow)?;
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.required_collateral == 0, EscrowError::CollateralRequired);
+        require!(escrow.milestones.is_empty(), EscrowError::InvalidMilestone);
+        require!(
+            fulfilled_amount > 0 && fulfilled_amount < escrow.amount,
+            EscrowError::InvalidFulfilledAmount
+        );
+        escrow.can_accept(&ctx.accounts.seller.key(), clock.unix_timestamp)?;
+
         escrow.seller = ctx.accounts.seller.key();
+        escrow.fulfilled_amount = fulfilled_amount;
         escrow.state = EscrowState::Accepted;
-        
+        if let Some(payout) = bound_payout(&ctx.accounts.seller_payout_account, &ctx.accounts.seller_payout_ata)? {
+            escrow.seller_payout_account = payout;
+        }
+
+        escrow.record(TimelineEvent::Accepted, clock.unix_timestamp)?;
+        warn_if_expiring(escrow, clock.unix_timestamp);
+
+        msg!(
+            "Escrow accepted by seller {} for {} of {}",
+            ctx.accounts.seller.key(),
+            fulfilled_amount,
+            escrow.amount
+        );
+        Ok(())
+    }
+
+    /// Buyer turns an open escrow into a sealed-bid auction: sellers
+    /// `commit_bid` until `reveal_at`, then `reveal_bid`, and the buyer
+    /// `select_bid`s one of the revealed bids. Direct acceptance is closed
+    /// meanwhile.
+    pub fn open_sealed_bidding(ctx: Context<OpenSealedBidding>, reveal_at: i64) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
         let clock = Clock::get()?;
-        escrow.accepted_at = clock.unix_timestamp;
-        
-        msg!("Escrow accepted by seller: {}", ctx.accounts.seller.key());
+
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.buyer == ctx.accounts.buyer.key(), EscrowError::UnauthorizedBuyer);
+        require!(
+            escrow.state == EscrowState::Created && escrow.seller == Pubkey::default(),
+            EscrowError::InvalidState
+        );
+        require!(escrow.bid_reveal_at == 0, EscrowError::SealedBidding);
+        require!(
+            reveal_at > clock.unix_timestamp && reveal_at < escrow.timeout_at,
+            EscrowError::WrongBidPhase
+        );
+
+        escrow.bid_reveal_at = reveal_at;
+        msg!("Sealed bidding open, bids reveal from {}", reveal_at);
+        Ok(())
+    }
+
+    /// Seller commits to a bid without showing it: `commitment` is the
+    /// sha256 of the price (u64 little-endian) followed by a 32-byte salt.
+    /// Only before the reveal deadline.
+    pub fn commit_bid(ctx: Context<CommitBid>, commitment: [u8; 32]) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+        let clock = Clock::get()?;
+
+        require!(escrow.state == EscrowState::Created, EscrowError::InvalidState);
+        require!(escrow.bid_reveal_at > 0, EscrowError::WrongBidPhase);
+        require!(clock.unix_timestamp < escrow.bid_reveal_at, EscrowError::WrongBidPhase);
+        require_keys_neq!(ctx.accounts.seller.key(), escrow.buyer, EscrowError::SellerCannotBeBuyer);
+
+        let bid = &mut ctx.accounts.bid;
+        bid.escrow = escrow.key();
+        bid.seller = ctx.accounts.seller.key();
+        bid.commitment = commitment;
+        bid.bump = ctx.bumps.bid;
+
+        msg!("Bid committed by seller: {}", bid.seller);
+        Ok(())
+    }
+
+    /// Seller opens their bid once the reveal deadline has passed; the price
+    /// and salt must hash to the commitment
+    pub fn reveal_bid(ctx: Context<RevealBid>, price: u64, salt: [u8; 32]) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+        let clock = Clock::get()?;
+
+        require!(escrow.state == EscrowState::Created, EscrowError::InvalidState);
+        require!(
+            escrow.bid_reveal_at > 0 && clock.unix_timestamp >= escrow.bid_reveal_at,
+            EscrowError::WrongBidPhase
+        );
+        let bid = &mut ctx.accounts.bid;
+        require!(!bid.revealed, EscrowError::WrongBidPhase);
+        require!(bid.matches(price, &salt), EscrowError::BidMismatch);
+
+        bid.price = price;
+        bid.revealed = true;
+        msg!("Bid revealed by seller {}: {}", bid.seller, price);
+        Ok(())
+    }
+
+    /// Buyer picks a revealed bid, which accepts the escrow for that seller.
+    /// A price below the amount accepts it partially, as `partial_accept`
+    /// would, so the rest refunds to the buyer on confirmation.
+    pub fn select_bid(ctx: Context<SelectBid>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let bid = &ctx.accounts.bid;
+        let clock = Clock::get()?;
+
+        require_operational_for(&ctx.accounts.config, escrow)?;
+        require!(escrow.buyer == ctx.accounts.buyer.key(), EscrowError::UnauthorizedBuyer);
+        require!(escrow.bid_reveal_at > 0, EscrowError::WrongBidPhase);
+        require!(bid.revealed, EscrowError::BidNotRevealed);
+        require!(escrow.required_collateral == 0, EscrowError::CollateralRequired);
+        require!(
+            bid.price > 0 && bid.price <= escrow.amount,
+            EscrowError::InvalidFulfilledAmount
+        );
+        require!(
+            bid.price == escrow.amount || escrow.milestones.is_empty(),
+            EscrowError::InvalidMilestone
+        );
+        // Selecting ends the auction, which is what lets it pass `can_accept`
+        escrow.bid_reveal_at = 0;
+        escrow.can_accept(&bid.seller, clock.unix_timestamp)?;
+
+        escrow.seller = bid.seller;
+        if bid.price < escrow.amount {
+            escrow.fulfilled_amount = bid.price;
+        }
+        escrow.state = EscrowState::Accepted;
+        escrow.record(TimelineEvent::Accepted, clock.unix_timestamp)?;
+        warn_if_expiring(escrow, clock.unix_timestamp);
+
+        msg!("Bid of {} selected from seller: {}", bid.price, bid.seller);
+        Ok(())
+    }
+
+    /// Seller withdraws their bid and takes back its rent, typically once the
+    /// auction is decided
+    pub fn close_bid(_ctx: Context<CloseBid>) -> Result<()> {
+        msg!("Bid closed");
+        Ok(())
+    }
+
+    /// Seller backs out of an escrow they accepted, giving `reason`. The
+    /// escrow reopens for other sellers, or for the buyer to cancel, with the
+    /// reason kept on it. Only possible before any delivery, release or
+    /// confirmation request, and not after depositing collateral, which must
+    /// be earned back by delivering.
+    pub fn decline_escrow(ctx: Context<DeclineEscrow>, reason: DeclineReason) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.state == EscrowState::Accepted, EscrowError::InvalidState);
+        require!(escrow.seller == ctx.accounts.seller.key(), EscrowError::UnauthorizedSeller);
+        require!(
+            escrow.required_collateral == 0
+                && escrow.delivered_at == 0
+                && escrow.released_amount == 0
+                && escrow.confirmation_requested_at == 0,
+            EscrowError::InvalidState
+        );
+
+        escrow.seller = Pubkey::default();
+        escrow.state = EscrowState::Created;
+        escrow.fulfilled_amount = 0;
+        escrow.seller_payout_account = Pubkey::default();
+        escrow.seller_is_multisig = false;
+        escrow.seller_treasury = Pubkey::default();
+        escrow.last_heartbeat_at = 0;
+        escrow.withdrawal_requested_at = 0;
+        // Forget the acceptance, so a later one starts the SLA clock afresh
+        escrow.timeline.retain(|entry| entry.event != TimelineEvent::Accepted);
+        escrow.decline_reason = Some(reason);
+
+        emit!(EscrowDeclined {
+            schema_version: EVENT_SCHEMA_VERSION,
+            escrow: escrow.key(),
+            buyer: escrow.buyer,
+            seller: ctx.accounts.seller.key(),
+            reason,
+        });
+
+        msg!("Escrow declined by seller {}: {:?}", ctx.accounts.seller.key(), reason);
+        Ok(())
+    }
+
+    /// Seller that is a multisig authority names the treasury whose token
+    /// accounts receive its payouts, for multisigs whose funds sit under a
+    /// different key than the one that signs. `None` clears it. A payout
+    /// account bound at acceptance still takes precedence.
+    pub fn set_seller_treasury(ctx: Context<SetSellerTreasury>, treasury: Option<Pubkey>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.state == EscrowState::Accepted, EscrowError::InvalidState);
+        require!(escrow.seller == ctx.accounts.seller.key(), EscrowError::UnauthorizedSeller);
+
+        escrow.seller_is_multisig = treasury.is_some();
+        escrow.seller_treasury = treasury.unwrap_or_default();
+
+        msg!("Seller treasury set to {}", escrow.seller_treasury);
+        Ok(())
+    }
+
+    /// Seller pre-authorizes `buyer` to open escrows already accepted by
+    /// them, skipping `accept_escrow` for a trusted, recurring counterparty
+    pub fn authorize_seller(ctx: Context<AuthorizeSeller>, buyer: Pubkey) -> Result<()> {
+        let authorization = &mut ctx.accounts.seller_authorization;
+        authorization.buyer = buyer;
+        authorization.seller = ctx.accounts.seller.key();
+        authorization.bump = ctx.bumps.seller_authorization;
+
+        msg!("Seller {} pre-authorized buyer {}", authorization.seller, buyer);
         Ok(())
     }
 
-    /// Buyer confirms receipt and releases funds to seller
-    pub fn confirm_delivery(ctx: Context<ConfirmDelivery>) -> Result<()> {
+    /// Opens the buyer's active escrow counter. Escrows created with it are
+    /// counted until they settle; a config with `max_active_per_buyer` set
+    /// requires it at creation.
+    pub fn init_buyer_account(ctx: Context<InitBuyerAccount>) -> Result<()> {
+        let buyer_account = &mut ctx.accounts.buyer_account;
+        buyer_account.buyer = ctx.accounts.buyer.key();
+        buyer_account.bump = ctx.bumps.buyer_account;
+
+        msg!("Buyer account opened for {}", buyer_account.buyer);
+        Ok(())
+    }
+
+    /// Seller withdraws a pre-authorization, reclaiming its rent. Escrows
+    /// already auto-accepted are unaffected.
+    pub fn revoke_seller_authorization(ctx: Context<RevokeSellerAuthorization>) -> Result<()> {
+        msg!(
+            "Seller {} revoked buyer {}",
+            ctx.accounts.seller.key(),
+            ctx.accounts.seller_authorization.buyer
+        );
+        Ok(())
+    }
+
+    /// Buyer confirms receipt and releases funds to seller. A nonzero `tip`
+    /// is paid on top from the buyer's token account, straight to the seller
+    /// and free of the protocol fee.
+    pub fn confirm_delivery(ctx: Context<ConfirmDelivery>, tip: u64) -> Result<()> {
         let escrow = &mut ctx.accounts.escrow;
         
+        require_operational_for(&ctx.accounts.config, escrow)?;
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
         require!(escrow.state == EscrowState::Accepted, EscrowError::InvalidState);
         require!(escrow.buyer == ctx.accounts.buyer.key(), EscrowError::UnauthorizedBuyer);
+        // Paying yourself would only burn the protocol fee
+        require_keys_neq!(escrow.buyer, escrow.seller, EscrowError::SellerCannotBeBuyer);
         
-        // Transfer funds from vault to seller
-        let seeds = &[
-            b"escrow",
-            escrow.buyer.as_ref(),
-            escrow.mint.as_ref(),
-            &[escrow.bump],
-        ];
-        let signer = &[&seeds[..]];
+        if tip > 0 {
+            let buyer_token_account = ctx
+                .accounts
+                .buyer_token_account
+                .as_deref()
+                .ok_or(EscrowError::BuyerTokenAccountRequired)?;
+            let cpi_accounts = Transfer {
+                from: buyer_token_account.to_account_info(),
+                to: ctx.accounts.seller_token_account.to_account_info(),
+                authority: ctx.accounts.buyer.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+            transfer_tokens(cpi_ctx, tip)?;
+            msg!("Buyer tipped the seller {}", tip);
+        }
         
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.vault_token_account.to_account_info(),
-            to: ctx.accounts.seller_token_account.to_account_info(),
-            authority: escrow.to_account_info(),
-        };
-        let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-        token::transfer(cpi_ctx, escrow.amount)?;
+        // Transfer funds from vault to seller, less any SLA penalty
+        return_collateral(
+            escrow,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.seller_token_account,
+            ctx.accounts.buyer_token_account.as_deref(),
+            &ctx.accounts.token_program,
+            Clock::get()?.unix_timestamp,
+        )?;
+        refund_unfulfilled(
+            escrow,
+            &ctx.accounts.vault_token_account,
+            ctx.accounts.buyer_token_account.as_deref(),
+            &ctx.accounts.token_program,
+        )?;
+        if escrow.vesting_duration > 0 {
+            start_vesting(escrow)?;
+            return Ok(());
+        }
+        pay_seller(
+            escrow,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.seller_token_account,
+            ctx.accounts.fee_vault.as_deref(),
+            ctx.accounts.referrer_token_account.as_deref(),
+            &ctx.accounts.token_program,
+            escrow.remaining_amount()?,
+        )?;
 
+        escrow.released_amount = escrow.amount;
+        for milestone in escrow.milestones.iter_mut() {
+            milestone.released = true;
+        }
         escrow.state = EscrowState::Completed;
         let clock = Clock::get()?;
-        escrow.completed_at = clock.unix_timestamp;
+        escrow.record(TimelineEvent::Completed, clock.unix_timestamp)?;
+        untrack(escrow, &mut ctx.accounts.config, &mut ctx.accounts.buyer_account)?;
         
         msg!("Delivery confirmed, funds released to seller");
+        let rent_recipient = ctx
+            .accounts
+            .rent_recipient
+            .as_ref()
+            .map_or_else(|| ctx.accounts.buyer.to_account_info(), |account| account.to_account_info());
+        auto_close(
+            escrow,
+            &mut ctx.accounts.vault_token_account,
+            Some(rent_recipient),
+            &ctx.accounts.token_program,
+        )
+    }
+
+    /// Relayer-submitted confirmation. The preceding instruction must be an
+    /// Ed25519 sig-verify of `signed_confirm_message` by `escrow.buyer`, so the
+    /// relayer can settle without holding the buyer's key. `nonce` must equal
+    /// `escrow.confirm_nonce`, which makes each signature single-use.
+    pub fn confirm_delivery_signed(ctx: Context<ConfirmDeliverySigned>, nonce: u64) -> Result<()> {
+        let message = signed_confirm_message(&ctx.accounts.escrow.key(), nonce);
+        let escrow = &mut ctx.accounts.escrow;
+
+        require_operational_for(&ctx.accounts.config, escrow)?;
+        require_relayer(&ctx.accounts.config, escrow, &ctx.accounts.relayer.key())?;
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(nonce == escrow.confirm_nonce, EscrowError::StaleSignature);
+        require!(escrow.state == EscrowState::Accepted, EscrowError::InvalidState);
+        require_keys_neq!(escrow.buyer, escrow.seller, EscrowError::SellerCannotBeBuyer);
+        verify_ed25519_instruction(&ctx.accounts.instructions, &escrow.buyer, &message)?;
+        escrow.confirm_nonce = escrow.confirm_nonce.checked_add(1).ok_or(EscrowError::ArithmeticOverflow)?;
+
+        return_collateral(
+            escrow,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.seller_token_account,
+            ctx.accounts.buyer_token_account.as_deref(),
+            &ctx.accounts.token_program,
+            Clock::get()?.unix_timestamp,
+        )?;
+        refund_unfulfilled(
+            escrow,
+            &ctx.accounts.vault_token_account,
+            ctx.accounts.buyer_token_account.as_deref(),
+            &ctx.accounts.token_program,
+        )?;
+        if escrow.vesting_duration > 0 {
+            start_vesting(escrow)?;
+            return Ok(());
+        }
+        pay_seller(
+            escrow,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.seller_token_account,
+            ctx.accounts.fee_vault.as_deref(),
+            ctx.accounts.referrer_token_account.as_deref(),
+            &ctx.accounts.token_program,
+            escrow.remaining_amount()?,
+        )?;
+
+        escrow.released_amount = escrow.amount;
+        for milestone in escrow.milestones.iter_mut() {
+            milestone.released = true;
+        }
+        escrow.state = EscrowState::Completed;
+        let clock = Clock::get()?;
+        escrow.record(TimelineEvent::Completed, clock.unix_timestamp)?;
+        untrack(escrow, &mut ctx.accounts.config, &mut ctx.accounts.buyer_account)?;
+
+        msg!("Signed delivery confirmation relayed by {}", ctx.accounts.relayer.key());
+        Ok(())
+    }
+
+    /// `confirm_delivery`, but only once the seller's `deliver_secret`
+    /// payload hashes to `payload_hash`, so the buyer confirms exactly what
+    /// they received
+    pub fn confirm_delivery_with_payload(ctx: Context<ConfirmDelivery>, payload_hash: [u8; 32]) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+
+        require!(!escrow.delivery_payload.is_empty(), EscrowError::NoDeliveryPayload);
+        require!(escrow.payload_matches(&payload_hash), EscrowError::PayloadMismatch);
+
+        confirm_delivery(ctx, 0)
+    }
+
+    /// Seller pulls whatever has vested since confirmation and wasn't claimed
+    /// yet. The claim that completes the schedule completes the escrow.
+    pub fn claim_vested(ctx: Context<ClaimVested>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let clock = Clock::get()?;
+
+        require_operational_for(&ctx.accounts.config, escrow)?;
+        require!(escrow.state == EscrowState::Vesting, EscrowError::InvalidState);
+        require!(escrow.seller == ctx.accounts.seller.key(), EscrowError::UnauthorizedSeller);
+
+        let now = escrow.now(&clock);
+        let claimable = escrow
+            .vested_amount(now)?
+            .checked_sub(escrow.claimed_amount)
+            .ok_or(EscrowError::ArithmeticOverflow)?;
+        require!(claimable > 0, EscrowError::NothingVested);
+
+        pay_seller(
+            escrow,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.seller_token_account,
+            ctx.accounts.fee_vault.as_deref(),
+            ctx.accounts.referrer_token_account.as_deref(),
+            &ctx.accounts.token_program,
+            claimable,
+        )?;
+        escrow.claimed_amount = escrow
+            .claimed_amount
+            .checked_add(claimable)
+            .ok_or(EscrowError::ArithmeticOverflow)?;
+        escrow.released_amount = escrow
+            .released_amount
+            .checked_add(claimable)
+            .ok_or(EscrowError::ArithmeticOverflow)?;
+
+        if escrow.remaining_amount()? == 0 {
+            for milestone in escrow.milestones.iter_mut() {
+                milestone.released = true;
+            }
+            escrow.state = EscrowState::Completed;
+            escrow.record(TimelineEvent::Completed, clock.unix_timestamp)?;
+            untrack(escrow, &mut ctx.accounts.config, &mut ctx.accounts.buyer_account)?;
+        }
+
+        msg!("Seller claimed {} vested tokens", claimable);
         Ok(())
     }
 
-    /// Cancel escrow before seller acceptance (buyer gets refund)
+    /// Cancel escrow before seller acceptance, refunding the original depositor.
+    /// A `Vesting` escrow can be cancelled too, ending the schedule early:
+    /// only the part that hasn't vested yet goes back, while whatever has
+    /// vested stays in the vault for the seller's `claim_vested`. The other
+    /// refund paths (`execute_withdrawal`, `claim_non_delivery`,
+    /// `claim_abandoned`, timeouts and disputes) never reach a vesting escrow.
     pub fn cancel_escrow(ctx: Context<CancelEscrow>) -> Result<()> {
         let escrow = &mut ctx.accounts.escrow;
         
-        require!(escrow.state == EscrowState::Created, EscrowError::InvalidState);
+        require!(
+            matches!(escrow.state, EscrowState::Created | EscrowState::Vesting),
+            EscrowError::InvalidState
+        );
         require!(escrow.buyer == ctx.accounts.buyer.key(), EscrowError::UnauthorizedBuyer);
+        require!(
+            ctx.accounts.buyer_token_account.owner == escrow.depositor(),
+            EscrowError::RefundAccountMismatch
+        );
         
-        // Transfer funds back to buyer
+        // Refund the live vault balance, which must still cover what is owed
+        let vault_balance = ctx.accounts.vault_token_account.amount;
+        require!(
+            vault_balance >= escrow.held_amount()?,
+            EscrowError::VaultBalanceMismatch
+        );
+        let clock = Clock::get()?;
+        let refund = if escrow.state == EscrowState::Vesting {
+            let unvested = escrow.unvested_amount(escrow.now(&clock))?;
+            require!(unvested > 0, EscrowError::FullyVested);
+            unvested
+        } else {
+            vault_balance
+        };
+
         let seeds = &[
             b"escrow",
-            escrow.buyer.as_ref(),
+            escrow.seed_buyer().as_ref(),
             escrow.mint.as_ref(),
+            escrow.seed_suffix(),
             &[escrow.bump],
         ];
         let signer = &[&seeds[..]];
@@ -115,14 +771,40 @@ pub mod solana_escrow_engine {
         };
         let cpi_program = ctx.accounts.token_program.to_account_info();
         let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-        token::transfer(cpi_ctx, escrow.amount)?;
+        transfer_tokens(cpi_ctx, refund)?;
 
-        escrow.state = EscrowState::Cancelled;
-        let clock = Clock::get()?;
-        escrow.cancelled_at = clock.unix_timestamp;
-        
-        msg!("Escrow cancelled, funds returned to buyer");
-        Ok(())
+        if escrow.state == EscrowState::Vesting {
+            // What's left is exactly what has vested and wasn't claimed, so
+            // the shortened schedule counts it all as vested
+            escrow.released_amount = escrow
+                .released_amount
+                .checked_add(refund)
+                .ok_or(EscrowError::ArithmeticOverflow)?;
+            escrow.vesting_duration = 0;
+            escrow.record(TimelineEvent::Cancelled, clock.unix_timestamp)?;
+            if escrow.remaining_amount()? == 0 {
+                escrow.state = EscrowState::Completed;
+                escrow.record(TimelineEvent::Completed, clock.unix_timestamp)?;
+                untrack(escrow, &mut ctx.accounts.config, &mut ctx.accounts.buyer_account)?;
+            }
+            msg!("Vesting cancelled, {} unvested tokens returned to buyer", refund);
+        } else {
+            escrow.state = EscrowState::Cancelled;
+            escrow.record(TimelineEvent::Cancelled, clock.unix_timestamp)?;
+            untrack(escrow, &mut ctx.accounts.config, &mut ctx.accounts.buyer_account)?;
+            msg!("Escrow cancelled, funds returned to buyer");
+        }
+        let rent_recipient = ctx
+            .accounts
+            .rent_recipient
+            .as_ref()
+            .map_or_else(|| ctx.accounts.buyer.to_account_info(), |account| account.to_account_info());
+        auto_close(
+            escrow,
+            &mut ctx.accounts.vault_token_account,
+            Some(rent_recipient),
+            &ctx.accounts.token_program,
+        )
     }
 
     /// Resolve timeout - automatically release funds if timeout passed
@@ -130,201 +812,5263 @@ pub mod solana_escrow_engine {
         let escrow = &mut ctx.accounts.escrow;
         let clock = Clock::get()?;
         
+        require_operational_for(&ctx.accounts.config, escrow)?;
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
         require!(escrow.state == EscrowState::Accepted, EscrowError::InvalidState);
-        require!(clock.unix_timestamp >= escrow.timeout_at, EscrowError::TimeoutNotReached);
+        require!(clock.unix_timestamp >= escrow.release_deadline(), EscrowError::TimeoutNotReached);
+        require_keys_neq!(escrow.buyer, escrow.seller, EscrowError::SellerCannotBeBuyer);
+        let resolver = ctx.accounts.resolver.key();
+        require!(
+            escrow.permissionless_timeout || resolver == escrow.buyer || resolver == escrow.seller,
+            EscrowError::UnauthorizedResolver
+        );
+        
+        match escrow.timeout_payee(clock.unix_timestamp) {
+            // Transfer funds from vault to seller (timeout favors seller, or
+            // the buyer let the dispute window lapse)
+            TimeoutBeneficiary::Seller => {
+                refund_unfulfilled(
+                    escrow,
+                    &ctx.accounts.vault_token_account,
+                    ctx.accounts.buyer_token_account.as_deref(),
+                    &ctx.accounts.token_program,
+                )?;
+                pay_seller(
+                    escrow,
+                    &ctx.accounts.vault_token_account,
+                    &ctx.accounts.seller_token_account,
+                    ctx.accounts.fee_vault.as_deref(),
+                    ctx.accounts.referrer_token_account.as_deref(),
+                    &ctx.accounts.token_program,
+                    escrow.remaining_amount()?,
+                )?;
+                return_collateral(
+                    escrow,
+                    &ctx.accounts.vault_token_account,
+                    &ctx.accounts.seller_token_account,
+                    ctx.accounts.buyer_token_account.as_deref(),
+                    &ctx.accounts.token_program,
+                    clock.unix_timestamp,
+                )?;
+            }
+            TimeoutBeneficiary::Buyer => {
+                let buyer_token_account = ctx
+                    .accounts
+                    .buyer_token_account
+                    .as_ref()
+                    .ok_or(EscrowError::BuyerTokenAccountRequired)?;
+                let seeds = &[
+                    b"escrow",
+                    escrow.seed_buyer().as_ref(),
+                    escrow.mint.as_ref(),
+                    escrow.seed_suffix(),
+                    &[escrow.bump],
+                ];
+                let signer = &[&seeds[..]];
+
+                let cpi_accounts = Transfer {
+                    from: ctx.accounts.vault_token_account.to_account_info(),
+                    to: buyer_token_account.to_account_info(),
+                    authority: escrow.to_account_info(),
+                };
+                let cpi_program = ctx.accounts.token_program.to_account_info();
+                let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+                transfer_tokens(cpi_ctx, escrow.remaining_amount()?)?;
+
+                // The seller missed the deadline, so the buyer keeps the collateral
+                release_collateral(
+                    escrow,
+                    &ctx.accounts.vault_token_account,
+                    buyer_token_account,
+                    &ctx.accounts.token_program,
+                    escrow.required_collateral,
+                )?;
+            }
+        }
+
+        escrow.released_amount = escrow.amount;
+        for milestone in escrow.milestones.iter_mut() {
+            milestone.released = true;
+        }
+        escrow.state = EscrowState::TimedOut;
+        escrow.record(TimelineEvent::TimedOut, clock.unix_timestamp)?;
+        untrack(escrow, &mut ctx.accounts.config, &mut ctx.accounts.buyer_account)?;
         
-        // Transfer funds from vault to seller (timeout favors seller)
+        msg!("Timeout resolved, funds released to the beneficiary");
+        auto_close(
+            escrow,
+            &mut ctx.accounts.vault_token_account,
+            ctx.accounts.rent_recipient.as_ref().map(|account| account.to_account_info()),
+            &ctx.accounts.token_program,
+        )
+    }
+
+    /// Keeper resolves up to `MAX_BATCH_RESOLVE` timeouts in one transaction.
+    /// `remaining_accounts` holds (escrow, vault, payout token account)
+    /// triples, where the payout account belongs to the timeout beneficiary.
+    /// Escrows that can't be resolved here (not yet expired, closed to this
+    /// resolver, fee-bearing, or with mismatched accounts) are skipped rather
+    /// than failing the batch. Returns a bitmask of the triples resolved.
+    pub fn resolve_timeouts_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ResolveTimeoutsBatch<'info>>,
+    ) -> Result<u8> {
+        require_operational(&ctx.accounts.config)?;
+        let triples = ctx.remaining_accounts.chunks_exact(3);
+        let batch_len = triples.len();
+        require!(
+            triples.remainder().is_empty() && batch_len <= MAX_BATCH_RESOLVE,
+            EscrowError::InvalidBatch
+        );
+        let clock = Clock::get()?;
+        let resolver = ctx.accounts.resolver.key();
+        let mut resolved = 0u8;
+
+        for (index, triple) in triples.enumerate() {
+            let (Ok(mut escrow), Ok(vault), Ok(payout)) = (
+                Account::<Escrow>::try_from(&triple[0]),
+                Account::<TokenAccount>::try_from(&triple[1]),
+                Account::<TokenAccount>::try_from(&triple[2]),
+            ) else {
+                continue;
+            };
+            let payee = escrow.timeout_payee(clock.unix_timestamp);
+            let payout_owner_ok = match payee {
+                TimeoutBeneficiary::Seller => escrow.is_payout_owner(&escrow.seller, &payout.owner),
+                TimeoutBeneficiary::Buyer => payout.owner == escrow.buyer,
+            };
+            // Fee-bearing escrows need their mint's fee vault, partial
+            // fulfilments and SLA penalties pay the buyer too, and escrows
+            // counted against their buyer need its `BuyerAccount`; resolve
+            // those singly
+            let eligible = !escrow.is_swap()
+                && (ctx.accounts.config.is_some() || !escrow.counted_in_config)
+                && escrow.state == EscrowState::Accepted
+                && escrow.buyer != escrow.seller
+                && clock.unix_timestamp >= escrow.release_deadline()
+                && (escrow.permissionless_timeout || resolver == escrow.buyer || resolver == escrow.seller)
+                && escrow.fee_bps == 0
+                && !escrow.counted_for_buyer
+                && matches!(escrow.unfulfilled_amount(), Ok(0))
+                && (payee == TimeoutBeneficiary::Buyer || matches!(escrow.collateral_penalty(clock.unix_timestamp), Ok(0)))
+                && escrow.held_amount().is_ok()
+                && vault.key() == escrow.vault
+                && vault.mint == escrow.mint
+                && payout.key() != vault.key()
+                && payout_owner_ok
+                && (payee == TimeoutBeneficiary::Buyer || escrow.accepts_payout_to(&payout.key()))
+                && payout.mint == escrow.mint
+                && triple.iter().all(|info| info.is_writable);
+            if !eligible {
+                continue;
+            }
+
+            // With no fee, both beneficiaries receive the remaining deposit
+            // and the seller's collateral
+            let seeds = &[
+                b"escrow",
+                escrow.seed_buyer().as_ref(),
+                escrow.mint.as_ref(),
+                escrow.seed_suffix(),
+                &[escrow.bump],
+            ];
+            let signer = &[&seeds[..]];
+            let cpi_accounts = Transfer {
+                from: vault.to_account_info(),
+                to: payout.to_account_info(),
+                authority: escrow.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            transfer_tokens(cpi_ctx, escrow.held_amount()?)?;
+
+            escrow.released_amount = escrow.amount;
+            for milestone in escrow.milestones.iter_mut() {
+                milestone.released = true;
+            }
+            escrow.state = EscrowState::TimedOut;
+            escrow.record(TimelineEvent::TimedOut, clock.unix_timestamp)?;
+            untrack(&mut escrow, &mut ctx.accounts.config, &mut None)?;
+            escrow.exit(&crate::ID)?;
+            resolved |= 1 << index;
+        }
+
+        msg!("Resolved {} of {} timeouts", resolved.count_ones(), batch_len);
+        Ok(resolved)
+    }
+
+    /// Buyer pulls back tokens sent straight to the vault on top of what the
+    /// escrow holds, leaving the escrowed balance and any seller collateral
+    pub fn recover_excess(ctx: Context<RecoverExcess>) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+
+        require!(!escrow.is_terminal(), EscrowError::InvalidState);
+        require!(escrow.buyer == ctx.accounts.buyer.key(), EscrowError::UnauthorizedBuyer);
+
+        let excess = ctx
+            .accounts
+            .vault_token_account
+            .amount
+            .saturating_sub(escrow.held_amount()?);
+        require!(excess > 0, EscrowError::NoExcessFunds);
+
         let seeds = &[
             b"escrow",
-            escrow.buyer.as_ref(),
+            escrow.seed_buyer().as_ref(),
             escrow.mint.as_ref(),
+            escrow.seed_suffix(),
             &[escrow.bump],
         ];
         let signer = &[&seeds[..]];
-        
+
         let cpi_accounts = Transfer {
             from: ctx.accounts.vault_token_account.to_account_info(),
-            to: ctx.accounts.seller_token_account.to_account_info(),
+            to: ctx.accounts.buyer_token_account.to_account_info(),
             authority: escrow.to_account_info(),
         };
         let cpi_program = ctx.accounts.token_program.to_account_info();
         let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-        token::transfer(cpi_ctx, escrow.amount)?;
+        transfer_tokens(cpi_ctx, excess)?;
 
-        escrow.state = EscrowState::TimedOut;
-        escrow.completed_at = clock.unix_timestamp;
-        
-        msg!("Timeout resolved, funds released to seller");
+        msg!("Recovered {} excess tokens from the vault", excess);
         Ok(())
     }
-}
 
-#[derive(Accounts)]
-#[instruction(amount: u64, timeout_duration: i64, description: String)]
-pub struct CreateEscrow<'info> {
-    #[account(mut)]
-    pub buyer: Signer<'info>,
-    
-    #[account(
-        init,
-        payer = buyer,
-        space = 8 + Escrow::LEN,
-        seeds = [b"escrow", buyer.key().as_ref(), mint.key().as_ref()],
-        bump
-    )]
-    pub escrow: Account<'info, Escrow>,
-    
-    pub mint: Account<'info, Mint>,
-    
-    #[account(
-        mut,
-        constraint = buyer_token_account.owner == buyer.key(),
-        constraint = buyer_token_account.mint == mint.key(),
-    )]
-    pub buyer_token_account: Account<'info, TokenAccount>,
-    
-    #[account(
-        init,
-        payer = buyer,
-        token::mint = mint,
-        token::authority = escrow,
-        seeds = [b"vault", escrow.key().as_ref()],
-        bump
-    )]
-    pub vault_token_account: Account<'info, TokenAccount>,
-    
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
-}
+    /// Writes a settled escrow's final amounts, parties and timestamps into a
+    /// `Receipt` PDA that outlives `close_escrow`. Anyone may pay for it; the
+    /// receipt is created once and never changes.
+    pub fn mint_receipt(ctx: Context<MintReceipt>) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+        require!(escrow.is_terminal(), EscrowError::InvalidState);
 
-#[derive(Accounts)]
-pub struct AcceptEscrow<'info> {
-    #[account(mut)]
-    pub seller: Signer<'info>,
-    
-    #[account(
-        mut,
-        seeds = [b"escrow", escrow.buyer.as_ref(), escrow.mint.as_ref()],
-        bump = escrow.bump
-    )]
-    pub escrow: Account<'info, Escrow>,
-}
+        let clock = Clock::get()?;
+        let receipt = &mut ctx.accounts.receipt;
+        receipt.escrow = escrow.key();
+        receipt.buyer = escrow.buyer;
+        receipt.seller = escrow.seller;
+        receipt.mint = escrow.mint;
+        receipt.amount = escrow.amount;
+        receipt.released_amount = escrow.released_amount;
+        receipt.fee_bps = escrow.fee_bps;
+        receipt.final_state = escrow.state.clone();
+        receipt.created_at = escrow.created_at();
+        receipt.accepted_at = escrow.accepted_at();
+        receipt.settled_at = escrow.completed_at().max(escrow.cancelled_at());
+        receipt.minted_at = clock.unix_timestamp;
+        receipt.bump = ctx.bumps.receipt;
 
-#[derive(Accounts)]
-pub struct ConfirmDelivery<'info> {
-    #[account(mut)]
-    pub buyer: Signer<'info>,
+        msg!("Receipt minted for escrow {}", receipt.escrow);
+        Ok(())
+    }
+
+    /// Buyer closes a finished escrow and its vault, reclaiming the rent and
+    /// freeing the PDA so the same buyer and mint can open a new escrow. Any
+    /// tokens sent to the vault after settlement are swept to the buyer first.
+    pub fn close_escrow(ctx: Context<CloseEscrow>) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.is_terminal(), EscrowError::InvalidState);
+        require!(escrow.buyer == ctx.accounts.buyer.key(), EscrowError::UnauthorizedBuyer);
+
+        // Rent goes back to whoever paid it: a sponsor if one reimbursed the
+        // buyer, otherwise the buyer
+        let rent_destination = if escrow.rent_payer == Pubkey::default() {
+            ctx.accounts.buyer.to_account_info()
+        } else {
+            let rent_payer = ctx
+                .accounts
+                .rent_payer
+                .as_ref()
+                .ok_or(EscrowError::RentPayerMismatch)?;
+            require_keys_eq!(rent_payer.key(), escrow.rent_payer, EscrowError::RentPayerMismatch);
+            rent_payer.to_account_info()
+        };
+
+        let seeds = &[
+            b"escrow",
+            escrow.seed_buyer().as_ref(),
+            escrow.mint.as_ref(),
+            escrow.seed_suffix(),
+            &[escrow.bump],
+        ];
+        let signer = &[&seeds[..]];
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+
+        let residual = ctx.accounts.vault_token_account.amount;
+        if residual > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.vault_token_account.to_account_info(),
+                to: ctx.accounts.buyer_token_account.to_account_info(),
+                authority: escrow.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program.clone(), cpi_accounts, signer);
+            transfer_tokens(cpi_ctx, residual)?;
+        }
+
+        let cpi_accounts = CloseAccount {
+            account: ctx.accounts.vault_token_account.to_account_info(),
+            destination: rent_destination.clone(),
+            authority: escrow.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::close_account(cpi_ctx)?;
+        escrow.close(rent_destination)?;
+
+        msg!("Escrow closed");
+        Ok(())
+    }
+
+    /// A relayer reimburses the buyer for the rent of an unaccepted escrow.
+    /// This covers whatever the escrow and vault accounts hold, for
+    /// deployments that subsidize rent. The relayer is recorded as
+    /// `rent_payer`, and `close_escrow` returns the rent to them instead of
+    /// the buyer.
+    pub fn sponsor_rent(ctx: Context<SponsorRent>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.state == EscrowState::Created, EscrowError::InvalidState);
+        require!(escrow.rent_payer == Pubkey::default(), EscrowError::RentAlreadySponsored);
+
+        let rent = escrow
+            .to_account_info()
+            .lamports()
+            .checked_add(ctx.accounts.vault_token_account.to_account_info().lamports())
+            .ok_or(EscrowError::ArithmeticOverflow)?;
+        let cpi_accounts = anchor_lang::system_program::Transfer {
+            from: ctx.accounts.sponsor.to_account_info(),
+            to: ctx.accounts.buyer.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.system_program.to_account_info(), cpi_accounts);
+        anchor_lang::system_program::transfer(cpi_ctx, rent)?;
+
+        escrow.rent_payer = ctx.accounts.sponsor.key();
+
+        msg!("Rent of {} lamports sponsored by {}", rent, escrow.rent_payer);
+        Ok(())
+    }
+
+    /// Buyer moves `new_amount` of an unaccepted escrow into a new escrow with
+    /// the same terms. The new escrow's PDA is
+    /// `[b"escrow", buyer, mint, parent_escrow]` and its vault is the usual
+    /// `[b"vault", new_escrow]`; the parent PDA signs the transfer between the
+    /// two vaults. Each escrow can be split once; split the new escrow to
+    /// divide further.
+    pub fn split_escrow(ctx: Context<SplitEscrow>, new_amount: u64) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.state == EscrowState::Created, EscrowError::InvalidState);
+        require!(escrow.buyer == ctx.accounts.buyer.key(), EscrowError::UnauthorizedBuyer);
+        require!(escrow.milestones.is_empty(), EscrowError::InvalidMilestone);
+        require!(
+            new_amount > 0 && new_amount < escrow.amount,
+            EscrowError::InvalidSplitAmount
+        );
+        require!(escrow.unfunded_amount == 0, EscrowError::NotFullyFunded);
+        require_operational_for(&ctx.accounts.config, escrow)?;
+        require!(
+            ctx.accounts.buyer_account.is_some() || !escrow.counted_for_buyer,
+            EscrowError::BuyerAccountRequired
+        );
+
+        let seeds = &[
+            b"escrow",
+            escrow.seed_buyer().as_ref(),
+            escrow.mint.as_ref(),
+            escrow.seed_suffix(),
+            &[escrow.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.vault_token_account.to_account_info(),
+            to: ctx.accounts.new_vault_token_account.to_account_info(),
+            authority: escrow.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        transfer_tokens(cpi_ctx, new_amount)?;
+
+        // Collateral requirement follows the amount
+        let child_collateral =
+            (escrow.required_collateral as u128 * new_amount as u128 / escrow.amount as u128) as u64;
+        escrow.amount = escrow
+            .amount
+            .checked_sub(new_amount)
+            .ok_or(EscrowError::ArithmeticOverflow)?;
+        escrow.required_collateral = escrow
+            .required_collateral
+            .checked_sub(child_collateral)
+            .ok_or(EscrowError::ArithmeticOverflow)?;
+
+        let clock = Clock::get()?;
+        let mut child = Escrow::clone(escrow);
+        child.amount = new_amount;
+        child.required_collateral = child_collateral;
+        child.parent = escrow.key();
+        child.bump = ctx.bumps.new_escrow;
+        child.vault = ctx.accounts.new_vault_token_account.key();
+        child.counted_in_config = false;
+        child.counted_for_buyer = false;
+        // The buyer pays the child's rent, whoever sponsored the parent's
+        child.rent_payer = Pubkey::default();
+        child.confirm_nonce = clock.slot;
+        child.timeline = Vec::new();
+        child.record(TimelineEvent::Created, clock.unix_timestamp)?;
+        // The child is one more active escrow for the buyer, so splitting
+        // can't get around `max_active_per_buyer`
+        track(&mut child, &mut ctx.accounts.config, &mut ctx.accounts.buyer_account)?;
+        ctx.accounts.new_escrow.set_inner(child);
+
+        msg!("Split {} tokens into escrow {}", new_amount, ctx.accounts.new_escrow.key());
+        Ok(())
+    }
+
+    /// Buyer folds an unaccepted `source_escrow` into `escrow`: the source
+    /// vault is drained into the escrow's vault, the amounts and collateral
+    /// requirements add up, and the source escrow and vault are closed with
+    /// their rent returned to the buyer. Both escrows must have the same
+    /// buyer, depositor, mint and terms; the escrow keeps its own timeout,
+    /// deadlines and description.
+    pub fn merge_escrows(ctx: Context<MergeEscrows>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let source = &mut ctx.accounts.source_escrow;
+
+        require!(!escrow.is_swap() && !source.is_swap(), EscrowError::WrongEscrowKind);
+        require!(
+            escrow.state == EscrowState::Created && source.state == EscrowState::Created,
+            EscrowError::InvalidState
+        );
+        require!(escrow.buyer == ctx.accounts.buyer.key(), EscrowError::UnauthorizedBuyer);
+        require!(escrow.can_merge(source), EscrowError::IncompatibleEscrows);
+        // Closing the source refunds its rent to the buyer, not a sponsor
+        require!(source.rent_payer == Pubkey::default(), EscrowError::RentAlreadySponsored);
+        require!(
+            escrow.unfunded_amount == 0 && source.unfunded_amount == 0,
+            EscrowError::NotFullyFunded
+        );
+        let amount = escrow
+            .amount
+            .checked_add(source.amount)
+            .ok_or(EscrowError::IncompatibleEscrows)?;
+
+        let seeds = &[
+            b"escrow",
+            source.seed_buyer().as_ref(),
+            source.mint.as_ref(),
+            source.seed_suffix(),
+            &[source.bump],
+        ];
+        let signer = &[&seeds[..]];
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+
+        // Sweep the whole balance so the vault can close; anything beyond
+        // the source amount lands as excess the buyer can recover
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.source_vault_token_account.to_account_info(),
+            to: ctx.accounts.vault_token_account.to_account_info(),
+            authority: source.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program.clone(), cpi_accounts, signer);
+        transfer_tokens(cpi_ctx, ctx.accounts.source_vault_token_account.amount)?;
+
+        let cpi_accounts = CloseAccount {
+            account: ctx.accounts.source_vault_token_account.to_account_info(),
+            destination: ctx.accounts.buyer.to_account_info(),
+            authority: source.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::close_account(cpi_ctx)?;
+
+        escrow.amount = amount;
+        escrow.required_collateral = escrow
+            .required_collateral
+            .checked_add(source.required_collateral)
+            .ok_or(EscrowError::ArithmeticOverflow)?;
+        untrack(source, &mut ctx.accounts.config, &mut ctx.accounts.buyer_account)?;
+
+        msg!("Merged escrow {} into {}, amount is now {}", source.key(), escrow.key(), amount);
+        Ok(())
+    }
+
+    /// Buyer replaces the description of an unaccepted escrow, up to
+    /// `MAX_EXPANDED_DESCRIPTION_LEN` bytes. The account is reallocated to fit:
+    /// the buyer pays the extra rent and the added bytes start zeroed.
+    pub fn expand_description(ctx: Context<ExpandDescription>, description: String) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.state == EscrowState::Created, EscrowError::InvalidState);
+        require!(escrow.buyer == ctx.accounts.buyer.key(), EscrowError::UnauthorizedBuyer);
+        require!(
+            description.len() <= Escrow::MAX_EXPANDED_DESCRIPTION_LEN,
+            EscrowError::DescriptionTooLong
+        );
+
+        escrow.description = description;
+
+        msg!("Description expanded to {} bytes", escrow.description.len());
+        Ok(())
+    }
+
+    /// Buyer deposits more into an unaccepted escrow. A deferred-funding
+    /// escrow's deposits first fill its unfunded part; anything beyond that
+    /// raises the amount.
+    pub fn deposit_additional(ctx: Context<AdjustDeposit>, amount: u64) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        require_adjustable(escrow, &ctx.accounts.buyer.key())?;
+        require!(amount > 0, EscrowError::InvalidAdjustment);
+        let filled = amount.min(escrow.unfunded_amount);
+        let new_amount = escrow
+            .amount
+            .checked_add(amount - filled)
+            .ok_or(EscrowError::InvalidAdjustment)?;
+        require_min_collateral(&ctx.accounts.config, escrow, new_amount)?;
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.buyer_token_account.to_account_info(),
+            to: ctx.accounts.vault_token_account.to_account_info(),
+            authority: ctx.accounts.buyer.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        transfer_tokens(cpi_ctx, amount)?;
+        escrow.amount = new_amount;
+        escrow.unfunded_amount -= filled;
+
+        msg!("Deposited {} more, escrow amount is now {}", amount, new_amount);
+        if escrow.unfunded_amount > 0 {
+            msg!("{} still to deposit before a seller may accept", escrow.unfunded_amount);
+        }
+        Ok(())
+    }
+
+    /// Buyer lowers the amount of an unaccepted escrow, withdrawing the
+    /// difference to the depositor. The amount cannot drop to zero; cancel
+    /// the escrow instead.
+    pub fn withdraw_partial(ctx: Context<AdjustDeposit>, amount: u64) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        require_adjustable(escrow, &ctx.accounts.buyer.key())?;
+        require!(
+            amount > 0 && amount < escrow.amount && amount <= escrow.held_amount()?,
+            EscrowError::InvalidAdjustment
+        );
+        require!(
+            ctx.accounts.buyer_token_account.owner == escrow.depositor(),
+            EscrowError::RefundAccountMismatch
+        );
+
+        let seeds = &[
+            b"escrow",
+            escrow.seed_buyer().as_ref(),
+            escrow.mint.as_ref(),
+            escrow.seed_suffix(),
+            &[escrow.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.vault_token_account.to_account_info(),
+            to: ctx.accounts.buyer_token_account.to_account_info(),
+            authority: escrow.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        transfer_tokens(cpi_ctx, amount)?;
+        escrow.amount = escrow
+            .amount
+            .checked_sub(amount)
+            .ok_or(EscrowError::ArithmeticOverflow)?;
+
+        msg!("Withdrew {}, escrow amount is now {}", amount, escrow.amount);
+        Ok(())
+    }
+
+    /// Buyer swaps an unaccepted escrow's amount, timeout and description
+    /// for new ones in one step, as if cancelled and created again. The PDA
+    /// depends only on buyer, mint and parent, never on the terms, so the
+    /// replacement would land at the same address and vault; closing and
+    /// re-initializing an account can't both happen in one transaction, so
+    /// the terms are rewritten in place and only the difference in amount
+    /// moves, from or to `buyer_token_account`. The deposit never leaves the
+    /// vault. The timeline restarts now, and the timeout, extension cap and
+    /// delivery deadline are re-based on the new start.
+    pub fn replace_escrow(
+        ctx: Context<AdjustDeposit>,
+        amount: u64,
+        timeout_duration: i64,
+        description: String,
+    ) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let clock = Clock::get()?;
+
+        require_adjustable(escrow, &ctx.accounts.buyer.key())?;
+        require!(escrow.unfunded_amount == 0, EscrowError::NotFullyFunded);
+        require!(amount > 0, EscrowError::InvalidAdjustment);
+        require!(
+            description.len() <= Escrow::MAX_DESCRIPTION_LEN,
+            EscrowError::DescriptionTooLong
+        );
+        require_min_collateral(&ctx.accounts.config, escrow, amount)?;
+
+        if amount > escrow.amount {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.buyer_token_account.to_account_info(),
+                to: ctx.accounts.vault_token_account.to_account_info(),
+                authority: ctx.accounts.buyer.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+            transfer_tokens(cpi_ctx, amount - escrow.amount)?;
+        } else if amount < escrow.amount {
+            require!(
+                ctx.accounts.buyer_token_account.owner == escrow.depositor(),
+                EscrowError::RefundAccountMismatch
+            );
+            let seeds = &[
+                b"escrow",
+                escrow.seed_buyer().as_ref(),
+                escrow.mint.as_ref(),
+                escrow.seed_suffix(),
+                &[escrow.bump],
+            ];
+            let signer = &[&seeds[..]];
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.vault_token_account.to_account_info(),
+                to: ctx.accounts.buyer_token_account.to_account_info(),
+                authority: escrow.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer);
+            transfer_tokens(cpi_ctx, escrow.amount - amount)?;
+        }
+
+        // Keep the delivery window and any future scheduled start; escrows
+        // from before `start_at` existed started at creation
+        let old_start = escrow.start_at.max(escrow.created_at());
+        let delivery_window = if escrow.delivery_deadline > 0 {
+            escrow
+                .delivery_deadline
+                .checked_sub(old_start)
+                .ok_or(EscrowError::ArithmeticOverflow)?
+        } else {
+            0
+        };
+        let start_at = escrow.start_at.max(clock.unix_timestamp);
+
+        escrow.amount = amount;
+        escrow.description = description;
+        escrow.timeline.clear();
+        escrow.record(TimelineEvent::Created, clock.unix_timestamp)?;
+        escrow.warned = false;
+        escrow.start_at = start_at;
+        escrow.timeout_at = start_at
+            .checked_add(timeout_duration)
+            .ok_or(EscrowError::ArithmeticOverflow)?;
+        escrow.max_timeout_at = escrow.timeout_at.max(
+            start_at
+                .checked_add(Escrow::MAX_TOTAL_DURATION)
+                .ok_or(EscrowError::ArithmeticOverflow)?,
+        );
+        if delivery_window > 0 {
+            escrow.delivery_deadline = start_at
+                .checked_add(delivery_window)
+                .ok_or(EscrowError::ArithmeticOverflow)?;
+        }
+
+        msg!("Escrow replaced: amount {}, timeout at {}", amount, escrow.timeout_at);
+        Ok(())
+    }
+
+    /// Buyer pushes the deadline back by `extension` seconds, never past
+    /// `max_timeout_at`
+    pub fn extend_timeout(ctx: Context<ExtendTimeout>, extension: i64) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        require!(
+            escrow.state == EscrowState::Created || escrow.state == EscrowState::Accepted,
+            EscrowError::InvalidState
+        );
+        require!(escrow.buyer == ctx.accounts.buyer.key(), EscrowError::UnauthorizedBuyer);
+        require!(extension > 0, EscrowError::InvalidExtension);
+
+        let new_timeout_at = escrow
+            .timeout_at
+            .checked_add(extension)
+            .ok_or(EscrowError::ExtensionCapExceeded)?;
+        require!(
+            new_timeout_at <= escrow.max_timeout_at,
+            EscrowError::ExtensionCapExceeded
+        );
+        escrow.timeout_at = new_timeout_at;
+        escrow.buyer_active_at = escrow.now(&Clock::get()?);
+
+        msg!("Timeout extended to {}", new_timeout_at);
+        Ok(())
+    }
+
+    /// Buyer assigns every buyer right (confirming, cancelling, refunds,
+    /// disputes, closing) to `new_buyer`, e.g. after selling the position.
+    /// The new buyer co-signs, so payment for the claim can move atomically
+    /// in the same transaction. The escrow keeps its address: its PDA stays
+    /// derived from the buyer it was created by.
+    pub fn transfer_escrow_rights(ctx: Context<TransferEscrowRights>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let new_buyer = ctx.accounts.new_buyer.key();
+
+        require!(!escrow.is_terminal(), EscrowError::InvalidState);
+        require!(escrow.buyer == ctx.accounts.buyer.key(), EscrowError::UnauthorizedBuyer);
+        require!(
+            new_buyer != escrow.buyer && new_buyer != escrow.seller,
+            EscrowError::InvalidNewBuyer
+        );
+
+        escrow.original_buyer = *escrow.seed_buyer();
+        escrow.buyer = new_buyer;
+        escrow.buyer_active_at = escrow.now(&Clock::get()?);
+
+        msg!("Buyer rights transferred to {}", new_buyer);
+        Ok(())
+    }
+
+    /// Read-only check of whether `seller` could accept right now, using the
+    /// same rules as `accept_escrow`/`accept_swap`. Nothing is mutated.
+    pub fn check_can_accept(ctx: Context<CheckCanAccept>, seller: Pubkey) -> Result<AcceptCheck> {
+        let clock = Clock::get()?;
+        let check = match ctx.accounts.escrow.can_accept(&seller, clock.unix_timestamp) {
+            Ok(()) => AcceptCheck { allowed: true, reason: 0 },
+            Err(err) => AcceptCheck { allowed: false, reason: err.into() },
+        };
+        Ok(check)
+    }
+
+    /// Read-only snapshot of the escrow's lifecycle timestamps and state, so
+    /// clients get a stable typed view independent of the account layout
+    pub fn get_timeline(ctx: Context<GetTimeline>) -> Result<Timeline> {
+        let escrow = &ctx.accounts.escrow;
+        Ok(Timeline {
+            state: escrow.state.clone(),
+            created_at: escrow.created_at(),
+            accepted_at: escrow.accepted_at(),
+            disputed_at: escrow.disputed_at(),
+            completed_at: escrow.completed_at(),
+            cancelled_at: escrow.cancelled_at(),
+            timeout_at: escrow.timeout_at,
+            max_timeout_at: escrow.max_timeout_at,
+            confirmation_requested_at: escrow.confirmation_requested_at,
+            release_deadline: escrow.release_deadline(),
+        })
+    }
+
+    /// Read-only view of the vault's live balance next to the escrow's own
+    /// accounting, for reconciliation in a single call
+    pub fn get_vault_balance(ctx: Context<GetVaultBalance>) -> Result<VaultBalance> {
+        let escrow = &ctx.accounts.escrow;
+        Ok(VaultBalance {
+            vault_balance: ctx.accounts.vault_token_account.amount,
+            amount: escrow.amount,
+            released_amount: escrow.released_amount,
+        })
+    }
+
+    /// Read-only preview of where the vault's tokens go if the buyer confirms
+    /// now, line by line, from the same math `confirm_delivery` settles with.
+    /// Nothing moves.
+    pub fn preview_settlement(ctx: Context<PreviewSettlement>) -> Result<SettlementBreakdown> {
+        let escrow = &ctx.accounts.escrow;
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.state == EscrowState::Accepted, EscrowError::InvalidState);
+        Ok(escrow.settlement_breakdown(Clock::get()?.unix_timestamp)?)
+    }
+
+    /// Buyer releases a single milestone's amount to the seller. Milestones
+    /// may be released in any order; the last one completes the escrow.
+    pub fn release_milestone(ctx: Context<ReleaseMilestone>, index: u8) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        require_operational_for(&ctx.accounts.config, escrow)?;
+        require!(escrow.state == EscrowState::Accepted, EscrowError::InvalidState);
+        require!(escrow.buyer == ctx.accounts.buyer.key(), EscrowError::UnauthorizedBuyer);
+
+        let index = index as usize;
+        require!(index < escrow.milestones.len(), EscrowError::InvalidMilestone);
+        require!(!escrow.milestones[index].released, EscrowError::MilestoneAlreadyReleased);
+        let amount = escrow.milestones[index].amount;
+        warn_if_expiring(escrow, Clock::get()?.unix_timestamp);
+
+        pay_seller(
+            escrow,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.seller_token_account,
+            ctx.accounts.fee_vault.as_deref(),
+            ctx.accounts.referrer_token_account.as_deref(),
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        escrow.milestones[index].released = true;
+        escrow.released_amount = escrow
+            .released_amount
+            .checked_add(amount)
+            .ok_or(EscrowError::ArithmeticOverflow)?;
+        escrow.buyer_active_at = escrow.now(&Clock::get()?);
+
+        if escrow.milestones.iter().all(|m| m.released) {
+            return_collateral(
+                escrow,
+                &ctx.accounts.vault_token_account,
+                &ctx.accounts.seller_token_account,
+                ctx.accounts.buyer_token_account.as_deref(),
+                &ctx.accounts.token_program,
+                Clock::get()?.unix_timestamp,
+            )?;
+            escrow.state = EscrowState::Completed;
+            let clock = Clock::get()?;
+            escrow.record(TimelineEvent::Completed, clock.unix_timestamp)?;
+            untrack(escrow, &mut ctx.accounts.config, &mut ctx.accounts.buyer_account)?;
+        }
+
+        msg!(
+            "Milestone {} released: {} tokens to seller",
+            escrow.milestones[index].label,
+            amount
+        );
+        Ok(())
+    }
+
+    /// Seller asks the buyer to confirm delivery. If the escrow has an
+    /// auto-confirm window, `resolve_timeout` may release once it elapses.
+    pub fn request_confirmation(ctx: Context<RequestConfirmation>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let clock = Clock::get()?;
+
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.state == EscrowState::Accepted, EscrowError::InvalidState);
+        require!(escrow.seller == ctx.accounts.seller.key(), EscrowError::UnauthorizedSeller);
+
+        escrow.confirmation_requested_at = escrow.now(&clock);
+        warn_if_expiring(escrow, clock.unix_timestamp);
+
+        emit!(ConfirmationRequested {
+            schema_version: EVENT_SCHEMA_VERSION,
+            escrow: escrow.key(),
+            buyer: escrow.buyer,
+            seller: escrow.seller,
+            release_deadline: escrow.release_deadline(),
+        });
+
+        msg!("Seller requested delivery confirmation");
+        Ok(())
+    }
+
+    /// Seller records that they delivered, which stops the buyer from
+    /// claiming non-delivery once the delivery deadline passes
+    pub fn mark_delivered(ctx: Context<MarkDelivered>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let clock = Clock::get()?;
+
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.state == EscrowState::Accepted, EscrowError::InvalidState);
+        require!(escrow.seller == ctx.accounts.seller.key(), EscrowError::UnauthorizedSeller);
+        require!(escrow.delivered_at == 0, EscrowError::InvalidState);
+
+        escrow.delivered_at = escrow.now(&clock);
+
+        msg!("Seller marked the escrow delivered");
+        Ok(())
+    }
+
+    /// Seller delivers a digital good on-chain: an encrypted key or other
+    /// blob, or just its hash. Counts as `mark_delivered` and can only be
+    /// submitted once.
+    pub fn deliver_secret(ctx: Context<MarkDelivered>, payload: Vec<u8>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let clock = Clock::get()?;
+
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.state == EscrowState::Accepted, EscrowError::InvalidState);
+        require!(escrow.seller == ctx.accounts.seller.key(), EscrowError::UnauthorizedSeller);
+        require!(escrow.delivery_payload.is_empty(), EscrowError::InvalidState);
+        require!(
+            !payload.is_empty() && payload.len() <= Escrow::MAX_DELIVERY_PAYLOAD_LEN,
+            EscrowError::InvalidDeliveryPayload
+        );
+
+        escrow.delivery_payload = payload;
+        if escrow.delivered_at == 0 {
+            escrow.delivered_at = escrow.now(&clock);
+        }
+
+        msg!("Seller delivered a {}-byte payload", escrow.delivery_payload.len());
+        Ok(())
+    }
+
+    /// Seller commits to a physical shipment, typically the hash of its
+    /// tracking number and a salt. Counts as `mark_delivered`. Every
+    /// commitment is recorded in a `[b"commitment", commitment]` PDA the
+    /// seller pays for, so one tracking hash can't prove delivery on two
+    /// escrows.
+    pub fn submit_shipment(ctx: Context<SubmitShipment>, commitment: [u8; 32]) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let clock = Clock::get()?;
+
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.state == EscrowState::Accepted, EscrowError::InvalidState);
+        require!(escrow.seller == ctx.accounts.seller.key(), EscrowError::UnauthorizedSeller);
+        require!(escrow.shipment_commitment == [0u8; 32], EscrowError::InvalidState);
+        require!(commitment != [0u8; 32], EscrowError::InvalidCommitment);
+
+        let record = ctx.accounts.commitment_record.to_account_info();
+        require!(record.data_is_empty(), EscrowError::CommitmentReused);
+        let space = 8 + CommitmentRecord::LEN;
+        let bump = ctx.bumps.commitment_record;
+        let seeds = &[b"commitment".as_ref(), commitment.as_ref(), &[bump]];
+        let signer = &[&seeds[..]];
+        let cpi_accounts = anchor_lang::system_program::CreateAccount {
+            from: ctx.accounts.seller.to_account_info(),
+            to: record.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            cpi_accounts,
+            signer,
+        );
+        anchor_lang::system_program::create_account(
+            cpi_ctx,
+            Rent::get()?.minimum_balance(space),
+            space as u64,
+            &crate::ID,
+        )?;
+        CommitmentRecord { escrow: escrow.key(), bump }
+            .try_serialize(&mut &mut record.try_borrow_mut_data()?[..])?;
+
+        escrow.shipment_commitment = commitment;
+        if escrow.delivered_at == 0 {
+            escrow.delivered_at = escrow.now(&clock);
+        }
+
+        msg!("Seller submitted a shipment commitment");
+        Ok(())
+    }
+
+    /// Buyer refunds themselves when the seller accepted but never marked
+    /// delivery by `delivery_deadline`. The buyer keeps any seller collateral,
+    /// as on a buyer-favoring timeout.
+    pub fn claim_non_delivery(ctx: Context<ClaimNonDelivery>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let clock = Clock::get()?;
+
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.state == EscrowState::Accepted, EscrowError::InvalidState);
+        require!(escrow.buyer == ctx.accounts.buyer.key(), EscrowError::UnauthorizedBuyer);
+        require!(
+            escrow.delivery_deadline > 0 && escrow.delivered_at == 0,
+            EscrowError::InvalidState
+        );
+        require!(
+            clock.unix_timestamp >= escrow.delivery_deadline,
+            EscrowError::DeliveryDeadlineNotPassed
+        );
+
+        let seeds = &[
+            b"escrow",
+            escrow.seed_buyer().as_ref(),
+            escrow.mint.as_ref(),
+            escrow.seed_suffix(),
+            &[escrow.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.vault_token_account.to_account_info(),
+            to: ctx.accounts.buyer_token_account.to_account_info(),
+            authority: escrow.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        transfer_tokens(cpi_ctx, escrow.held_amount()?)?;
+
+        escrow.released_amount = escrow.amount;
+        for milestone in escrow.milestones.iter_mut() {
+            milestone.released = true;
+        }
+        escrow.state = EscrowState::TimedOut;
+        escrow.record(TimelineEvent::TimedOut, clock.unix_timestamp)?;
+        untrack(escrow, &mut ctx.accounts.config, &mut ctx.accounts.buyer_account)?;
+
+        msg!("Seller missed the delivery deadline, buyer refunded");
+        Ok(())
+    }
+
+    /// Seller proves they're still engaged with an escrow created with a
+    /// `heartbeat_interval`. Each heartbeat must come within an interval of
+    /// the last, or of acceptance; a late one fails, as the buyer may
+    /// already `claim_abandoned`.
+    pub fn heartbeat(ctx: Context<Heartbeat>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let clock = Clock::get()?;
+
+        require!(escrow.seller == ctx.accounts.seller.key(), EscrowError::UnauthorizedSeller);
+        let due_at = escrow.heartbeat_due_at()?.ok_or(EscrowError::InvalidState)?;
+        require!(clock.unix_timestamp <= due_at, EscrowError::HeartbeatMissed);
+
+        escrow.last_heartbeat_at = escrow.now(&clock);
+
+        msg!("Seller heartbeat, next due by {}", escrow.heartbeat_due_at()?.unwrap_or_default());
+        Ok(())
+    }
+
+    /// Buyer refunds themselves when the seller missed a heartbeat before
+    /// marking delivery. Like `claim_non_delivery`, the buyer keeps any
+    /// seller collateral.
+    pub fn claim_abandoned(ctx: Context<ClaimNonDelivery>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let clock = Clock::get()?;
+
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.buyer == ctx.accounts.buyer.key(), EscrowError::UnauthorizedBuyer);
+        let due_at = escrow.heartbeat_due_at()?.ok_or(EscrowError::InvalidState)?;
+        require!(clock.unix_timestamp > due_at, EscrowError::HeartbeatNotMissed);
+
+        let seeds = &[
+            b"escrow",
+            escrow.seed_buyer().as_ref(),
+            escrow.mint.as_ref(),
+            escrow.seed_suffix(),
+            &[escrow.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.vault_token_account.to_account_info(),
+            to: ctx.accounts.buyer_token_account.to_account_info(),
+            authority: escrow.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        transfer_tokens(cpi_ctx, escrow.held_amount()?)?;
+
+        escrow.released_amount = escrow.amount;
+        for milestone in escrow.milestones.iter_mut() {
+            milestone.released = true;
+        }
+        escrow.state = EscrowState::TimedOut;
+        escrow.record(TimelineEvent::TimedOut, clock.unix_timestamp)?;
+        untrack(escrow, &mut ctx.accounts.config, &mut ctx.accounts.buyer_account)?;
+
+        msg!("Seller missed a heartbeat, buyer refunded");
+        Ok(())
+    }
+
+    /// Seller settles a delivered escrow as if confirmed once a buyer with a
+    /// `buyer_inactivity_timeout` has gone quiet for that long after the
+    /// delivery. Unlike `resolve_timeout`, it needs the delivery marked first.
+    pub fn claim_inactive_buyer(ctx: Context<ClaimInactiveBuyer>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let clock = Clock::get()?;
+
+        require_operational_for(&ctx.accounts.config, escrow)?;
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.seller == ctx.accounts.seller.key(), EscrowError::UnauthorizedSeller);
+        let inactive_at = escrow.buyer_inactive_at()?.ok_or(EscrowError::InvalidState)?;
+        require!(clock.unix_timestamp >= inactive_at, EscrowError::BuyerNotInactive);
+
+        return_collateral(
+            escrow,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.seller_token_account,
+            ctx.accounts.buyer_token_account.as_deref(),
+            &ctx.accounts.token_program,
+            clock.unix_timestamp,
+        )?;
+        refund_unfulfilled(
+            escrow,
+            &ctx.accounts.vault_token_account,
+            ctx.accounts.buyer_token_account.as_deref(),
+            &ctx.accounts.token_program,
+        )?;
+        if escrow.vesting_duration > 0 {
+            start_vesting(escrow)?;
+            return Ok(());
+        }
+        pay_seller(
+            escrow,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.seller_token_account,
+            ctx.accounts.fee_vault.as_deref(),
+            ctx.accounts.referrer_token_account.as_deref(),
+            &ctx.accounts.token_program,
+            escrow.remaining_amount()?,
+        )?;
+
+        escrow.released_amount = escrow.amount;
+        for milestone in escrow.milestones.iter_mut() {
+            milestone.released = true;
+        }
+        escrow.state = EscrowState::Completed;
+        escrow.record(TimelineEvent::Completed, clock.unix_timestamp)?;
+        untrack(escrow, &mut ctx.accounts.config, &mut ctx.accounts.buyer_account)?;
+
+        msg!("Buyer inactive since delivery, funds released to seller");
+        Ok(())
+    }
+
+    /// Buyer starts the clock on leaving an accepted escrow created with a
+    /// `withdraw_delay`. The seller has until the delay passes to contest by
+    /// marking delivery or opening a dispute.
+    pub fn request_withdrawal(ctx: Context<RequestWithdrawal>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let clock = Clock::get()?;
+
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.state == EscrowState::Accepted, EscrowError::InvalidState);
+        require!(escrow.buyer == ctx.accounts.buyer.key(), EscrowError::UnauthorizedBuyer);
+        require!(
+            escrow.withdraw_delay > 0 && escrow.withdrawal_requested_at == 0,
+            EscrowError::InvalidState
+        );
+        // Once delivered, the buyer's recourse is a dispute
+        require!(escrow.delivered_at == 0, EscrowError::WithdrawalContested);
+
+        escrow.withdrawal_requested_at = escrow.now(&clock);
+        escrow.buyer_active_at = escrow.withdrawal_requested_at;
+
+        msg!(
+            "Buyer requested withdrawal, executable from {}",
+            escrow.withdrawal_executable_at()?.unwrap_or_default()
+        );
+        Ok(())
+    }
+
+    /// Buyer takes back the deposit once their withdrawal request has gone
+    /// uncontested for the whole `withdraw_delay`. The seller's collateral
+    /// goes back to them, as nothing was found against them.
+    pub fn execute_withdrawal(ctx: Context<ExecuteWithdrawal>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let clock = Clock::get()?;
+
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.buyer == ctx.accounts.buyer.key(), EscrowError::UnauthorizedBuyer);
+        require!(escrow.withdrawal_requested_at > 0, EscrowError::InvalidState);
+        let executable_at = escrow
+            .withdrawal_executable_at()?
+            .ok_or(EscrowError::WithdrawalContested)?;
+        require!(clock.unix_timestamp >= executable_at, EscrowError::WithdrawDelayNotElapsed);
+
+        if escrow.required_collateral > 0 {
+            let seller_token_account = ctx
+                .accounts
+                .seller_token_account
+                .as_deref()
+                .ok_or(EscrowError::SellerTokenAccountRequired)?;
+            release_collateral(
+                escrow,
+                &ctx.accounts.vault_token_account,
+                seller_token_account,
+                &ctx.accounts.token_program,
+                escrow.required_collateral,
+            )?;
+        }
+
+        let seeds = &[
+            b"escrow",
+            escrow.seed_buyer().as_ref(),
+            escrow.mint.as_ref(),
+            escrow.seed_suffix(),
+            &[escrow.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.vault_token_account.to_account_info(),
+            to: ctx.accounts.buyer_token_account.to_account_info(),
+            authority: escrow.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        transfer_tokens(cpi_ctx, escrow.remaining_amount()?)?;
+
+        escrow.released_amount = escrow.amount;
+        for milestone in escrow.milestones.iter_mut() {
+            milestone.released = true;
+        }
+        escrow.state = EscrowState::Cancelled;
+        escrow.record(TimelineEvent::Cancelled, clock.unix_timestamp)?;
+        untrack(escrow, &mut ctx.accounts.config, &mut ctx.accounts.buyer_account)?;
+
+        msg!("Uncontested withdrawal executed, buyer refunded");
+        Ok(())
+    }
+
+    /// Buyer or seller escalates an accepted escrow to its arbiter
+    pub fn open_dispute(ctx: Context<OpenDispute>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let caller = ctx.accounts.party.key();
+
+        require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.state == EscrowState::Accepted, EscrowError::InvalidState);
+        require!(escrow.arbiter != Pubkey::default(), EscrowError::DisputesDisabled);
+        require!(
+            caller == escrow.buyer || caller == escrow.seller,
+            EscrowError::NotAParty
+        );
+
+        let clock = Clock::get()?;
+        warn_if_expiring(escrow, clock.unix_timestamp);
+        escrow.state = EscrowState::Disputed;
+        escrow.record(TimelineEvent::Disputed, clock.unix_timestamp)?;
+
+        msg!("Dispute opened by {}", caller);
+        Ok(())
+    }
+
+    /// Arbiter asks the parties for more before deciding a dispute. While it
+    /// stands, nothing but the arbiter's own `resolve_dispute` settles the
+    /// escrow; a `submit_evidence` answers it.
+    pub fn request_evidence(ctx: Context<RequestEvidence>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        require!(escrow.state == EscrowState::Disputed, EscrowError::InvalidState);
+        require!(escrow.arbiter == ctx.accounts.arbiter.key(), EscrowError::UnauthorizedArbiter);
+
+        escrow.evidence_requested = true;
+
+        emit!(EvidenceRequested {
+            schema_version: EVENT_SCHEMA_VERSION,
+            escrow: escrow.key(),
+            buyer: escrow.buyer,
+            seller: escrow.seller,
+            arbiter: escrow.arbiter,
+        });
+
+        msg!("Arbiter requested evidence");
+        Ok(())
+    }
+
+    /// Buyer or seller points the arbiter at their evidence, e.g. an IPFS or
+    /// Arweave URI, replacing any submitted before and answering a pending
+    /// `request_evidence`
+    pub fn submit_evidence(ctx: Context<OpenDispute>, evidence_uri: String) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let caller = ctx.accounts.party.key();
+
+        require!(escrow.state == EscrowState::Disputed, EscrowError::InvalidState);
+        require!(
+            caller == escrow.buyer || caller == escrow.seller,
+            EscrowError::NotAParty
+        );
+        require!(
+            !evidence_uri.is_empty() && evidence_uri.len() <= Escrow::MAX_EVIDENCE_URI_LEN,
+            EscrowError::InvalidEvidenceUri
+        );
+
+        escrow.evidence_requested = false;
+        escrow.evidence_uri = evidence_uri;
+
+        emit!(EvidenceSubmitted {
+            schema_version: EVENT_SCHEMA_VERSION,
+            escrow: escrow.key(),
+            buyer: escrow.buyer,
+            seller: escrow.seller,
+            submitter: caller,
+            evidence_uri: escrow.evidence_uri.clone(),
+        });
+
+        msg!("Evidence submitted by {}", caller);
+        Ok(())
+    }
+
+    /// Arbiter settles a dispute, paying `seller_bps` of the remaining funds
+    /// to the seller and refunding the rest to the buyer
+    pub fn resolve_dispute(ctx: Context<ResolveDispute>, seller_bps: u16) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        require_operational_for(&ctx.accounts.config, escrow)?;
+        require!(escrow.state == EscrowState::Disputed, EscrowError::InvalidState);
+        require!(escrow.arbiter == ctx.accounts.arbiter.key(), EscrowError::UnauthorizedArbiter);
+        require!(seller_bps <= 10_000, EscrowError::InvalidSplit);
+
+        let remaining = escrow.remaining_amount()?;
+        let seller_share = escrow.rounding.bps_of(remaining, seller_bps, true)?;
+        let buyer_share = remaining
+            .checked_sub(seller_share)
+            .ok_or(EscrowError::ArithmeticOverflow)?;
+
+        let seeds = &[
+            b"escrow",
+            escrow.seed_buyer().as_ref(),
+            escrow.mint.as_ref(),
+            escrow.seed_suffix(),
+            &[escrow.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        pay_seller(
+            escrow,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.seller_token_account,
+            ctx.accounts.fee_vault.as_deref(),
+            ctx.accounts.referrer_token_account.as_deref(),
+            &ctx.accounts.token_program,
+            seller_share,
+        )?;
+        if buyer_share > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.vault_token_account.to_account_info(),
+                to: ctx.accounts.buyer_token_account.to_account_info(),
+                authority: escrow.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            transfer_tokens(cpi_ctx, buyer_share)?;
+        }
+        return_collateral(
+            escrow,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.seller_token_account,
+            Some(&ctx.accounts.buyer_token_account),
+            &ctx.accounts.token_program,
+            Clock::get()?.unix_timestamp,
+        )?;
+
+        escrow.released_amount = escrow.amount;
+        escrow.state = EscrowState::Resolved;
+        let clock = Clock::get()?;
+        escrow.record(TimelineEvent::Resolved, clock.unix_timestamp)?;
+        untrack(escrow, &mut ctx.accounts.config, &mut ctx.accounts.buyer_account)?;
+
+        msg!(
+            "Dispute resolved: {} to seller, {} to buyer",
+            seller_share,
+            buyer_share
+        );
+        Ok(())
+    }
+
+    /// Creates the deployment-wide config PDA; the signer becomes its authority
+    pub fn initialize_config(ctx: Context<InitializeConfig>, default_arbiter: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.authority = ctx.accounts.authority.key();
+        config.default_arbiter = default_arbiter;
+        config.bump = ctx.bumps.config;
+
+        msg!("Config initialized with authority {}", config.authority);
+        Ok(())
+    }
+
+    /// First step of handing the config to a new authority, which takes
+    /// effect only once it calls `accept_authority`. `Pubkey::default()`
+    /// withdraws a pending proposal.
+    pub fn propose_new_authority(ctx: Context<UpdateConfig>, new_authority: Pubkey) -> Result<()> {
+        ctx.accounts.config.pending_authority = new_authority;
+
+        msg!("Proposed {} as the new config authority", new_authority);
+        Ok(())
+    }
+
+    /// The proposed authority takes over the config
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+
+        require!(
+            config.pending_authority != Pubkey::default()
+                && config.pending_authority == ctx.accounts.new_authority.key(),
+            EscrowError::NotPendingAuthority
+        );
+        config.authority = config.pending_authority;
+        config.pending_authority = Pubkey::default();
+
+        msg!("Config authority is now {}", config.authority);
+        Ok(())
+    }
+
+    /// Sets the arbiter new escrows inherit; `Pubkey::default()` disables it
+    pub fn update_default_arbiter(
+        ctx: Context<UpdateConfig>,
+        default_arbiter: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.config.default_arbiter = default_arbiter;
+
+        msg!("Default arbiter set to {}", default_arbiter);
+        Ok(())
+    }
+
+    /// Sets how long after a confirmation request new escrows let the seller
+    /// self-release via `resolve_timeout`; 0 disables it
+    pub fn update_auto_confirm(ctx: Context<UpdateConfig>, auto_confirm_after: i64) -> Result<()> {
+        require!(auto_confirm_after >= 0, EscrowError::InvalidAutoConfirm);
+        ctx.accounts.config.auto_confirm_after = auto_confirm_after;
+
+        msg!("Auto-confirm window set to {}s", auto_confirm_after);
+        Ok(())
+    }
+
+    /// Sets the protocol fee new escrows snapshot at creation
+    pub fn update_protocol_fee(ctx: Context<UpdateConfig>, fee_bps: u16) -> Result<()> {
+        require!(fee_bps <= Config::MAX_FEE_BPS, EscrowError::InvalidFee);
+        ctx.accounts.config.fee_bps = fee_bps;
+
+        msg!("Protocol fee set to {} bps", fee_bps);
+        Ok(())
+    }
+
+    /// Sets how new escrows round fees and dispute splits that don't divide
+    /// evenly
+    pub fn update_rounding_policy(ctx: Context<UpdateConfig>, policy: RoundingPolicy) -> Result<()> {
+        ctx.accounts.config.rounding_policy = policy;
+
+        msg!("Rounding policy set to {:?}", policy);
+        Ok(())
+    }
+
+    /// Turns emergency mode on or off. While on, escrows can only be unwound
+    /// by buyer refunds (`cancel_escrow`, `claim_non_delivery`,
+    /// `withdraw_partial`); creation, acceptance and every payout fail with
+    /// `EmergencyMode`.
+    pub fn set_emergency_mode(ctx: Context<UpdateConfig>, enabled: bool) -> Result<()> {
+        ctx.accounts.config.emergency_mode = enabled;
+
+        msg!("Emergency mode {}", if enabled { "enabled" } else { "disabled" });
+        Ok(())
+    }
+
+    /// Exempts `buyer`'s future escrows from the protocol fee. Escrows that
+    /// already exist keep the fee they were created with.
+    pub fn add_fee_exempt(ctx: Context<UpdateConfig>, buyer: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        require!(!config.fee_exempt.contains(&buyer), EscrowError::AlreadyFeeExempt);
+        require!(
+            config.fee_exempt.len() < Config::MAX_FEE_EXEMPT,
+            EscrowError::FeeExemptListFull
+        );
+        config.fee_exempt.push(buyer);
+
+        msg!("{} is now fee-exempt", buyer);
+        Ok(())
+    }
+
+    /// Removes `buyer` from the fee-exempt list
+    pub fn remove_fee_exempt(ctx: Context<UpdateConfig>, buyer: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let index = config
+            .fee_exempt
+            .iter()
+            .position(|exempt| *exempt == buyer)
+            .ok_or(EscrowError::NotFeeExempt)?;
+        config.fee_exempt.swap_remove(index);
+
+        msg!("{} is no longer fee-exempt", buyer);
+        Ok(())
+    }
+
+    /// Approves `relayer` to submit `confirm_delivery_signed`. Once the list
+    /// is non-empty, only listed relayers (or the buyer) may relay.
+    pub fn add_relayer(ctx: Context<UpdateConfig>, relayer: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        require!(!config.relayer_allowlist.contains(&relayer), EscrowError::AlreadyRelayer);
+        require!(
+            config.relayer_allowlist.len() < Config::MAX_RELAYERS,
+            EscrowError::RelayerListFull
+        );
+        config.relayer_allowlist.push(relayer);
+
+        msg!("{} is now an approved relayer", relayer);
+        Ok(())
+    }
+
+    /// Removes `relayer` from the relayer allowlist
+    pub fn remove_relayer(ctx: Context<UpdateConfig>, relayer: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let index = config
+            .relayer_allowlist
+            .iter()
+            .position(|listed| *listed == relayer)
+            .ok_or(EscrowError::NotRelayer)?;
+        config.relayer_allowlist.swap_remove(index);
+
+        msg!("{} is no longer an approved relayer", relayer);
+        Ok(())
+    }
+
+    /// Sets the smallest seller collateral, as basis points of the amount,
+    /// that escrows created with the config may require
+    pub fn update_min_collateral(ctx: Context<UpdateConfig>, min_collateral_bps: u16) -> Result<()> {
+        require!(min_collateral_bps <= 10_000, EscrowError::InvalidCollateral);
+        ctx.accounts.config.min_collateral_bps = min_collateral_bps;
+
+        msg!("Minimum collateral set to {} bps", min_collateral_bps);
+        Ok(())
+    }
+
+    /// Caps how many unsettled escrows each buyer may have open; 0 removes
+    /// the cap. Escrows already open are unaffected, even past a lowered cap.
+    pub fn update_max_active_per_buyer(ctx: Context<UpdateConfig>, max_active_per_buyer: u32) -> Result<()> {
+        ctx.accounts.config.max_active_per_buyer = max_active_per_buyer;
+
+        msg!("Active escrows per buyer capped at {}", max_active_per_buyer);
+        Ok(())
+    }
+
+    /// Authority corrects the fee stored on a not-yet-accepted escrow. It can
+    /// only be lowered, so a buyer never pays more than they agreed to.
+    pub fn update_fee_bps(ctx: Context<UpdateEscrowFee>, fee_bps: u16) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        require!(escrow.state == EscrowState::Created, EscrowError::InvalidState);
+        require!(fee_bps <= escrow.fee_bps, EscrowError::FeeTooHigh);
+        escrow.fee_bps = fee_bps;
+
+        msg!("Escrow fee lowered to {} bps", fee_bps);
+        Ok(())
+    }
+
+    /// Creates the config-owned account that collects fees for `mint`
+    pub fn initialize_fee_vault(ctx: Context<InitializeFeeVault>) -> Result<()> {
+        msg!("Fee vault created for mint {}", ctx.accounts.mint.key());
+        Ok(())
+    }
+
+    /// Authority sweeps the collected fees for one mint to `destination`
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>) -> Result<()> {
+        let amount = ctx.accounts.fee_vault.amount;
+        require!(amount > 0, EscrowError::NoFeesToWithdraw);
+
+        let seeds = &[b"config".as_ref(), &[ctx.accounts.config.bump]];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.fee_vault.to_account_info(),
+            to: ctx.accounts.destination.to_account_info(),
+            authority: ctx.accounts.config.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        transfer_tokens(cpi_ctx, amount)?;
+
+        msg!("Withdrew {} in fees", amount);
+        Ok(())
+    }
+
+    /// Shuts down the deployment config, returning its rent to the authority.
+    /// Only escrows created with the config are counted, so this is a
+    /// best-effort guard rather than a guarantee that no escrows are live.
+    pub fn close_config(ctx: Context<CloseConfig>) -> Result<()> {
+        require!(
+            ctx.accounts.config.active_escrows == 0,
+            EscrowError::ConfigHasActiveEscrows
+        );
+
+        msg!("Config closed");
+        Ok(())
+    }
+
+    /// Grows a v1 escrow (no `timeout_beneficiary`) to the current layout, with
+    /// the seller as beneficiary so timeouts behave as before and the vault PDA
+    /// recorded as its vault. `payer` covers the extra rent.
+    pub fn migrate_v2(ctx: Context<MigrateV2>) -> Result<()> {
+        let info = ctx.accounts.escrow.to_account_info();
+        require!(info.data_len() == 8 + Escrow::LEN_V1, EscrowError::AlreadyMigrated);
+        require!(
+            info.try_borrow_data()?[..8] == *Escrow::DISCRIMINATOR,
+            ErrorCode::AccountDiscriminatorMismatch
+        );
+
+        let new_len = 8 + Escrow::LEN;
+        let top_up = Rent::get()?
+            .minimum_balance(new_len)
+            .saturating_sub(info.lamports());
+        if top_up > 0 {
+            let cpi_accounts = anchor_lang::system_program::Transfer {
+                from: ctx.accounts.payer.to_account_info(),
+                to: info.clone(),
+            };
+            let cpi_ctx = CpiContext::new(ctx.accounts.system_program.to_account_info(), cpi_accounts);
+            anchor_lang::system_program::transfer(cpi_ctx, top_up)?;
+        }
+        info.resize(new_len)?;
+
+        // The v1 fields are a prefix of v2, and the new byte is zeroed
+        let mut escrow = Escrow::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+        escrow.timeout_beneficiary = TimeoutBeneficiary::Seller;
+        escrow.vault = Pubkey::find_program_address(&[b"vault", info.key.as_ref()], &crate::ID).0;
+        escrow.original_depositor = escrow.buyer;
+        escrow.permissionless_timeout = true;
+        escrow.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+
+        msg!("Escrow migrated to v2");
+        Ok(())
+    }
+
+    /// Creates a swap escrow: buyer deposits token A and names the token B
+    /// mint and amount the seller must deposit to accept
+    pub fn create_swap(
+        ctx: Context<CreateSwap>,
+        amount: u64,
+        swap_amount: u64,
+        min_receive: u64,
+        swap_valid_until: i64,
+        timeout_duration: i64,
+        description: String,
+    ) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let clock = Clock::get()?;
+
+        require!(min_receive <= swap_amount, EscrowError::InvalidSwapTerms);
+        require!(
+            description.len() <= Escrow::MAX_DESCRIPTION_LEN,
+            EscrowError::DescriptionTooLong
+        );
+
+        escrow.buyer = ctx.accounts.buyer.key();
+        escrow.original_depositor = ctx.accounts.buyer.key();
+        escrow.permissionless_timeout = true;
+        escrow.seller = Pubkey::default();
+        escrow.mint = ctx.accounts.mint.key();
+        escrow.amount = amount;
+        escrow.record(TimelineEvent::Created, clock.unix_timestamp)?;
+        escrow.timeout_at = clock
+            .unix_timestamp
+            .checked_add(timeout_duration)
+            .ok_or(EscrowError::ArithmeticOverflow)?;
+        escrow.state = EscrowState::Created;
+        escrow.description = description;
+        escrow.bump = ctx.bumps.escrow;
+        escrow.vault = ctx.accounts.vault_token_account.key();
+        escrow.swap_mint = ctx.accounts.swap_mint.key();
+        escrow.swap_amount = swap_amount;
+        escrow.swap_min_receive = min_receive;
+        escrow.swap_valid_until = swap_valid_until;
+
+        // Transfer token A to the escrow vault
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.buyer_token_account.to_account_info(),
+            to: ctx.accounts.vault_token_account.to_account_info(),
+            authority: ctx.accounts.buyer.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        transfer_tokens(cpi_ctx, amount)?;
+
+        msg!(
+            "Swap created: {} tokens deposited for {} of mint {}",
+            amount,
+            swap_amount,
+            escrow.swap_mint
+        );
+        Ok(())
+    }
+
+    /// Seller accepts a swap by depositing the requested token B amount
+    pub fn accept_swap(ctx: Context<AcceptSwap>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let clock = Clock::get()?;
+
+        require_operational_for(&ctx.accounts.config, escrow)?;
+        require!(escrow.is_swap(), EscrowError::WrongEscrowKind);
+        escrow.can_accept(&ctx.accounts.seller.key(), clock.unix_timestamp)?;
+
+        // Transfer token B from seller into the swap vault
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.seller_swap_token_account.to_account_info(),
+            to: ctx.accounts.swap_vault_token_account.to_account_info(),
+            authority: ctx.accounts.seller.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        transfer_tokens(cpi_ctx, escrow.swap_amount)?;
+
+        // Guard against the vault receiving less than the buyer will accept
+        ctx.accounts.swap_vault_token_account.reload()?;
+        require!(
+            ctx.accounts.swap_vault_token_account.amount >= escrow.swap_min_receive,
+            EscrowError::SwapAmountTooLow
+        );
+
+        escrow.seller = ctx.accounts.seller.key();
+        escrow.state = EscrowState::Accepted;
+        escrow.record(TimelineEvent::Accepted, clock.unix_timestamp)?;
+
+        msg!("Swap accepted by seller: {}", ctx.accounts.seller.key());
+        Ok(())
+    }
+
+    /// Settles an accepted swap: each party receives the other's deposit.
+    /// The buyer may confirm at any time; after the timeout anyone may settle.
+    pub fn confirm_swap(ctx: Context<ConfirmSwap>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let clock = Clock::get()?;
+
+        require_operational_for(&ctx.accounts.config, escrow)?;
+        require!(escrow.is_swap(), EscrowError::WrongEscrowKind);
+        require!(escrow.state == EscrowState::Accepted, EscrowError::InvalidState);
+        let by_buyer = ctx.accounts.authority.key() == escrow.buyer;
+        require!(
+            by_buyer || clock.unix_timestamp >= escrow.timeout_at,
+            EscrowError::UnauthorizedBuyer
+        );
+
+        let seeds = &[
+            b"escrow",
+            escrow.seed_buyer().as_ref(),
+            escrow.mint.as_ref(),
+            escrow.seed_suffix(),
+            &[escrow.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        // Token A to seller
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.vault_token_account.to_account_info(),
+            to: ctx.accounts.seller_token_account.to_account_info(),
+            authority: escrow.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        transfer_tokens(cpi_ctx, escrow.amount)?;
+
+        // Token B to buyer (whatever the swap vault actually received)
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.swap_vault_token_account.to_account_info(),
+            to: ctx.accounts.buyer_swap_token_account.to_account_info(),
+            authority: escrow.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        transfer_tokens(cpi_ctx, ctx.accounts.swap_vault_token_account.amount)?;
+
+        escrow.released_amount = escrow.amount;
+        if by_buyer {
+            escrow.state = EscrowState::Completed;
+            escrow.record(TimelineEvent::Completed, clock.unix_timestamp)?;
+        } else {
+            escrow.state = EscrowState::TimedOut;
+            escrow.record(TimelineEvent::TimedOut, clock.unix_timestamp)?;
+        }
+
+        msg!("Swap settled, both legs released");
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(params: CreateParams)]
+pub struct CreateEscrow<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = buyer,
+        space = Escrow::space_for(&params),
+        seeds = [b"escrow", buyer.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+    
+    pub mint: Account<'info, Mint>,
+    
+    /// Owned by the buyer, or by someone who approved the buyer as delegate
+    #[account(
+        mut,
+        constraint = buyer_token_account.owner == buyer.key()
+            || buyer_token_account.delegate == COption::Some(buyer.key()),
+        constraint = buyer_token_account.mint == mint.key() @ EscrowError::MintMismatch,
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        init,
+        payer = buyer,
+        token::mint = mint,
+        token::authority = escrow,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    
+    /// Deployment config; omitted for permissionless deployments
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    /// Buyer's active escrow counter; required when the config caps it
+    #[account(mut, seeds = [b"buyer", buyer.key().as_ref()], bump = buyer_account.bump)]
+    pub buyer_account: Option<Account<'info, BuyerAccount>>,
+    
+    /// Seller's standing authorization for this buyer; when passed, the
+    /// escrow opens already accepted by that seller
+    #[account(
+        seeds = [b"seller-auth", buyer.key().as_ref(), seller_authorization.seller.as_ref()],
+        bump = seller_authorization.bump
+    )]
+    pub seller_authorization: Option<Account<'info, SellerAuthorization>>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(name: String)]
+pub struct CreateTemplate<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Template::LEN,
+        seeds = [b"template", authority.key().as_ref(), name.as_bytes()],
+        bump
+    )]
+    pub template: Account<'info, Template>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateEscrowFromTemplate<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        seeds = [b"template", template.authority.as_ref(), template.name.as_bytes()],
+        bump = template.bump
+    )]
+    pub template: Account<'info, Template>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = Escrow::space_for(&CreateParams::default()),
+        seeds = [b"escrow", buyer.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    pub mint: Account<'info, Mint>,
+
+    /// Owned by the buyer, or by someone who approved the buyer as delegate
+    #[account(
+        mut,
+        constraint = buyer_token_account.owner == buyer.key()
+            || buyer_token_account.delegate == COption::Some(buyer.key()),
+        constraint = buyer_token_account.mint == mint.key() @ EscrowError::MintMismatch,
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = buyer,
+        token::mint = mint,
+        token::authority = escrow,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    /// Deployment config; omitted for permissionless deployments
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    /// Buyer's active escrow counter; required when the config caps it
+    #[account(mut, seeds = [b"buyer", buyer.key().as_ref()], bump = buyer_account.bump)]
+    pub buyer_account: Option<Account<'info, BuyerAccount>>,
+
+    /// Seller's standing authorization for this buyer; when passed, the
+    /// escrow opens already accepted by that seller
+    #[account(
+        seeds = [b"seller-auth", buyer.key().as_ref(), seller_authorization.seller.as_ref()],
+        bump = seller_authorization.bump
+    )]
+    pub seller_authorization: Option<Account<'info, SellerAuthorization>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(params: CreateParams)]
+pub struct CreateEscrowWithVault<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = buyer,
+        space = Escrow::space_for(&params),
+        seeds = [b"escrow", buyer.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+    
+    pub mint: Account<'info, Mint>,
+    
+    /// Owned by the buyer, or by someone who approved the buyer as delegate
+    #[account(
+        mut,
+        constraint = buyer_token_account.owner == buyer.key()
+            || buyer_token_account.delegate == COption::Some(buyer.key()),
+        constraint = buyer_token_account.mint == mint.key() @ EscrowError::MintMismatch,
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+    
+    /// Existing, empty vault controlled solely by the escrow PDA
+    #[account(
+        mut,
+        constraint = vault_token_account.mint == mint.key() @ EscrowError::MintMismatch,
+        constraint = vault_token_account.owner == escrow.key() @ EscrowError::InvalidVault,
+        constraint = vault_token_account.delegate.is_none() @ EscrowError::InvalidVault,
+        constraint = vault_token_account.close_authority.is_none() @ EscrowError::InvalidVault,
+        constraint = vault_token_account.amount == 0 @ EscrowError::InvalidVault,
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    
+    /// Deployment config; omitted for permissionless deployments
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    /// Buyer's active escrow counter; required when the config caps it
+    #[account(mut, seeds = [b"buyer", buyer.key().as_ref()], bump = buyer_account.bump)]
+    pub buyer_account: Option<Account<'info, BuyerAccount>>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(params: CreateParams)]
+pub struct CreateAndAccept<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    pub seller: Signer<'info>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = Escrow::space_for(&params),
+        seeds = [b"escrow", buyer.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    pub mint: Account<'info, Mint>,
+
+    /// Owned by the buyer, or by someone who approved the buyer as delegate
+    #[account(
+        mut,
+        constraint = buyer_token_account.owner == buyer.key()
+            || buyer_token_account.delegate == COption::Some(buyer.key()),
+        constraint = buyer_token_account.mint == mint.key() @ EscrowError::MintMismatch,
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+
+    /// Source of the seller's collateral, if the escrow requires any
+    #[account(
+        mut,
+        constraint = seller_token_account.owner == seller.key(),
+        constraint = seller_token_account.mint == mint.key() @ EscrowError::MintMismatch,
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = buyer,
+        token::mint = mint,
+        token::authority = escrow,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    /// Deployment config; omitted for permissionless deployments
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    /// Buyer's active escrow counter; required when the config caps it
+    #[account(mut, seeds = [b"buyer", buyer.key().as_ref()], bump = buyer_account.bump)]
+    pub buyer_account: Option<Account<'info, BuyerAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptEscrow<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// When passed, the only account the seller can be paid out to
+    #[account(
+        constraint = escrow.is_payout_owner(&seller.key(), &seller_payout_account.owner),
+        constraint = seller_payout_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub seller_payout_account: Option<Account<'info, TokenAccount>>,
+
+    /// Alternative to `seller_payout_account`: the seller's associated token
+    /// account for the escrow mint, created at the seller's expense if it
+    /// doesn't exist yet, and bound as the payout account
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program,
+    )]
+    pub seller_payout_ata: Option<Account<'info, TokenAccount>>,
+
+    /// Checked for emergency mode; required for escrows counted in it
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    /// Escrow mint, with the programs below only needed for `seller_payout_ata`
+    #[account(address = escrow.mint @ EscrowError::MintMismatch)]
+    pub mint: Option<Account<'info, Mint>>,
+    pub token_program: Option<Program<'info, Token>>,
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+    pub system_program: Option<Program<'info, System>>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptEscrowsBatch<'info> {
+    pub seller: Signer<'info>,
+
+    /// Checked for emergency mode; required for escrows counted in it
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptWithCollateral<'info> {
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        address = escrow.vault @ EscrowError::InvalidVault,
+        constraint = vault_token_account.mint == escrow.mint @ EscrowError::MintMismatch
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = seller_token_account.owner == seller.key(),
+        constraint = seller_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+
+    /// When passed, the only account the seller can be paid out to
+    #[account(
+        constraint = escrow.is_payout_owner(&seller.key(), &seller_payout_account.owner),
+        constraint = seller_payout_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub seller_payout_account: Option<Account<'info, TokenAccount>>,
+
+    /// Checked for emergency mode; required for escrows counted in it
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(buyer: Pubkey)]
+pub struct AuthorizeSeller<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + SellerAuthorization::LEN,
+        seeds = [b"seller-auth", buyer.as_ref(), seller.key().as_ref()],
+        bump
+    )]
+    pub seller_authorization: Account<'info, SellerAuthorization>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitBuyerAccount<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + BuyerAccount::LEN,
+        seeds = [b"buyer", buyer.key().as_ref()],
+        bump
+    )]
+    pub buyer_account: Account<'info, BuyerAccount>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeSellerAuthorization<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        close = seller,
+        seeds = [b"seller-auth", seller_authorization.buyer.as_ref(), seller.key().as_ref()],
+        bump = seller_authorization.bump
+    )]
+    pub seller_authorization: Account<'info, SellerAuthorization>,
+}
+
+#[derive(Accounts)]
+pub struct ConfirmDelivery<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+    
+    #[account(
+        mut,
+        address = escrow.vault @ EscrowError::InvalidVault,
+        constraint = vault_token_account.mint == escrow.mint @ EscrowError::MintMismatch
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = seller_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = escrow.is_payout_owner(&escrow.seller, &seller_token_account.owner),
+        constraint = seller_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+        constraint = escrow.accepts_payout_to(&seller_token_account.key()) @ EscrowError::PayoutAccountMismatch,
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+    
+    /// Refund destination for the unfulfilled part of a `partial_accept` and
+    /// any collateral the seller forfeits under the SLA tiers; also pays a
+    /// `confirm_delivery` tip
+    #[account(
+        mut,
+        constraint = buyer_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = buyer_token_account.owner == escrow.buyer,
+        constraint = buyer_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub buyer_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    
+    /// Receives the protocol fee; required when `escrow.fee_bps > 0`
+    #[account(
+        mut,
+        seeds = [b"fee-vault", escrow.mint.as_ref()],
+        bump
+    )]
+    pub fee_vault: Option<Box<Account<'info, TokenAccount>>>,
+    /// Receives the referrer's slice of the fee; required when it's nonzero
+    #[account(
+        mut,
+        constraint = referrer_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = referrer_token_account.owner == escrow.referrer @ EscrowError::ReferrerAccountMismatch,
+        constraint = referrer_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub referrer_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Decrements the active escrow count when the escrow was counted
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    /// The creating buyer's active escrow counter; required when the escrow was counted in it
+    #[account(mut, seeds = [b"buyer", escrow.seed_buyer().as_ref()], bump = buyer_account.bump)]
+    pub buyer_account: Option<Account<'info, BuyerAccount>>,
+
+    /// Receives the rent when an `auto_close` escrow closes; defaults to the
+    /// buyer, and must be the sponsor if one paid the rent
+    /// CHECK: Only receives lamports; checked against `Escrow::rent_recipient`
+    #[account(mut)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ConfirmDeliverySigned<'info> {
+    /// Submits the buyer's pre-signed confirmation; needs no escrow role
+    pub relayer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        address = escrow.vault @ EscrowError::InvalidVault,
+        constraint = vault_token_account.mint == escrow.mint @ EscrowError::MintMismatch
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = seller_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = escrow.is_payout_owner(&escrow.seller, &seller_token_account.owner),
+        constraint = seller_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+        constraint = escrow.accepts_payout_to(&seller_token_account.key()) @ EscrowError::PayoutAccountMismatch,
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: address is pinned to the instructions sysvar
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    /// Refund destination for the unfulfilled part of a `partial_accept` and
+    /// any collateral the seller forfeits under the SLA tiers
+    #[account(
+        mut,
+        constraint = buyer_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = buyer_token_account.owner == escrow.buyer,
+        constraint = buyer_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub buyer_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Receives the protocol fee; required when `escrow.fee_bps > 0`
+    #[account(
+        mut,
+        seeds = [b"fee-vault", escrow.mint.as_ref()],
+        bump
+    )]
+    pub fee_vault: Option<Box<Account<'info, TokenAccount>>>,
+    /// Receives the referrer's slice of the fee; required when it's nonzero
+    #[account(
+        mut,
+        constraint = referrer_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = referrer_token_account.owner == escrow.referrer @ EscrowError::ReferrerAccountMismatch,
+        constraint = referrer_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub referrer_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Decrements the active escrow count when the escrow was counted
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    /// The creating buyer's active escrow counter; required when the escrow was counted in it
+    #[account(mut, seeds = [b"buyer", escrow.seed_buyer().as_ref()], bump = buyer_account.bump)]
+    pub buyer_account: Option<Account<'info, BuyerAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CancelEscrow<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+    
+    #[account(
+        mut,
+        address = escrow.vault @ EscrowError::InvalidVault,
+        constraint = vault_token_account.mint == escrow.mint @ EscrowError::MintMismatch
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    
+    /// Refund destination; must belong to the wallet that made the deposit
+    #[account(
+        mut,
+        constraint = buyer_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+    
+    /// Decrements the active escrow count when the escrow was counted
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    /// The creating buyer's active escrow counter; required when the escrow was counted in it
+    #[account(mut, seeds = [b"buyer", escrow.seed_buyer().as_ref()], bump = buyer_account.bump)]
+    pub buyer_account: Option<Account<'info, BuyerAccount>>,
+
+    /// Receives the rent when an `auto_close` escrow closes; defaults to the
+    /// buyer, and must be the sponsor if one paid the rent
+    /// CHECK: Only receives lamports; checked against `Escrow::rent_recipient`
+    #[account(mut)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveTimeout<'info> {
+    /// Anyone can call this to resolve timeout
+    pub resolver: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+    
+    #[account(
+        mut,
+        address = escrow.vault @ EscrowError::InvalidVault,
+        constraint = vault_token_account.mint == escrow.mint @ EscrowError::MintMismatch
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = seller_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = escrow.is_payout_owner(&escrow.seller, &seller_token_account.owner),
+        constraint = seller_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+        constraint = escrow.accepts_payout_to(&seller_token_account.key()) @ EscrowError::PayoutAccountMismatch,
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+    
+    /// Refund destination; required when the timeout favors the buyer or the
+    /// seller forfeits collateral under the SLA tiers
+    #[account(
+        mut,
+        constraint = buyer_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = buyer_token_account.owner == escrow.buyer,
+        constraint = buyer_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub buyer_token_account: Option<Box<Account<'info, TokenAccount>>>,
     
+    /// Receives the protocol fee; required when `escrow.fee_bps > 0`
+    #[account(
+        mut,
+        seeds = [b"fee-vault", escrow.mint.as_ref()],
+        bump
+    )]
+    pub fee_vault: Option<Box<Account<'info, TokenAccount>>>,
+    /// Receives the referrer's slice of the fee; required when it's nonzero
+    #[account(
+        mut,
+        constraint = referrer_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = referrer_token_account.owner == escrow.referrer @ EscrowError::ReferrerAccountMismatch,
+        constraint = referrer_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub referrer_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Decrements the active escrow count when the escrow was counted
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    /// The creating buyer's active escrow counter; required when the escrow was counted in it
+    #[account(mut, seeds = [b"buyer", escrow.seed_buyer().as_ref()], bump = buyer_account.bump)]
+    pub buyer_account: Option<Account<'info, BuyerAccount>>,
+
+    /// Receives the rent when an `auto_close` escrow closes, so required for
+    /// one: the buyer, or the sponsor if one paid the rent
+    /// CHECK: Only receives lamports; checked against `Escrow::rent_recipient`
+    #[account(mut)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveTimeoutsBatch<'info> {
+    /// Keeper submitting the batch; escrows that restrict who may resolve
+    /// their timeout are skipped unless this is the buyer or seller
+    pub resolver: Signer<'info>,
+
+    /// Decrements the active escrow count for counted escrows
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RecoverExcess<'info> {
+    pub buyer: Signer<'info>,
+
+    #[account(
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        address = escrow.vault @ EscrowError::InvalidVault,
+        constraint = vault_token_account.mint == escrow.mint @ EscrowError::MintMismatch
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = buyer_token_account.owner == buyer.key(),
+        constraint = buyer_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct MintReceipt<'info> {
+    /// Pays the receipt's rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Receipt::LEN,
+        seeds = [b"receipt", escrow.key().as_ref()],
+        bump
+    )]
+    pub receipt: Account<'info, Receipt>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseEscrow<'info> {
+    /// Receives the rent of the escrow and vault accounts unless a sponsor paid it
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        address = escrow.vault @ EscrowError::InvalidVault,
+        constraint = vault_token_account.mint == escrow.mint @ EscrowError::MintMismatch
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    /// Receives any tokens left in the vault
+    #[account(
+        mut,
+        constraint = buyer_token_account.owner == buyer.key(),
+        constraint = buyer_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+
+    /// Sponsor who receives the rent; required when the escrow has a
+    /// `rent_payer`
+    /// CHECK: Only receives lamports; checked against `escrow.rent_payer`
+    #[account(mut)]
+    pub rent_payer: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SponsorRent<'info> {
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+
+    /// Reimbursed for the rent
+    #[account(mut, address = escrow.buyer @ EscrowError::UnauthorizedBuyer)]
+    pub buyer: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(address = escrow.vault @ EscrowError::InvalidVault)]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AdjustDeposit<'info> {
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        address = escrow.vault @ EscrowError::InvalidVault,
+        constraint = vault_token_account.mint == escrow.mint @ EscrowError::MintMismatch
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    /// Source of a deposit, or destination of a withdrawal, which must
+    /// belong to the wallet that made the original deposit
+    #[account(
+        mut,
+        constraint = buyer_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+
+    /// Checked for the collateral minimum when the amount grows; required
+    /// for escrows counted in it
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SplitEscrow<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        address = escrow.vault @ EscrowError::InvalidVault,
+        constraint = vault_token_account.mint == escrow.mint @ EscrowError::MintMismatch
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(address = escrow.mint)]
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = escrow.space_with_description(escrow.description.len()),
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.key().as_ref()],
+        bump
+    )]
+    pub new_escrow: Account<'info, Escrow>,
+
+    #[account(
+        init,
+        payer = buyer,
+        token::mint = mint,
+        token::authority = new_escrow,
+        seeds = [b"vault", new_escrow.key().as_ref()],
+        bump
+    )]
+    pub new_vault_token_account: Account<'info, TokenAccount>,
+
+    /// Counts the new escrow; required when the split escrow was counted in it
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    /// The creating buyer's active escrow counter; required when the split
+    /// escrow was counted in it or the config caps active escrows
+    #[account(mut, seeds = [b"buyer", escrow.seed_buyer().as_ref()], bump = buyer_account.bump)]
+    pub buyer_account: Option<Account<'info, BuyerAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct MergeEscrows<'info> {
+    /// Receives the rent of the source escrow and vault
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        address = escrow.vault @ EscrowError::InvalidVault,
+        constraint = vault_token_account.mint == escrow.mint @ EscrowError::MintMismatch
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        close = buyer,
+        constraint = source_escrow.key() != escrow.key() @ EscrowError::IncompatibleEscrows,
+        constraint = source_escrow.mint == escrow.mint @ EscrowError::MintMismatch,
+        seeds = [b"escrow", source_escrow.seed_buyer().as_ref(), source_escrow.mint.as_ref(), source_escrow.seed_suffix()],
+        bump = source_escrow.bump
+    )]
+    pub source_escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        address = source_escrow.vault @ EscrowError::InvalidVault,
+        constraint = source_vault_token_account.mint == escrow.mint @ EscrowError::MintMismatch
+    )]
+    pub source_vault_token_account: Account<'info, TokenAccount>,
+
+    /// Decrements the active escrow count when the source escrow was counted
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    /// The creating buyer's active escrow counter; required when the source escrow was counted in it
+    #[account(mut, seeds = [b"buyer", source_escrow.seed_buyer().as_ref()], bump = buyer_account.bump)]
+    pub buyer_account: Option<Account<'info, BuyerAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(description: String)]
+pub struct ExpandDescription<'info> {
+    /// Pays the rent for the added bytes
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump,
+        realloc = escrow.space_with_description(description.len()),
+        realloc::payer = buyer,
+        realloc::zero = true
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExtendTimeout<'info> {
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct TransferEscrowRights<'info> {
+    pub buyer: Signer<'info>,
+
+    /// Takes over the buyer role, and signs to accept it
+    pub new_buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct CheckCanAccept<'info> {
+    #[account(
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct GetTimeline<'info> {
+    #[account(
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct GetVaultBalance<'info> {
+    #[account(
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(address = escrow.vault @ EscrowError::InvalidVault)]
+    pub vault_token_account: Account<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct PreviewSettlement<'info> {
+    #[account(
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseMilestone<'info> {
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        address = escrow.vault @ EscrowError::InvalidVault,
+        constraint = vault_token_account.mint == escrow.mint @ EscrowError::MintMismatch
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = seller_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = escrow.is_payout_owner(&escrow.seller, &seller_token_account.owner),
+        constraint = seller_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+        constraint = escrow.accepts_payout_to(&seller_token_account.key()) @ EscrowError::PayoutAccountMismatch,
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+
+    /// Receives the collateral the seller forfeits under the SLA tiers;
+    /// required when releasing the last milestone forfeits any
+    #[account(
+        mut,
+        constraint = buyer_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = buyer_token_account.owner == escrow.buyer,
+        constraint = buyer_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub buyer_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Receives the protocol fee; required when `escrow.fee_bps > 0`
+    #[account(
+        mut,
+        seeds = [b"fee-vault", escrow.mint.as_ref()],
+        bump
+    )]
+    pub fee_vault: Option<Box<Account<'info, TokenAccount>>>,
+    /// Receives the referrer's slice of the fee; required when it's nonzero
+    #[account(
+        mut,
+        constraint = referrer_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = referrer_token_account.owner == escrow.referrer @ EscrowError::ReferrerAccountMismatch,
+        constraint = referrer_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub referrer_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Decrements the active escrow count when the escrow was counted
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    /// The creating buyer's active escrow counter; required when the escrow was counted in it
+    #[account(mut, seeds = [b"buyer", escrow.seed_buyer().as_ref()], bump = buyer_account.bump)]
+    pub buyer_account: Option<Account<'info, BuyerAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RequestConfirmation<'info> {
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct OpenSealedBidding<'info> {
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct CommitBid<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    #[account(
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + Bid::LEN,
+        seeds = [b"bid", escrow.key().as_ref(), seller.key().as_ref()],
+        bump
+    )]
+    pub bid: Account<'info, Bid>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevealBid<'info> {
+    pub seller: Signer<'info>,
+
+    #[account(
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        seeds = [b"bid", escrow.key().as_ref(), seller.key().as_ref()],
+        bump = bid.bump
+    )]
+    pub bid: Account<'info, Bid>,
+}
+
+#[derive(Accounts)]
+pub struct SelectBid<'info> {
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        seeds = [b"bid", escrow.key().as_ref(), bid.seller.as_ref()],
+        bump = bid.bump
+    )]
+    pub bid: Account<'info, Bid>,
+
+    /// Checked for emergency mode; required for escrows counted in it
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+}
+
+#[derive(Accounts)]
+pub struct CloseBid<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        close = seller,
+        seeds = [b"bid", bid.escrow.as_ref(), seller.key().as_ref()],
+        bump = bid.bump
+    )]
+    pub bid: Account<'info, Bid>,
+}
+
+#[derive(Accounts)]
+pub struct DeclineEscrow<'info> {
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct SetSellerTreasury<'info> {
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct Heartbeat<'info> {
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct MarkDelivered<'info> {
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimVested<'info> {
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        address = escrow.vault @ EscrowError::InvalidVault,
+        constraint = vault_token_account.mint == escrow.mint @ EscrowError::MintMismatch
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = escrow.is_payout_owner(&escrow.seller, &seller_token_account.owner),
+        constraint = seller_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+        constraint = escrow.accepts_payout_to(&seller_token_account.key()) @ EscrowError::PayoutAccountMismatch,
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+
+    /// Receives the protocol fee on each claim; required when `escrow.fee_bps > 0`
+    #[account(
+        mut,
+        seeds = [b"fee-vault", escrow.mint.as_ref()],
+        bump
+    )]
+    pub fee_vault: Option<Box<Account<'info, TokenAccount>>>,
+    /// Receives the referrer's slice of the fee; required when it's nonzero
+    #[account(
+        mut,
+        constraint = referrer_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = referrer_token_account.owner == escrow.referrer @ EscrowError::ReferrerAccountMismatch,
+        constraint = referrer_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub referrer_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Decrements the active escrow count on the final claim
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    /// The creating buyer's active escrow counter; required when the escrow was counted in it
+    #[account(mut, seeds = [b"buyer", escrow.seed_buyer().as_ref()], bump = buyer_account.bump)]
+    pub buyer_account: Option<Account<'info, BuyerAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(commitment: [u8; 32])]
+pub struct SubmitShipment<'info> {
+    /// Pays the commitment record's rent
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// CHECK: must be empty, which proves the commitment is unused; the
+    /// handler creates it as a `CommitmentRecord`
+    #[account(mut, seeds = [b"commitment", commitment.as_ref()], bump)]
+    pub commitment_record: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimNonDelivery<'info> {
+    pub buyer: Signer<'info>,
+
     #[account(
         mut,
-        seeds = [b"escrow", escrow.buyer.as_ref(), escrow.mint.as_ref()],
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
         bump = escrow.bump
     )]
     pub escrow: Account<'info, Escrow>,
-    
+
     #[account(
         mut,
-        seeds = [b"vault", escrow.key().as_ref()],
+        address = escrow.vault @ EscrowError::InvalidVault,
+        constraint = vault_token_account.mint == escrow.mint @ EscrowError::MintMismatch
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = buyer_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = buyer_token_account.owner == escrow.buyer,
+        constraint = buyer_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+
+    /// Decrements the active escrow count when the escrow was counted
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    /// The creating buyer's active escrow counter; required when the escrow was counted in it
+    #[account(mut, seeds = [b"buyer", escrow.seed_buyer().as_ref()], bump = buyer_account.bump)]
+    pub buyer_account: Option<Account<'info, BuyerAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimInactiveBuyer<'info> {
+    pub seller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        address = escrow.vault @ EscrowError::InvalidVault,
+        constraint = vault_token_account.mint == escrow.mint @ EscrowError::MintMismatch
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = seller_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = escrow.is_payout_owner(&escrow.seller, &seller_token_account.owner),
+        constraint = seller_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+        constraint = escrow.accepts_payout_to(&seller_token_account.key()) @ EscrowError::PayoutAccountMismatch,
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+
+    /// Refund destination for the unfulfilled part of a `partial_accept` and
+    /// any collateral the seller forfeits under the SLA tiers
+    #[account(
+        mut,
+        constraint = buyer_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = buyer_token_account.owner == escrow.buyer,
+        constraint = buyer_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub buyer_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Receives the protocol fee; required when `escrow.fee_bps > 0`
+    #[account(
+        mut,
+        seeds = [b"fee-vault", escrow.mint.as_ref()],
         bump
     )]
+    pub fee_vault: Option<Box<Account<'info, TokenAccount>>>,
+    /// Receives the referrer's slice of the fee; required when it's nonzero
+    #[account(
+        mut,
+        constraint = referrer_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = referrer_token_account.owner == escrow.referrer @ EscrowError::ReferrerAccountMismatch,
+        constraint = referrer_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub referrer_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Decrements the active escrow count when the escrow was counted
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    /// The creating buyer's active escrow counter; required when the escrow was counted in it
+    #[account(mut, seeds = [b"buyer", escrow.seed_buyer().as_ref()], bump = buyer_account.bump)]
+    pub buyer_account: Option<Account<'info, BuyerAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RequestWithdrawal<'info> {
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteWithdrawal<'info> {
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        address = escrow.vault @ EscrowError::InvalidVault,
+        constraint = vault_token_account.mint == escrow.mint @ EscrowError::MintMismatch
+    )]
     pub vault_token_account: Account<'info, TokenAccount>,
-    
+
+    #[account(
+        mut,
+        constraint = buyer_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = buyer_token_account.owner == escrow.buyer,
+        constraint = buyer_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+
+    /// Receives the seller's collateral back; required when the escrow has any
+    #[account(
+        mut,
+        constraint = seller_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = escrow.is_payout_owner(&escrow.seller, &seller_token_account.owner),
+        constraint = seller_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub seller_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Decrements the active escrow count when the escrow was counted
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    /// The creating buyer's active escrow counter; required when the escrow was counted in it
+    #[account(mut, seeds = [b"buyer", escrow.seed_buyer().as_ref()], bump = buyer_account.bump)]
+    pub buyer_account: Option<Account<'info, BuyerAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct OpenDispute<'info> {
+    /// Buyer or seller of the escrow
+    pub party: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct RequestEvidence<'info> {
+    pub arbiter: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveDispute<'info> {
+    pub arbiter: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        address = escrow.vault @ EscrowError::InvalidVault,
+        constraint = vault_token_account.mint == escrow.mint @ EscrowError::MintMismatch
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = seller_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = escrow.is_payout_owner(&escrow.seller, &seller_token_account.owner),
+        constraint = seller_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+        constraint = escrow.accepts_payout_to(&seller_token_account.key()) @ EscrowError::PayoutAccountMismatch,
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = buyer_token_account.owner == escrow.buyer,
+        constraint = buyer_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+
+    /// Receives the protocol fee; required when `escrow.fee_bps > 0`
+    #[account(
+        mut,
+        seeds = [b"fee-vault", escrow.mint.as_ref()],
+        bump
+    )]
+    pub fee_vault: Option<Box<Account<'info, TokenAccount>>>,
+    /// Receives the referrer's slice of the fee; required when it's nonzero
+    #[account(
+        mut,
+        constraint = referrer_token_account.key() != vault_token_account.key() @ EscrowError::InvalidTokenAccount,
+        constraint = referrer_token_account.owner == escrow.referrer @ EscrowError::ReferrerAccountMismatch,
+        constraint = referrer_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
+    )]
+    pub referrer_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Decrements the active escrow count when the escrow was counted
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    /// The creating buyer's active escrow counter; required when the escrow was counted in it
+    #[account(mut, seeds = [b"buyer", escrow.seed_buyer().as_ref()], bump = buyer_account.bump)]
+    pub buyer_account: Option<Account<'info, BuyerAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Config::LEN,
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, Config>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    pub new_authority: Signer<'info>,
+
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = authority @ EscrowError::UnauthorizedAuthority
+    )]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateEscrowFee<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = authority @ EscrowError::UnauthorizedAuthority
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeFeeVault<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = authority @ EscrowError::UnauthorizedAuthority
+    )]
+    pub config: Account<'info, Config>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = authority,
+        token::mint = mint,
+        token::authority = config,
+        seeds = [b"fee-vault", mint.key().as_ref()],
+        bump
+    )]
+    pub fee_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = authority @ EscrowError::UnauthorizedAuthority
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        seeds = [b"fee-vault", fee_vault.mint.as_ref()],
+        bump
+    )]
+    pub fee_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = destination.mint == fee_vault.mint @ EscrowError::MintMismatch,
+    )]
+    pub destination: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CloseConfig<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = authority @ EscrowError::UnauthorizedAuthority
+    )]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateV2<'info> {
+    /// Covers the rent for the added bytes
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: v1 escrows don't deserialize as `Escrow`; size and discriminator
+    /// are checked in the handler
+    #[account(mut, owner = crate::ID)]
+    pub escrow: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(
+    amount: u64,
+    swap_amount: u64,
+    min_receive: u64,
+    swap_valid_until: i64,
+    timeout_duration: i64,
+    description: String
+)]
+pub struct CreateSwap<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = Escrow::space_for(&CreateParams::default()),
+        seeds = [b"escrow", buyer.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    pub mint: Account<'info, Mint>,
+
+    /// Mint the seller must deposit to accept
+    pub swap_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = buyer_token_account.owner == buyer.key(),
+        constraint = buyer_token_account.mint == mint.key() @ EscrowError::MintMismatch,
+    )]
+    pub buyer_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = buyer,
+        token::mint = mint,
+        token::authority = escrow,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump
+    )]
+    pub vault_token_account: Box<Account<'info, TokenAccount>>,
+
     #[account(
-        mut,
-        constraint = seller_token_account.owner == escrow.seller,
-        constraint = seller_token_account.mint == escrow.mint,
+        init,
+        payer = buyer,
+        token::mint = swap_mint,
+        token::authority = escrow,
+        seeds = [b"swap-vault", escrow.key().as_ref()],
+        bump
     )]
-    pub seller_token_account: Account<'info, TokenAccount>,
-    
+    pub swap_vault_token_account: Box<Account<'info, TokenAccount>>,
+
     pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-pub struct CancelEscrow<'info> {
+pub struct AcceptSwap<'info> {
     #[account(mut)]
-    pub buyer: Signer<'info>,
-    
+    pub seller: Signer<'info>,
+
     #[account(
         mut,
-        seeds = [b"escrow", escrow.buyer.as_ref(), escrow.mint.as_ref()],
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
         bump = escrow.bump
     )]
     pub escrow: Account<'info, Escrow>,
-    
+
     #[account(
         mut,
-        seeds = [b"vault", escrow.key().as_ref()],
-        bump
+        seeds = [b"swap-vault", escrow.key().as_ref()],
+        bump,
+        constraint = swap_vault_token_account.mint == escrow.swap_mint @ EscrowError::MintMismatch
     )]
-    pub vault_token_account: Account<'info, TokenAccount>,
-    
+    pub swap_vault_token_account: Account<'info, TokenAccount>,
+
     #[account(
         mut,
-        constraint = buyer_token_account.owner == buyer.key(),
-        constraint = buyer_token_account.mint == escrow.mint,
+        constraint = seller_swap_token_account.owner == seller.key(),
+        constraint = seller_swap_token_account.mint == escrow.swap_mint @ EscrowError::MintMismatch,
     )]
-    pub buyer_token_account: Account<'info, TokenAccount>,
-    
+    pub seller_swap_token_account: Account<'info, TokenAccount>,
+
+    /// Checked for emergency mode; required for escrows counted in it
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
     pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
-pub struct ResolveTimeout<'info> {
-    /// Anyone can call this to resolve timeout
-    pub resolver: Signer<'info>,
-    
+pub struct ConfirmSwap<'info> {
+    /// The buyer, or anyone once the timeout has passed
+    pub authority: Signer<'info>,
+
     #[account(
         mut,
-        seeds = [b"escrow", escrow.buyer.as_ref(), escrow.mint.as_ref()],
+        seeds = [b"escrow", escrow.seed_buyer().as_ref(), escrow.mint.as_ref(), escrow.seed_suffix()],
         bump = escrow.bump
     )]
     pub escrow: Account<'info, Escrow>,
-    
+
     #[account(
         mut,
         seeds = [b"vault", escrow.key().as_ref()],
-        bump
+        bump,
+        constraint = vault_token_account.mint == escrow.mint @ EscrowError::MintMismatch
     )]
     pub vault_token_account: Account<'info, TokenAccount>,
-    
+
+    #[account(
+        mut,
+        seeds = [b"swap-vault", escrow.key().as_ref()],
+        bump,
+        constraint = swap_vault_token_account.mint == escrow.swap_mint @ EscrowError::MintMismatch
+    )]
+    pub swap_vault_token_account: Account<'info, TokenAccount>,
+
     #[account(
         mut,
         constraint = seller_token_account.owner == escrow.seller,
-        constraint = seller_token_account.mint == escrow.mint,
+        constraint = seller_token_account.mint == escrow.mint @ EscrowError::MintMismatch,
     )]
     pub seller_token_account: Account<'info, TokenAccount>,
-    
+
+    #[account(
+        mut,
+        constraint = buyer_swap_token_account.owner == escrow.buyer,
+        constraint = buyer_swap_token_account.mint == escrow.swap_mint @ EscrowError::MintMismatch,
+    )]
+    pub buyer_swap_token_account: Account<'info, TokenAccount>,
+
+    /// Checked for emergency mode; required for escrows counted in it
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
     pub token_program: Program<'info, Token>,
 }
 
-#[account]
-pub struct Escrow {
-    pub buyer: Pubkey,
-    pub seller: Pubkey,
-    pub mint: Pubkey,
-    pub amount: u64,
+/// Shared body of the escrow creation instructions: validates the terms,
+/// fills in the new escrow and moves the deposit into its vault
+#[allow(clippy::too_many_arguments)]
+fn open_escrow<'info>(
+    escrow: &mut Account<'info, Escrow>,
+    bump: u8,
+    buyer: &Signer<'info>,
+    mint: &Account<'info, Mint>,
+    buyer_token_account: &Account<'info, TokenAccount>,
+    vault_token_account: &Account<'info, TokenAccount>,
+    config: &mut Option<Account<'info, Config>>,
+    buyer_account: &mut Option<Account<'info, BuyerAccount>>,
+    token_program: &Program<'info, Token>,
+    params: CreateParams,
+) -> Result<()> {
+    require_operational(config)?;
+    require_keys_neq!(
+        buyer_token_account.key(),
+        vault_token_account.key(),
+        EscrowError::InvalidTokenAccount
+    );
+    // A delegate must be approved for the whole deposit
+    if buyer_token_account.owner != buyer.key() {
+        require!(
+            buyer_token_account.delegated_amount >= params.amount,
+            EscrowError::InsufficientDelegation
+        );
+    }
+
+    let CreateParams {
+        amount,
+        timeout_duration,
+        description,
+        milestones,
+        arbiter,
+        warn_lead,
+        timeout_beneficiary,
+        required_collateral,
+        permissionless_timeout,
+        reject_freezable,
+        delivery_window,
+        vesting_duration,
+        accept_lock,
+        dispute_window,
+        start_at,
+        sla_tiers,
+        priority,
+        route_to_program,
+        heartbeat_interval,
+        referrer,
+        referrer_bps,
+        withdraw_delay,
+        buyer_inactivity_timeout,
+        deferred_funding,
+        auto_close,
+    } = params;
+    let clock = Clock::get()?;
+    require!(delivery_window >= 0, EscrowError::InvalidDeliveryWindow);
+    require!(vesting_duration >= 0, EscrowError::InvalidVestingDuration);
+    require!(accept_lock >= 0, EscrowError::InvalidAcceptLock);
+    require!(dispute_window >= 0, EscrowError::InvalidDisputeWindow);
+    require!(heartbeat_interval >= 0, EscrowError::InvalidHeartbeatInterval);
+    require!(referrer.is_some() || referrer_bps == 0, EscrowError::InvalidReferrerBps);
+    require!(withdraw_delay >= 0, EscrowError::InvalidWithdrawDelay);
+    require!(buyer_inactivity_timeout >= 0, EscrowError::InvalidBuyerInactivityTimeout);
+    // Milestones must sum to the amount, which staged deposits may raise
+    require!(!deferred_funding || milestones.is_empty(), EscrowError::InvalidMilestone);
+    require!(
+        sla_tiers.len() <= Escrow::MAX_SLA_TIERS
+            && sla_tiers.iter().all(|tier| tier.window > 0 && tier.keep_bps <= 10_000)
+            && sla_tiers
+                .windows(2)
+                .all(|pair| pair[0].window < pair[1].window && pair[0].keep_bps >= pair[1].keep_bps),
+        EscrowError::InvalidSlaTiers
+    );
+    require!(
+        start_at == 0 || start_at >= clock.unix_timestamp,
+        EscrowError::InvalidStartAt
+    );
+    // Deadlines run from the start, which defaults to creation
+    let start_at = start_at.max(clock.unix_timestamp);
+    require!(
+        !reject_freezable || mint.freeze_authority.is_none(),
+        EscrowError::MintIsFreezable
+    );
+    require!(
+        description.len() <= Escrow::MAX_DESCRIPTION_LEN,
+        EscrowError::DescriptionTooLong
+    );
+    
+    if !milestones.is_empty() {
+        require!(milestones.len() <= Escrow::MAX_MILESTONES, EscrowError::InvalidMilestone);
+        let mut total: u64 = 0;
+        for milestone in &milestones {
+            require!(
+                milestone.label.len() <= Milestone::MAX_LABEL_LEN,
+                EscrowError::InvalidMilestone
+            );
+            total = total
+                .checked_add(milestone.amount)
+                .ok_or(EscrowError::MilestoneSumMismatch)?;
+        }
+        require!(total == amount, EscrowError::MilestoneSumMismatch);
+    }
+    
+    // Initialize escrow account
+    escrow.buyer = buyer.key();
+    escrow.original_depositor = buyer.key();
+    escrow.seller = Pubkey::default(); // Will be set when seller accepts
+    escrow.mint = vault_token_account.mint;
+    escrow.amount = amount;
+    escrow.record(TimelineEvent::Created, clock.unix_timestamp)?;
+    escrow.start_at = start_at;
+    escrow.timeout_at = start_at
+        .checked_add(timeout_duration)
+        .ok_or(EscrowError::ArithmeticOverflow)?;
+    escrow.max_timeout_at = escrow.timeout_at.max(
+        start_at
+            .checked_add(Escrow::MAX_TOTAL_DURATION)
+            .ok_or(EscrowError::ArithmeticOverflow)?,
+    );
+    escrow.warn_lead = warn_lead;
+    escrow.timeout_beneficiary = timeout_beneficiary;
+    escrow.permissionless_timeout = permissionless_timeout;
+    escrow.confirm_nonce = clock.slot;
+    escrow.vesting_duration = vesting_duration;
+    escrow.accept_lock = accept_lock;
+    escrow.dispute_window = dispute_window;
+    escrow.sla_tiers = sla_tiers;
+    escrow.priority = priority;
+    escrow.route_to_program = route_to_program.unwrap_or_default();
+    escrow.heartbeat_interval = heartbeat_interval;
+    escrow.referrer = referrer.unwrap_or_default();
+    escrow.referrer_bps = referrer_bps;
+    escrow.withdraw_delay = withdraw_delay;
+    escrow.buyer_inactivity_timeout = buyer_inactivity_timeout;
+    if deferred_funding {
+        escrow.unfunded_amount = amount;
+    }
+    escrow.auto_close = auto_close;
+    escrow.delivery_deadline = if delivery_window > 0 {
+        start_at
+            .checked_add(delivery_window)
+            .ok_or(EscrowError::ArithmeticOverflow)?
+    } else {
+        0
+    };
+    escrow.state = EscrowState::Created;
+    escrow.description = description;
+    escrow.bump = bump;
+    escrow.vault = vault_token_account.key();
+    escrow.arbiter = arbiter
+        .or_else(|| config.as_ref().map(|config| config.default_arbiter))
+        .unwrap_or_default();
+    escrow.required_collateral = required_collateral;
+    if let Some(config) = config.as_mut() {
+        require!(
+            required_collateral >= config.min_collateral_for(amount),
+            EscrowError::CollateralTooLow
+        );
+        escrow.fee_bps = config.fee_bps_for(&buyer.key());
+        escrow.auto_confirm_after = config.auto_confirm_after;
+        escrow.rounding = config.rounding_policy;
+    }
+    require!(referrer_bps <= escrow.fee_bps, EscrowError::InvalidReferrerBps);
+    track(escrow, config, buyer_account)?;
+    escrow.milestones = milestones
+        .into_iter()
+        .map(|m| Milestone {
+            label: m.label,
+            amount: m.amount,
+            released: false,
+        })
+        .collect();
+
+    #[cfg(feature = "verbose-logs")]
+    msg!("escrow PDA {} (bump {}), vault {}", escrow.key(), bump, escrow.vault);
+
+    if deferred_funding {
+        msg!("Escrow created: awaiting deposits of {} tokens", amount);
+        return Ok(());
+    }
+
+    // Transfer funds to escrow vault
+    let cpi_accounts = Transfer {
+        from: buyer_token_account.to_account_info(),
+        to: vault_token_account.to_account_info(),
+        authority: buyer.to_account_info(),
+    };
+    let cpi_program = token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    transfer_tokens(cpi_ctx, amount)?;
+
+    msg!("Escrow created: {} tokens deposited", amount);
+    Ok(())
+}
+
+/// Moves a confirmed escrow into `Vesting` instead of paying the seller; the
+/// funds stay in the vault until `claim_vested`
+fn start_vesting(escrow: &mut Account<Escrow>) -> Result<()> {
+    let clock = Clock::get()?;
+    escrow.state = EscrowState::Vesting;
+    escrow.vesting_start = escrow.now(&clock);
+
+    msg!(
+        "Delivery confirmed, {} tokens vesting over {}s",
+        escrow.remaining_amount()?,
+        escrow.vesting_duration
+    );
+    Ok(())
+}
+
+/// Fails with `EmergencyMode` while the config, when passed, has emergency
+/// mode on. Only instructions that create, accept or pay out escrows call it.
+fn require_operational(config: &Option<Account<'_, Config>>) -> Result<()> {
+    require!(
+        !matches!(config, Some(config) if config.emergency_mode),
+        EscrowError::EmergencyMode
+    );
+    Ok(())
+}
+
+/// `require_operational` for an existing escrow. One counted in the config
+/// must be handled with it, so leaving the config out can't skip emergency
+/// mode or the active escrow count.
+fn require_operational_for(config: &Option<Account<'_, Config>>, escrow: &Escrow) -> Result<()> {
+    require!(
+        config.is_some() || !escrow.counted_in_config,
+        EscrowError::ConfigRequired
+    );
+    require_operational(config)
+}
+
+/// Fails with `UnauthorizedRelayer` when someone other than the buyer relays
+/// a confirmation past the config's relayer allowlist. An empty list allows
+/// any relayer. Escrows counted in the config must be relayed with it, so
+/// leaving the config out can't skip the list.
+fn require_relayer(config: &Option<Account<'_, Config>>, escrow: &Escrow, relayer: &Pubkey) -> Result<()> {
+    if *relayer == escrow.buyer {
+        return Ok(());
+    }
+    let allowed = match config {
+        Some(config) => {
+            config.relayer_allowlist.is_empty() || config.relayer_allowlist.contains(relayer)
+        }
+        None => !escrow.counted_in_config,
+    };
+    require!(allowed, EscrowError::UnauthorizedRelayer);
+    Ok(())
+}
+
+/// Checks shared by `deposit_additional` and `withdraw_partial`: only the
+/// buyer may change the amount, and only while no seller is committed.
+/// Milestone amounts must sum to the total, so their escrows stay fixed.
+fn require_adjustable(escrow: &Escrow, buyer: &Pubkey) -> Result<()> {
+    require!(!escrow.is_swap(), EscrowError::WrongEscrowKind);
+    require!(escrow.state == EscrowState::Created, EscrowError::InvalidState);
+    require!(escrow.buyer == *buyer, EscrowError::UnauthorizedBuyer);
+    require!(escrow.milestones.is_empty(), EscrowError::InvalidMilestone);
+    Ok(())
+}
+
+/// Fails with `CollateralTooLow` when the escrow's collateral is below the
+/// config's minimum for `amount`, as it would be at creation. Escrows
+/// counted in the config must be adjusted with it, so leaving it out can't
+/// skip the minimum.
+fn require_min_collateral(config: &Option<Account<'_, Config>>, escrow: &Escrow, amount: u64) -> Result<()> {
+    match config {
+        Some(config) => require!(
+            escrow.required_collateral >= config.min_collateral_for(amount),
+            EscrowError::CollateralTooLow
+        ),
+        None => require!(!escrow.counted_in_config, EscrowError::ConfigRequired),
+    }
+    Ok(())
+}
+
+/// Payout account an accepting seller asked to bind: an existing account, or
+/// their associated token account; passing both is ambiguous
+fn bound_payout<'info>(
+    payout_account: &Option<Account<'info, TokenAccount>>,
+    payout_ata: &Option<Account<'info, TokenAccount>>,
+) -> Result<Option<Pubkey>> {
+    match (payout_account, payout_ata) {
+        (Some(_), Some(_)) => err!(EscrowError::PayoutAccountConflict),
+        (Some(account), None) | (None, Some(account)) => Ok(Some(account.key())),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Opens a just-created escrow as accepted by the seller who pre-authorized
+/// its buyer
+fn accept_preauthorized(escrow: &mut Account<Escrow>, authorization: &SellerAuthorization) -> Result<()> {
+    let clock = Clock::get()?;
+    // The seller isn't signing, so there's no one to take collateral from
+    require!(escrow.required_collateral == 0, EscrowError::CollateralRequired);
+    escrow.can_accept(&authorization.seller, clock.unix_timestamp)?;
+
+    escrow.seller = authorization.seller;
+    escrow.state = EscrowState::Accepted;
+    escrow.record(TimelineEvent::Accepted, clock.unix_timestamp)?;
+    warn_if_expiring(escrow, clock.unix_timestamp);
+    msg!("Escrow auto-accepted for pre-authorized seller: {}", authorization.seller);
+    Ok(())
+}
+
+/// Closes a settled `auto_close` escrow and its vault, returning their rent
+/// to `rent_recipient`, which must be `Escrow::rent_recipient`. Does nothing
+/// for other escrows. A vault holding stray tokens is left open for
+/// `close_escrow`, which sweeps them to the buyer.
+fn auto_close<'info>(
+    escrow: &Account<'info, Escrow>,
+    vault: &mut Account<'info, TokenAccount>,
+    rent_recipient: Option<AccountInfo<'info>>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    if !escrow.auto_close {
+        return Ok(());
+    }
+    vault.reload()?;
+    if vault.amount > 0 {
+        msg!("Vault still holds {} tokens, left for close_escrow", vault.amount);
+        return Ok(());
+    }
+    let rent_recipient = rent_recipient.ok_or(EscrowError::RentRecipientMismatch)?;
+    require_keys_eq!(rent_recipient.key(), escrow.rent_recipient(), EscrowError::RentRecipientMismatch);
+
+    let seeds = &[
+        b"escrow",
+        escrow.seed_buyer().as_ref(),
+        escrow.mint.as_ref(),
+        escrow.seed_suffix(),
+        &[escrow.bump],
+    ];
+    let signer = &[&seeds[..]];
+    let cpi_accounts = CloseAccount {
+        account: vault.to_account_info(),
+        destination: rent_recipient.clone(),
+        authority: escrow.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+    token::close_account(cpi_ctx)?;
+    escrow.close(rent_recipient)?;
+
+    msg!("Escrow closed automatically");
+    Ok(())
+}
+
+/// Pays `amount` from the escrow vault to the seller, diverting the escrow's
+/// `fee_bps` share of it to the protocol fee vault, less the referrer's
+/// slice. Returns the fee taken, referrer's slice included.
+fn pay_seller<'info>(
+    escrow: &Account<'info, Escrow>,
+    vault: &Account<'info, TokenAccount>,
+    seller_token_account: &Account<'info, TokenAccount>,
+    fee_vault: Option<&Account<'info, TokenAccount>>,
+    referrer_token_account: Option<&Account<'info, TokenAccount>>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<u64> {
+    let (payout, protocol_fee, referral) = escrow.payout_split(amount)?;
+
+    let seeds = &[
+        b"escrow",
+        escrow.seed_buyer().as_ref(),
+        escrow.mint.as_ref(),
+        escrow.seed_suffix(),
+        &[escrow.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    if protocol_fee > 0 {
+        let fee_vault = fee_vault.ok_or(EscrowError::FeeVaultRequired)?;
+        let cpi_accounts = Transfer {
+            from: vault.to_account_info(),
+            to: fee_vault.to_account_info(),
+            authority: escrow.to_account_info(),
+        };
+        let cpi_ctx =
+            CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+        transfer_tokens(cpi_ctx, protocol_fee)?;
+    }
+
+    if referral > 0 {
+        let referrer_token_account =
+            referrer_token_account.ok_or(EscrowError::ReferrerAccountRequired)?;
+        let cpi_accounts = Transfer {
+            from: vault.to_account_info(),
+            to: referrer_token_account.to_account_info(),
+            authority: escrow.to_account_info(),
+        };
+        let cpi_ctx =
+            CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+        transfer_tokens(cpi_ctx, referral)?;
+    }
+
+    if payout > 0 {
+        let cpi_accounts = Transfer {
+            from: vault.to_account_info(),
+            to: seller_token_account.to_account_info(),
+            authority: escrow.to_account_info(),
+        };
+        let cpi_ctx =
+            CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+        transfer_tokens(cpi_ctx, payout)?;
+    }
+
+    Ok(protocol_fee + referral)
+}
+
+/// Refunds the unfulfilled part of a `partial_accept` to the buyer before the
+/// seller is paid, counting it as released so only the fulfilled amount remains
+fn refund_unfulfilled<'info>(
+    escrow: &mut Account<'info, Escrow>,
+    vault: &Account<'info, TokenAccount>,
+    buyer_token_account: Option<&Account<'info, TokenAccount>>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    let unfulfilled = escrow.unfulfilled_amount()?;
+    if unfulfilled == 0 {
+        return Ok(());
+    }
+    let buyer_token_account = buyer_token_account.ok_or(EscrowError::BuyerTokenAccountRequired)?;
+
+    let seeds = &[
+        b"escrow",
+        escrow.seed_buyer().as_ref(),
+        escrow.mint.as_ref(),
+        escrow.seed_suffix(),
+        &[escrow.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let cpi_accounts = Transfer {
+        from: vault.to_account_info(),
+        to: buyer_token_account.to_account_info(),
+        authority: escrow.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+    transfer_tokens(cpi_ctx, unfulfilled)?;
+
+    escrow.released_amount = escrow
+        .released_amount
+        .checked_add(unfulfilled)
+        .ok_or(EscrowError::ArithmeticOverflow)?;
+    msg!("Refunded {} unfulfilled tokens to buyer", unfulfilled);
+    Ok(())
+}
+
+/// `token::transfer`. With the `verbose-logs` feature it also logs both
+/// accounts' balances and the signing authority (the escrow PDA for vault
+/// payouts) around the transfer; without it this compiles to the bare CPI.
+fn transfer_tokens<'info>(
+    cpi_ctx: CpiContext<'_, '_, '_, 'info, Transfer<'info>>,
+    amount: u64,
+) -> Result<()> {
+    #[cfg(feature = "verbose-logs")]
+    let (from, to) = (cpi_ctx.accounts.from.clone(), cpi_ctx.accounts.to.clone());
+    #[cfg(feature = "verbose-logs")]
+    {
+        msg!("transfer {} by {}", amount, cpi_ctx.accounts.authority.key);
+        log_balances("before", &from, &to);
+    }
+
+    token::transfer(cpi_ctx, amount)?;
+
+    #[cfg(feature = "verbose-logs")]
+    log_balances("after", &from, &to);
+    Ok(())
+}
+
+/// Token balance held in `account`, if it is a token account
+#[cfg(feature = "verbose-logs")]
+fn token_balance(account: &AccountInfo) -> Option<u64> {
+    let data = account.try_borrow_data().ok()?;
+    TokenAccount::try_deserialize_unchecked(&mut &data[..])
+        .ok()
+        .map(|token_account| token_account.amount)
+}
+
+#[cfg(feature = "verbose-logs")]
+fn log_balances(stage: &str, from: &AccountInfo, to: &AccountInfo) {
+    msg!(
+        "{}: {} holds {:?}, {} holds {:?}",
+        stage,
+        from.key,
+        token_balance(from),
+        to.key,
+        token_balance(to)
+    );
+}
+
+/// Returns the seller's acceptance collateral on settling at `now`, less any
+/// SLA penalty, which goes to the buyer
+fn return_collateral<'info>(
+    escrow: &Account<'info, Escrow>,
+    vault: &Account<'info, TokenAccount>,
+    seller_token_account: &Account<'info, TokenAccount>,
+    buyer_token_account: Option<&Account<'info, TokenAccount>>,
+    token_program: &Program<'info, Token>,
+    now: i64,
+) -> Result<()> {
+    let penalty = escrow.collateral_penalty(now)?;
+    release_collateral(escrow, vault, seller_token_account, token_program, escrow.collateral_kept(now)?)?;
+    if penalty > 0 {
+        let buyer_token_account = buyer_token_account.ok_or(EscrowError::BuyerTokenAccountRequired)?;
+        release_collateral(escrow, vault, buyer_token_account, token_program, penalty)?;
+        msg!("Seller missed the delivery SLA: {} collateral to the buyer", penalty);
+    }
+    Ok(())
+}
+
+/// Moves `amount` of the seller's acceptance collateral out of the vault to `to`
+fn release_collateral<'info>(
+    escrow: &Account<'info, Escrow>,
+    vault: &Account<'info, TokenAccount>,
+    to: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let seeds = &[
+        b"escrow",
+        escrow.seed_buyer().as_ref(),
+        escrow.mint.as_ref(),
+        escrow.seed_suffix(),
+        &[escrow.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let cpi_accounts = Transfer {
+        from: vault.to_account_info(),
+        to: to.to_account_info(),
+        authority: escrow.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+    transfer_tokens(cpi_ctx, amount)
+}
+
+/// Emits `EscrowExpiringSoon` the first time an instruction runs inside the
+/// escrow's warning window
+fn warn_if_expiring(escrow: &mut Account<Escrow>, now: i64) {
+    if escrow.take_expiry_warning(now) {
+        emit!(EscrowExpiringSoon {
+            schema_version: EVENT_SCHEMA_VERSION,
+            escrow: escrow.key(),
+            buyer: escrow.buyer,
+            seller: escrow.seller,
+            timeout_at: escrow.timeout_at,
+        });
+    }
+}
+
+/// Counts a new escrow in the config and in its buyer's `BuyerAccount`,
+/// which `untrack` undoes when it settles. Fails with `TooManyActiveEscrows`
+/// once the buyer is at the config's `max_active_per_buyer`.
+fn track(
+    escrow: &mut Escrow,
+    config: &mut Option<Account<Config>>,
+    buyer_account: &mut Option<Account<BuyerAccount>>,
+) -> Result<()> {
+    if let Some(config) = config.as_mut() {
+        config.active_escrows = config
+            .active_escrows
+            .checked_add(1)
+            .ok_or(EscrowError::ArithmeticOverflow)?;
+        escrow.counted_in_config = true;
+    }
+    let max_active = config.as_ref().map_or(0, |config| config.max_active_per_buyer);
+    if let Some(buyer_account) = buyer_account.as_mut() {
+        buyer_account.active_count = buyer_account
+            .active_count
+            .checked_add(1)
+            .ok_or(EscrowError::ArithmeticOverflow)?;
+        require!(
+            max_active == 0 || buyer_account.active_count <= max_active,
+            EscrowError::TooManyActiveEscrows
+        );
+        escrow.counted_for_buyer = true;
+    } else {
+        require!(max_active == 0, EscrowError::BuyerAccountRequired);
+    }
+    Ok(())
+}
+
+/// Drops a settled escrow from the config's and its buyer's active counts,
+/// if it was counted. Neither count has another way down, so a counted
+/// escrow can't settle without the config or `BuyerAccount` it is counted in.
+fn untrack(
+    escrow: &mut Escrow,
+    config: &mut Option<Account<Config>>,
+    buyer_account: &mut Option<Account<BuyerAccount>>,
+) -> Result<()> {
+    if escrow.counted_in_config {
+        let config = config.as_mut().ok_or(EscrowError::ConfigRequired)?;
+        config.active_escrows = config.active_escrows.saturating_sub(1);
+        escrow.counted_in_config = false;
+    }
+    if escrow.counted_for_buyer {
+        let buyer_account = buyer_account.as_mut().ok_or(EscrowError::BuyerAccountRequired)?;
+        buyer_account.active_count = buyer_account.active_count.saturating_sub(1);
+        escrow.counted_for_buyer = false;
+    }
+    Ok(())
+}
+
+/// Most (escrow, vault, payout) triples `resolve_timeouts_batch` takes; each
+/// costs a token transfer and an escrow re-serialization
+pub const MAX_BATCH_RESOLVE: usize = 8;
+
+/// First seed of the PDA a `route_to_program` derives for each seller; token
+/// accounts it owns may receive that seller's payouts
+pub const PAYOUT_ROUTE_SEED: &[u8] = b"escrow-payout";
+
+/// Most escrows `accept_escrows_batch` takes; each costs an escrow
+/// re-serialization but no transfer
+pub const MAX_BATCH_ACCEPT: usize = 16;
+
+/// Message the buyer signs off-chain to authorize `confirm_delivery_signed`:
+/// the escrow address followed by the little-endian `nonce`
+pub fn signed_confirm_message(escrow: &Pubkey, nonce: u64) -> Vec<u8> {
+    let mut message = escrow.to_bytes().to_vec();
+    message.extend_from_slice(&nonce.to_le_bytes());
+    message
+}
+
+/// Checks that the instruction immediately before the current one is an
+/// Ed25519 sig-verify of `message` by `signer`, with every offset pointing
+/// into that instruction's own data.
+fn verify_ed25519_instruction(
+    instructions: &AccountInfo,
+    signer: &Pubkey,
+    message: &[u8],
+) -> Result<()> {
+    let current = load_current_index_checked(instructions)? as usize;
+    require!(current > 0, EscrowError::InvalidBuyerSignature);
+    let ix = load_instruction_at_checked(current - 1, instructions)?;
+    require_keys_eq!(ix.program_id, ed25519_program::ID, EscrowError::InvalidBuyerSignature);
+
+    // Layout: num_signatures (u8), padding (u8), then one 14-byte offsets
+    // struct of seven little-endian u16s
+    let data = &ix.data;
+    require!(data.len() >= 16 && data[0] == 1, EscrowError::InvalidBuyerSignature);
+    let read_u16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+
+    let signature_ix = read_u16(4);
+    let pubkey_offset = read_u16(6) as usize;
+    let pubkey_ix = read_u16(8);
+    let message_offset = read_u16(10) as usize;
+    let message_size = read_u16(12) as usize;
+    let message_ix = read_u16(14);
+    require!(
+        signature_ix == u16::MAX && pubkey_ix == u16::MAX && message_ix == u16::MAX,
+        EscrowError::InvalidBuyerSignature
+    );
+
+    let signed_pubkey = data.get(pubkey_offset..pubkey_offset + 32);
+    let signed_message = data.get(message_offset..message_offset + message_size);
+    require!(
+        signed_pubkey == Some(signer.as_ref()) && signed_message == Some(message),
+        EscrowError::InvalidBuyerSignature
+    );
+    Ok(())
+}
+
+#[account]
+pub struct Escrow {
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub state: EscrowState,
+    /// Lifecycle events in the order they fired; only fired events use space
+    pub timeline: Vec<TimelineEntry>,
+    pub timeout_at: i64,
+    pub description: String,
+    pub bump: u8,
+    /// Token B mint for swap escrows; default for standard escrows
+    pub swap_mint: Pubkey,
+    /// Token B amount the seller deposits on acceptance
+    pub swap_amount: u64,
+    /// Minimum token B the swap vault must receive for acceptance to succeed
+    pub swap_min_receive: u64,
+    /// Last timestamp at which a seller may accept the swap
+    pub swap_valid_until: i64,
+    /// Total already released from the deposit: paid to the seller, or
+    /// refunded to the buyer as the unfulfilled part of a `partial_accept`
+    pub released_amount: u64,
+    /// Optional payment schedule; empty for lump-sum escrows
+    pub milestones: Vec<Milestone>,
+    /// Resolves disputes; default when disputes are disabled
+    pub arbiter: Pubkey,
+    /// Protocol fee on seller payouts, snapshotted from config at creation
+    pub fee_bps: u16,
+    /// Whether this escrow is included in `Config::active_escrows`
+    pub counted_in_config: bool,
+    /// Seconds before `timeout_at` at which `EscrowExpiringSoon` fires; 0 disables it
+    pub warn_lead: i64,
+    /// Set once `EscrowExpiringSoon` has been emitted
+    pub warned: bool,
+    /// When the seller last called `request_confirmation`; 0 if never
+    pub confirmation_requested_at: i64,
+    /// Seconds after a confirmation request the seller may self-release; 0 disables it
+    pub auto_confirm_after: i64,
+    /// Who `resolve_timeout` pays. Added in v2; v1 accounts migrate to `Seller`.
+    pub timeout_beneficiary: TimeoutBeneficiary,
+    /// Token account holding the deposit: the vault PDA, or the account
+    /// supplied to `create_escrow_with_vault`
+    pub vault: Pubkey,
+    /// Latest deadline `extend_timeout` may set; 0 on migrated v1 escrows
+    pub max_timeout_at: i64,
+    /// Escrow this one was split from; default for top-level escrows
+    pub parent: Pubkey,
+    /// Collateral the seller deposits into the vault on acceptance
+    pub required_collateral: u64,
+    /// Wallet that funded the deposit; set at creation and never changed
+    pub original_depositor: Pubkey,
+    /// Whether anyone may call `resolve_timeout`, or only the buyer and seller
+    pub permissionless_timeout: bool,
+    /// Nonce the next `confirm_delivery_signed` signature must carry. Starts
+    /// at the creation slot, so an escrow re-created at the same address never
+    /// accepts a signature made for an earlier one.
+    pub confirm_nonce: u64,
+    /// Time by which the seller must `mark_delivered`; 0 if there is none
+    pub delivery_deadline: i64,
+    /// When the seller called `mark_delivered`; 0 if not yet
+    pub delivered_at: i64,
+    /// Rounding for fees and dispute splits, snapshotted from the config
+    pub rounding: RoundingPolicy,
+    /// What the seller submitted via `deliver_secret`; empty if nothing yet
+    pub delivery_payload: Vec<u8>,
+    /// Commitment the seller submitted via `submit_shipment`; zero if none
+    pub shipment_commitment: [u8; 32],
+    /// Seconds confirmed funds vest over; 0 pays them out on confirmation
+    pub vesting_duration: i64,
+    /// When confirmation started the vesting; 0 if it hasn't
+    pub vesting_start: i64,
+    /// Vested funds the seller has claimed so far
+    pub claimed_amount: u64,
+    /// Part of `amount` the seller committed to via `partial_accept`; 0 for
+    /// a full acceptance
+    pub fulfilled_amount: u64,
+    /// Seconds after creation during which no seller may accept
+    pub accept_lock: i64,
+    /// Buyer the PDA was derived from, recorded when `transfer_escrow_rights`
+    /// first hands the buyer role on; default until then
+    pub original_buyer: Pubkey,
+    /// Seconds after `delivered_at` the buyer has to confirm or dispute
+    /// before the delivery is deemed accepted; 0 disables it
+    pub dispute_window: i64,
+    /// Earliest time a seller may accept, and what the timeout runs from;
+    /// the creation time unless one was scheduled, 0 on older escrows
+    pub start_at: i64,
+    /// Token account the seller bound at acceptance as the only payout
+    /// destination; default if any of the seller's accounts will do
+    pub seller_payout_account: Pubkey,
+    /// Share of the collateral returned by when the seller marked delivery,
+    /// tightest window first; empty returns all of it
+    pub sla_tiers: Vec<SlaTier>,
+    /// Advisory urgency set at creation, for keepers to order their work by;
+    /// the program never reads it
+    pub priority: u8,
+    /// Program whose `[PAYOUT_ROUTE_SEED, seller]` PDA may own the seller's
+    /// payout accounts; default if only the seller may
+    pub route_to_program: Pubkey,
+    /// Whether this escrow is included in its creating buyer's `BuyerAccount::active_count`
+    pub counted_for_buyer: bool,
+    /// Why the last seller to accept backed out with `decline_escrow`; none if no one has
+    pub decline_reason: Option<DeclineReason>,
+    /// Whether the seller is a multisig authority paid through `seller_treasury`
+    pub seller_is_multisig: bool,
+    /// Owner of the token accounts a multisig seller is paid into; default if none
+    pub seller_treasury: Pubkey,
+    /// Longest an accepted seller may go between heartbeats before the buyer
+    /// may `claim_abandoned`; 0 disables it
+    pub heartbeat_interval: i64,
+    /// When the seller last called `heartbeat`; 0 if never
+    pub last_heartbeat_at: i64,
+    /// Who referred the escrow; default if no one
+    pub referrer: Pubkey,
+    /// Referrer's slice of `fee_bps`, paid out of the protocol fee
+    pub referrer_bps: u16,
+    /// Wait between the buyer's `request_withdrawal` and
+    /// `execute_withdrawal`; 0 disables withdrawals
+    pub withdraw_delay: i64,
+    /// When the buyer last called `request_withdrawal`; 0 if never
+    pub withdrawal_requested_at: i64,
+    /// How long the buyer may go quiet after a marked delivery before the
+    /// seller may `claim_inactive_buyer`; 0 disables it
+    pub buyer_inactivity_timeout: i64,
+    /// When the buyer last acted on the escrow without settling it; 0 if never
+    pub buyer_active_at: i64,
+    /// Set by the arbiter's `request_evidence`, cleared by `submit_evidence`
+    pub evidence_requested: bool,
+    /// Latest evidence a party submitted to the arbiter; empty if none
+    pub evidence_uri: String,
+    /// Relayer that reimbursed the buyer for the escrow's rent and gets it
+    /// back on close; default if the buyer paid it
+    pub rent_payer: Pubkey,
+    /// Part of `amount` a deferred-funding escrow still awaits in deposits
+    pub unfunded_amount: u64,
+    /// Close the escrow and vault in the instruction that settles it
+    pub auto_close: bool,
+    /// While sealed bidding is open, when bid commits close and reveals
+    /// open; 0 otherwise
+    pub bid_reveal_at: i64,
+}
+
+impl Escrow {
+    /// Created, Accepted, Disputed and one terminal event
+    pub const MAX_TIMELINE_ENTRIES: usize = 4;
+
+    /// Size of v1 accounts, created before `timeout_beneficiary` existed.
+    /// Frozen: new fields are appended and only grow `LEN`.
+    pub const LEN_V1: usize = 32 + 32 + 32 + 8 + 1
+        + (4 + Self::MAX_TIMELINE_ENTRIES * TimelineEntry::LEN)
+        + 8 + (4 + Self::MAX_DESCRIPTION_LEN) + 1 + 32 + 8 + 8 + 8
+        + 8 + (4 + Self::MAX_MILESTONES * Milestone::LEN) + 32 + 2 + 1 + 8 + 1 + 8 + 8;
+
+    pub const LEN: usize = Self::LEN_V1 + 1 + 32 + 8 + 32 + 8 + 32 + 1 + 8 + 8 + 8 + 1
+        + (4 + Self::MAX_DELIVERY_PAYLOAD_LEN) + 32 + 8 + 8 + 8 + 8 + 8 + 32 + 8 + 8 + 32
+        + (4 + Self::MAX_SLA_TIERS * SlaTier::LEN) + 1 + 32 + 1 + 2 + 1 + 32 + 8 + 8 + 32 + 2 + 8 + 8 + 8 + 8
+        + 1 + (4 + Self::MAX_EVIDENCE_URI_LEN) + 32 + 8 + 1 + 8;
+
+    /// Furthest past creation `extend_timeout` can push the deadline
+    pub const MAX_TOTAL_DURATION: i64 = 90 * 24 * 60 * 60;
+
+    pub const MAX_MILESTONES: usize = 5;
+
+    pub const MAX_SLA_TIERS: usize = 3;
+
+    pub const MAX_DESCRIPTION_LEN: usize = 200;
+
+    pub const MAX_EVIDENCE_URI_LEN: usize = 200;
+
+    /// Longest description `expand_description` accepts
+    pub const MAX_EXPANDED_DESCRIPTION_LEN: usize = 1024;
+
+    /// Account size an escrow created with `params` needs. `LEN` reserves the
+    /// most milestones and SLA tiers an escrow may have; both are fixed at
+    /// creation, so only the slots `params` fills are allocated. Everything
+    /// that can still grow later keeps its full reserve.
+    pub fn space_for(params: &CreateParams) -> usize {
+        Self::space(params.milestones.len(), params.sla_tiers.len(), 0)
+    }
+
+    /// Account size this escrow needs once its description is
+    /// `description_len` bytes; a description within `MAX_DESCRIPTION_LEN`
+    /// takes no more than its reserve
+    pub fn space_with_description(&self, description_len: usize) -> usize {
+        Self::space(self.milestones.len(), self.sla_tiers.len(), description_len)
+    }
+
+    fn space(milestones: usize, sla_tiers: usize, description_len: usize) -> usize {
+        8 + Self::LEN
+            - Self::MAX_MILESTONES.saturating_sub(milestones) * Milestone::LEN
+            - Self::MAX_SLA_TIERS.saturating_sub(sla_tiers) * SlaTier::LEN
+            + description_len.saturating_sub(Self::MAX_DESCRIPTION_LEN)
+    }
+
+    pub const MAX_DELIVERY_PAYLOAD_LEN: usize = 256;
+
+    /// Whether the delivered payload's SHA-256 is `payload_hash`
+    pub fn payload_matches(&self, payload_hash: &[u8; 32]) -> bool {
+        solana_sha256_hasher::hash(&self.delivery_payload).to_bytes() == *payload_hash
+    }
+
+    pub fn is_swap(&self) -> bool {
+        self.swap_mint != Pubkey::default()
+    }
+
+    /// Acceptance rules shared by the accept instructions and `check_can_accept`
+    pub fn can_accept(&self, seller: &Pubkey, now: i64) -> core::result::Result<(), EscrowError> {
+        if self.state != EscrowState::Created {
+            return Err(EscrowError::InvalidState);
+        }
+        if self.seller != Pubkey::default() {
+            return Err(EscrowError::AlreadyAccepted);
+        }
+        if *seller == self.buyer {
+            return Err(EscrowError::SellerCannotBeBuyer);
+        }
+        // Sealed bidding only ends in `select_bid`
+        if self.bid_reveal_at > 0 {
+            return Err(EscrowError::SealedBidding);
+        }
+        if self.unfunded_amount > 0 {
+            return Err(EscrowError::NotFullyFunded);
+        }
+        if now < self.start_at {
+            return Err(EscrowError::NotYetStarted);
+        }
+        // Gives the buyer a window to cancel before anyone can commit
+        let lock_ends_at = self
+            .created_at()
+            .checked_add(self.accept_lock)
+            .ok_or(EscrowError::ArithmeticOverflow)?;
+        if now < lock_ends_at {
+            return Err(EscrowError::AcceptLockActive);
+        }
+        // Accepting after the deadline would make it resolvable at once
+        if now >= self.timeout_at {
+            return Err(EscrowError::AlreadyExpired);
+        }
+        if self.is_swap() && now > self.swap_valid_until {
+            return Err(EscrowError::SwapExpired);
+        }
+        Ok(())
+    }
+
+    /// Wallet the escrow's rent returns to on close: the sponsor who paid it,
+    /// or else the buyer
+    pub fn rent_recipient(&self) -> Pubkey {
+        if self.rent_payer == Pubkey::default() {
+            self.buyer
+        } else {
+            self.rent_payer
+        }
+    }
+
+    /// Wallet cancellation refunds go to: the funding wallet, or the current
+    /// buyer once `transfer_escrow_rights` has assigned the claim away. Falls
+    /// back to the buyer for accounts created before `original_depositor`
+    /// was recorded.
+    pub fn depositor(&self) -> Pubkey {
+        if self.original_buyer != Pubkey::default() || self.original_depositor == Pubkey::default() {
+            self.buyer
+        } else {
+            self.original_depositor
+        }
+    }
+
+    /// Whether the escrow has settled and can no longer move funds
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self.state,
+            EscrowState::Completed
+                | EscrowState::Cancelled
+                | EscrowState::TimedOut
+                | EscrowState::Resolved
+        )
+    }
+
+    /// Buyer key in the escrow's PDA seeds, which stays fixed when
+    /// `transfer_escrow_rights` moves the buyer role to another wallet
+    pub fn seed_buyer(&self) -> &Pubkey {
+        if self.original_buyer == Pubkey::default() {
+            &self.buyer
+        } else {
+            &self.original_buyer
+        }
+    }
+
+    /// Whether the seller may be paid to `account`: any of their accounts,
+    /// unless they bound one at acceptance
+    pub fn accepts_payout_to(&self, account: &Pubkey) -> bool {
+        self.seller_payout_account == Pubkey::default() || self.seller_payout_account == *account
+    }
+
+    /// Whether a token account owned by `owner` may receive `seller`'s
+    /// payouts: the seller's own, a multisig seller's treasury, or with
+    /// `route_to_program` set, one owned by that program's
+    /// `[PAYOUT_ROUTE_SEED, seller]` PDA
+    pub fn is_payout_owner(&self, seller: &Pubkey, owner: &Pubkey) -> bool {
+        if owner == seller || (self.seller_is_multisig && *owner == self.seller_treasury) {
+            return true;
+        }
+        self.route_to_program != Pubkey::default()
+            && *owner
+                == Pubkey::find_program_address(&[PAYOUT_ROUTE_SEED, seller.as_ref()], &self.route_to_program).0
+    }
+
+    /// Collateral the seller gets back on settling at `now`: the `keep_bps`
+    /// share of the first SLA tier whose window, counted from acceptance,
+    /// covers the delivery, and nothing past the last tier. A delivery never
+    /// marked counts as made at `now`. Without tiers it all comes back.
+    pub fn collateral_kept(&self, now: i64) -> core::result::Result<u64, EscrowError> {
+        if self.sla_tiers.is_empty() {
+            return Ok(self.required_collateral);
+        }
+        let delivered_at = if self.delivered_at > 0 { self.delivered_at } else { now };
+        let elapsed = delivered_at
+            .checked_sub(self.accepted_at())
+            .ok_or(EscrowError::ArithmeticOverflow)?;
+        match self.sla_tiers.iter().find(|tier| elapsed <= tier.window) {
+            Some(tier) => self.rounding.bps_of(self.required_collateral, tier.keep_bps, true),
+            None => Ok(0),
+        }
+    }
+
+    /// Collateral forfeited to the buyer under the SLA tiers on settling at `now`
+    pub fn collateral_penalty(&self, now: i64) -> core::result::Result<u64, EscrowError> {
+        self.required_collateral
+            .checked_sub(self.collateral_kept(now)?)
+            .ok_or(EscrowError::ArithmeticOverflow)
+    }
+
+    /// Whether `other` can be folded into this escrow by `merge_escrows`:
+    /// same parties and mint, no milestones, and terms that settle alike
+    pub fn can_merge(&self, other: &Escrow) -> bool {
+        self.buyer == other.buyer
+            && self.original_depositor == other.original_depositor
+            && self.mint == other.mint
+            && self.milestones.is_empty()
+            && other.milestones.is_empty()
+            && self.arbiter == other.arbiter
+            && self.fee_bps == other.fee_bps
+            && self.rounding == other.rounding
+            && self.timeout_beneficiary == other.timeout_beneficiary
+            && self.permissionless_timeout == other.permissionless_timeout
+            && self.auto_confirm_after == other.auto_confirm_after
+            && self.vesting_duration == other.vesting_duration
+            && self.dispute_window == other.dispute_window
+            && self.sla_tiers == other.sla_tiers
+    }
+
+    /// Trailing PDA seed: empty for top-level escrows, so their address stays
+    /// `[b"escrow", buyer, mint]`, and the parent's address for escrows created
+    /// by `split_escrow`
+    pub fn seed_suffix(&self) -> &[u8] {
+        if self.parent == Pubkey::default() {
+            &[]
+        } else {
+            self.parent.as_ref()
+        }
+    }
+
+    /// Total vested by `now`, claimed or not: a linear share of what was left
+    /// at confirmation. Without a vesting duration everything is vested.
+    pub fn vested_amount(&self, now: i64) -> core::result::Result<u64, EscrowError> {
+        let total = self
+            .claimed_amount
+            .checked_add(self.remaining_amount()?)
+            .ok_or(EscrowError::ArithmeticOverflow)?;
+        if self.vesting_duration <= 0 {
+            return Ok(total);
+        }
+        let elapsed = now
+            .checked_sub(self.vesting_start)
+            .ok_or(EscrowError::ArithmeticOverflow)?
+            .clamp(0, self.vesting_duration);
+        if elapsed == self.vesting_duration {
+            return Ok(total);
+        }
+        // Below `total`, since elapsed < vesting_duration
+        u64::try_from(total as u128 * elapsed as u128 / self.vesting_duration as u128)
+            .map_err(|_| EscrowError::ArithmeticOverflow)
+    }
+
+    /// Part of the schedule that hasn't vested by `now`, which is all a buyer
+    /// cancelling a `Vesting` escrow can take back; vested but unclaimed funds
+    /// belong to the seller
+    pub fn unvested_amount(&self, now: i64) -> core::result::Result<u64, EscrowError> {
+        self.claimed_amount
+            .checked_add(self.remaining_amount()?)
+            .and_then(|total| total.checked_sub(self.vested_amount(now).ok()?))
+            .ok_or(EscrowError::ArithmeticOverflow)
+    }
+
+    /// Part of the deposit the seller didn't commit to, still due back to
+    /// the buyer; 0 after a full acceptance or once refunded
+    pub fn unfulfilled_amount(&self) -> core::result::Result<u64, EscrowError> {
+        if self.fulfilled_amount == 0 {
+            return Ok(0);
+        }
+        Ok(self.remaining_amount()?.saturating_sub(self.fulfilled_amount))
+    }
+
+    /// Amount still held for the seller. Fails if more was released than
+    /// deposited, which only a corrupted account can show.
+    pub fn remaining_amount(&self) -> core::result::Result<u64, EscrowError> {
+        self.amount
+            .checked_sub(self.released_amount)
+            .ok_or(EscrowError::ArithmeticOverflow)
+    }
+
+    /// What the vault should hold: the unreleased amount not still awaiting
+    /// deposit, plus the seller's collateral once it has been deposited on
+    /// acceptance
+    pub fn held_amount(&self) -> core::result::Result<u64, EscrowError> {
+        let collateral = match self.state {
+            EscrowState::Accepted | EscrowState::Disputed => self.required_collateral,
+            _ => 0,
+        };
+        self.remaining_amount()?
+            .checked_sub(self.unfunded_amount)
+            .and_then(|funded| funded.checked_add(collateral))
+            .ok_or(EscrowError::ArithmeticOverflow)
+    }
+
+    /// Earliest time `resolve_timeout` may release to the seller: the timeout,
+    /// or sooner if an auto-confirm window has run since a confirmation request
+    /// or the dispute window has run since delivery. A window that ends past
+    /// `i64::MAX` never ends, so it can't bring the deadline forward.
+    pub fn release_deadline(&self) -> i64 {
+        let mut deadline = self.timeout_at;
+        if self.auto_confirm_after > 0 && self.confirmation_requested_at > 0 {
+            if let Some(auto_confirm_at) =
+                self.confirmation_requested_at.checked_add(self.auto_confirm_after)
+            {
+                deadline = deadline.min(auto_confirm_at);
+            }
+        }
+        if let Some(deemed_accepted_at) = self.deemed_accepted_at() {
+            deadline = deadline.min(deemed_accepted_at);
+        }
+        deadline
+    }
+
+    /// When the delivery counts as accepted because the buyer neither
+    /// confirmed nor disputed within the dispute window; None without a
+    /// window or before delivery
+    pub fn deemed_accepted_at(&self) -> Option<i64> {
+        if self.dispute_window > 0 && self.delivered_at > 0 {
+            self.delivered_at.checked_add(self.dispute_window)
+        } else {
+            None
+        }
+    }
+
+    /// Who `resolve_timeout` pays at `now`: the seller once the delivery is
+    /// deemed accepted, otherwise the timeout beneficiary
+    pub fn timeout_payee(&self, now: i64) -> TimeoutBeneficiary {
+        match self.deemed_accepted_at() {
+            Some(deemed_accepted_at) if now >= deemed_accepted_at => TimeoutBeneficiary::Seller,
+            _ => self.timeout_beneficiary,
+        }
+    }
+
+    /// Whether `now` is inside the warning window and the warning hasn't fired
+    /// yet; marks it fired so each escrow warns at most once
+    pub fn take_expiry_warning(&mut self, now: i64) -> bool {
+        let in_window = self
+            .timeout_at
+            .checked_sub(now)
+            .is_some_and(|left| left < self.warn_lead);
+        if self.warned || self.warn_lead <= 0 || !in_window {
+            return false;
+        }
+        self.warned = true;
+        true
+    }
+
+    /// Clock time for a timestamp stored on this escrow. Local and warped
+    /// test validators can move `unix_timestamp` backwards, so this never
+    /// returns earlier than the latest recorded event.
+    pub fn now(&self, clock: &Clock) -> i64 {
+        self.clamp_timestamp(clock.unix_timestamp)
+    }
+
+    fn clamp_timestamp(&self, timestamp: i64) -> i64 {
+        self.timeline
+            .last()
+            .map_or(timestamp, |last| timestamp.max(last.timestamp))
+    }
+
+    /// Appends a lifecycle event to the timeline, clamped so events never
+    /// precede earlier ones (`completed_at` is never before `created_at`)
+    pub fn record(&mut self, event: TimelineEvent, timestamp: i64) -> Result<()> {
+        require!(
+            self.timeline.len() < Self::MAX_TIMELINE_ENTRIES,
+            EscrowError::TimelineFull
+        );
+        let timestamp = self.clamp_timestamp(timestamp);
+        self.timeline.push(TimelineEntry { event, timestamp });
+        Ok(())
+    }
+
+    /// Referrer's slice of the fee on a seller payout of `amount`. A fee
+    /// lowered after creation caps the slice, so the referrer never takes
+    /// more than the whole fee.
+    pub fn referral_of(&self, amount: u64) -> core::result::Result<u64, EscrowError> {
+        self.rounding
+            .bps_of(amount, self.referrer_bps.min(self.fee_bps), false)
+    }
+
+    /// Splits a seller payout of `amount` into what the seller, the fee
+    /// vault and the referrer receive, in that order
+    pub fn payout_split(&self, amount: u64) -> core::result::Result<(u64, u64, u64), EscrowError> {
+        let fee = self.rounding.bps_of(amount, self.fee_bps, false)?;
+        let payout = amount.checked_sub(fee).ok_or(EscrowError::ArithmeticOverflow)?;
+        let referral = self.referral_of(amount)?;
+        let protocol_fee = fee.checked_sub(referral).ok_or(EscrowError::ArithmeticOverflow)?;
+        Ok((payout, protocol_fee, referral))
+    }
+
+    /// Where the vault's tokens go on confirming at `now`: the unfulfilled
+    /// refund, the fee split of what's left, and the collateral by SLA tier.
+    /// With vesting the seller's share vests rather than paying out at once.
+    pub fn settlement_breakdown(&self, now: i64) -> core::result::Result<SettlementBreakdown, EscrowError> {
+        let buyer_refund = self.unfulfilled_amount()?;
+        let paid = self
+            .remaining_amount()?
+            .checked_sub(buyer_refund)
+            .ok_or(EscrowError::ArithmeticOverflow)?;
+        let (seller_payout, protocol_fee, referrer_fee) = self.payout_split(paid)?;
+        Ok(SettlementBreakdown {
+            seller_payout,
+            protocol_fee,
+            referrer_fee,
+            buyer_refund,
+            collateral_returned: self.collateral_kept(now)?,
+            collateral_penalty: self.collateral_penalty(now)?,
+        })
+    }
+
+    /// Latest the seller's next `heartbeat` may come: an interval after the
+    /// last one, or after acceptance before the first. `None` while no
+    /// heartbeat is owed: disabled, not accepted, or delivery marked.
+    pub fn heartbeat_due_at(&self) -> core::result::Result<Option<i64>, EscrowError> {
+        if self.heartbeat_interval <= 0 || self.state != EscrowState::Accepted || self.delivered_at > 0 {
+            return Ok(None);
+        }
+        self.last_heartbeat_at
+            .max(self.accepted_at())
+            .checked_add(self.heartbeat_interval)
+            .map(Some)
+            .ok_or(EscrowError::ArithmeticOverflow)
+    }
+
+    /// When a pending `request_withdrawal` may execute. `None` while none is
+    /// pending or the seller contested it by marking delivery or disputing.
+    pub fn withdrawal_executable_at(&self) -> core::result::Result<Option<i64>, EscrowError> {
+        if self.withdrawal_requested_at == 0 || self.state != EscrowState::Accepted || self.delivered_at > 0 {
+            return Ok(None);
+        }
+        self.withdrawal_requested_at
+            .checked_add(self.withdraw_delay)
+            .map(Some)
+            .ok_or(EscrowError::ArithmeticOverflow)
+    }
+
+    /// When the seller may `claim_inactive_buyer`: the inactivity timeout
+    /// after the later of the marked delivery and the buyer's last action.
+    /// `None` while the escrow isn't accepted or has no timeout.
+    pub fn buyer_inactive_at(&self) -> core::result::Result<Option<i64>, EscrowError> {
+        if self.buyer_inactivity_timeout <= 0 || self.state != EscrowState::Accepted {
+            return Ok(None);
+        }
+        if self.delivered_at == 0 {
+            return Err(EscrowError::NotDelivered);
+        }
+        self.delivered_at
+            .max(self.buyer_active_at)
+            .checked_add(self.buyer_inactivity_timeout)
+            .map(Some)
+            .ok_or(EscrowError::ArithmeticOverflow)
+    }
+
+    /// Timestamp of the first occurrence of `event`, or 0 if it never fired
+    pub fn event_at(&self, event: TimelineEvent) -> i64 {
+        self.timeline
+            .iter()
+            .find(|entry| entry.event == event)
+            .map_or(0, |entry| entry.timestamp)
+    }
+
+    pub fn created_at(&self) -> i64 {
+        self.event_at(TimelineEvent::Created)
+    }
+
+    pub fn accepted_at(&self) -> i64 {
+        self.event_at(TimelineEvent::Accepted)
+    }
+
+    /// Settlement time, whether by confirmation, timeout or arbitration
+    pub fn completed_at(&self) -> i64 {
+        self.timeline
+            .iter()
+            .find(|entry| {
+                matches!(
+                    entry.event,
+                    TimelineEvent::Completed | TimelineEvent::TimedOut | TimelineEvent::Resolved
+                )
+            })
+            .map_or(0, |entry| entry.timestamp)
+    }
+
+    pub fn disputed_at(&self) -> i64 {
+        self.event_at(TimelineEvent::Disputed)
+    }
+
+    pub fn cancelled_at(&self) -> i64 {
+        self.event_at(TimelineEvent::Cancelled)
+    }
+}
+
+/// Result of `check_can_accept`; `reason` is the `EscrowError` code that
+/// acceptance would fail with, or 0 when allowed
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct AcceptCheck {
+    pub allowed: bool,
+    pub reason: u32,
+}
+
+/// Result of `get_timeline`. Timestamps are 0 for events that haven't
+/// happened; `completed_at` covers settlement by confirmation, timeout or
+/// arbitration.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct Timeline {
     pub state: EscrowState,
     pub created_at: i64,
     pub accepted_at: i64,
+    pub disputed_at: i64,
     pub completed_at: i64,
     pub cancelled_at: i64,
     pub timeout_at: i64,
-    pub description: String,
-    pub bump: u8,
+    pub max_timeout_at: i64,
+    pub confirmation_requested_at: i64,
+    pub release_deadline: i64,
 }
 
-impl Escrow {
-    pub const LEN: usize = 32 + 32 + 32 + 8 + 1 + 8 + 8 + 8 + 8 + 8 + (4 + 200) + 1;
+/// Result of `get_vault_balance`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct VaultBalance {
+    /// Tokens the vault actually holds
+    pub vault_balance: u64,
+    pub amount: u64,
+    pub released_amount: u64,
+}
+
+/// Result of `preview_settlement`. Every token the vault holds lands in
+/// exactly one line.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Debug)]
+pub struct SettlementBreakdown {
+    /// To the seller, net of the fee
+    pub seller_payout: u64,
+    /// To the protocol fee vault
+    pub protocol_fee: u64,
+    /// To the referrer, out of the fee
+    pub referrer_fee: u64,
+    /// Unfulfilled part of a `partial_accept`, back to the buyer
+    pub buyer_refund: u64,
+    /// Acceptance collateral back to the seller
+    pub collateral_returned: u64,
+    /// Collateral forfeited to the buyer under the SLA tiers
+    pub collateral_penalty: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct Milestone {
+    pub label: String,
+    pub amount: u64,
+    pub released: bool,
+}
+
+impl Milestone {
+    pub const MAX_LABEL_LEN: usize = 32;
+    pub const LEN: usize = (4 + Self::MAX_LABEL_LEN) + 8 + 1;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct MilestoneInput {
+    pub label: String,
+    pub amount: u64,
+}
+
+/// One step of a seller response SLA: marking delivery within `window`
+/// seconds of acceptance gets `keep_bps` of the collateral back
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SlaTier {
+    pub window: i64,
+    pub keep_bps: u16,
+}
+
+impl SlaTier {
+    pub const LEN: usize = 8 + 2;
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct TimelineEntry {
+    pub event: TimelineEvent,
+    pub timestamp: i64,
+}
+
+impl TimelineEntry {
+    pub const LEN: usize = 1 + 8;
+}
+
+/// Terms shared by `create_escrow`, `create_escrow_with_vault` and
+/// `create_and_accept`. Durations are in seconds, and a zero duration turns
+/// its feature off.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct CreateParams {
+    /// Tokens held for the seller
+    pub amount: u64,
+    /// Time from the start until the escrow can time out
+    pub timeout_duration: i64,
+    /// Up to `Escrow::MAX_DESCRIPTION_LEN` bytes; may be empty
+    pub description: String,
+    /// Partial releases whose amounts sum to `amount`; empty for a lump sum
+    pub milestones: Vec<MilestoneInput>,
+    /// Settles disputes. Without one the config's default arbiter is used;
+    /// with neither, disputes are disabled for this escrow.
+    pub arbiter: Option<Pubkey>,
+    /// How long before the timeout `EscrowExpiringSoon` fires
+    pub warn_lead: i64,
+    /// Party `resolve_timeout` releases the funds to
+    pub timeout_beneficiary: TimeoutBeneficiary,
+    /// Collateral the seller deposits on acceptance, returned on settlement
+    pub required_collateral: u64,
+    /// Whether anyone may call `resolve_timeout`, or only the buyer and seller
+    pub permissionless_timeout: bool,
+    /// Refuse mints with a freeze authority, since freezing the vault would
+    /// trap the deposit
+    pub reject_freezable: bool,
+    /// Time from the start the seller has to `mark_delivered` before the
+    /// buyer may `claim_non_delivery`
+    pub delivery_window: i64,
+    /// Time confirmed funds vest over linearly, claimed by the seller with
+    /// `claim_vested`, instead of paying out the lump sum
+    pub vesting_duration: i64,
+    /// Time after creation no seller may accept, so the buyer can cancel a
+    /// mistaken escrow first; can't be combined with a `seller_authorization`
+    pub accept_lock: i64,
+    /// Time after the seller marks delivery before it is deemed accepted,
+    /// letting `resolve_timeout` pay the seller before the hard timeout
+    /// unless the buyer confirms or disputes
+    pub dispute_window: i64,
+    /// Time acceptance opens, which the timeout and delivery window run from
+    /// instead of creation; 0 starts at creation
+    pub start_at: i64,
+    /// Scale back the collateral returned the later the seller marks delivery
+    /// after acceptance, the rest going to the buyer; see
+    /// `Escrow::collateral_kept`. Empty returns it all.
+    pub sla_tiers: Vec<SlaTier>,
+    /// Advisory urgency keepers may order their work by; the program ignores it
+    pub priority: u8,
+    /// Lets seller payouts also go to token accounts owned by this program's
+    /// `[PAYOUT_ROUTE_SEED, seller]` PDA, e.g. a staking vault
+    pub route_to_program: Option<Pubkey>,
+    /// Longest an accepted seller may go without a `heartbeat` until they mark
+    /// delivery; once one is missed the buyer may `claim_abandoned`
+    pub heartbeat_interval: i64,
+    /// Who referred the escrow; takes `referrer_bps` of each seller payout out
+    /// of the protocol fee
+    pub referrer: Option<Pubkey>,
+    /// Referrer's slice in basis points of each payout; can't exceed the
+    /// escrow's `fee_bps`
+    pub referrer_bps: u16,
+    /// Lets the buyer `request_withdrawal` after acceptance and
+    /// `execute_withdrawal` once this passes, unless the seller contests by
+    /// marking delivery or opening a dispute first
+    pub withdraw_delay: i64,
+    /// Lets a seller who marked delivery `claim_inactive_buyer` once the buyer
+    /// has done nothing for this long
+    pub buyer_inactivity_timeout: i64,
+    /// Deposit nothing yet: the buyer fills `amount` over several
+    /// `deposit_additional` calls, and no seller may accept until it is
+    /// fully funded
+    pub deferred_funding: bool,
+    /// `confirm_delivery`, `cancel_escrow` and `resolve_timeout` also close
+    /// the escrow and vault, returning the rent at once instead of waiting
+    /// for `close_escrow`
+    pub auto_close: bool,
+}
+
+/// Which side keeps the leftover unit when a basis-point share of an amount
+/// doesn't divide evenly. Fees are taken from the seller's payout, so for a
+/// fee the seller is one side and the protocol stands in for the buyer.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum RoundingPolicy {
+    /// The fractional unit goes to the seller: fees round down, the seller's
+    /// dispute share rounds up
+    #[default]
+    FloorToSeller,
+    /// The fractional unit goes against the seller: fees round up, the
+    /// seller's dispute share rounds down
+    FloorToBuyer,
+    /// Round half to even on the fee or the seller's dispute share
+    BankersRounding,
+}
+
+impl RoundingPolicy {
+    /// `amount * bps / 10_000` rounded under this policy. `to_seller` is true
+    /// when the result is paid to the seller and false when it is taken from
+    /// them. Never exceeds `amount` for `bps <= 10_000`; fails if a larger
+    /// `bps` takes the result past `u64::MAX`.
+    pub fn bps_of(self, amount: u64, bps: u16, to_seller: bool) -> core::result::Result<u64, EscrowError> {
+        let product = amount as u128 * bps as u128;
+        let floor = product / 10_000;
+        let remainder = product % 10_000;
+        let round_up = remainder > 0
+            && match self {
+                RoundingPolicy::FloorToSeller => to_seller,
+                RoundingPolicy::FloorToBuyer => !to_seller,
+                RoundingPolicy::BankersRounding => {
+                    remainder > 5_000 || (remainder == 5_000 && floor % 2 == 1)
+                }
+            };
+        // u64 * u16 can't overflow u128, so only the narrowing can fail
+        u64::try_from(floor + round_up as u128).map_err(|_| EscrowError::ArithmeticOverflow)
+    }
+}
+
+/// Why a seller backed out of an accepted escrow
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeclineReason {
+    OutOfStock,
+    PriceChanged,
+    Other,
+}
+
+/// Party `resolve_timeout` releases the funds to
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TimeoutBeneficiary {
+    #[default]
+    Seller,
+    Buyer,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimelineEvent {
+    Created,
+    Accepted,
+    Completed,
+    Cancelled,
+    TimedOut,
+    Disputed,
+    Resolved,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
 pub enum EscrowState {
     Created,
     Accepted,
     Completed,
     Cancelled,
     TimedOut,
+    Disputed,
+    Resolved,
+    /// Confirmed, with the funds vesting to the seller via `claim_vested`
+    Vesting,
+}
+
+/// Deployment-wide settings, seeded `[b"config"]`
+#[account]
+pub struct Config {
+    pub authority: Pubkey,
+    /// Arbiter for escrows created without one; default disables it
+    pub default_arbiter: Pubkey,
+    pub bump: u8,
+    /// Protocol fee on seller payouts, in basis points
+    pub fee_bps: u16,
+    /// Escrows created with this config that haven't settled yet
+    pub active_escrows: u64,
+    /// Auto-confirm window new escrows snapshot at creation
+    pub auto_confirm_after: i64,
+    /// Smallest `required_collateral` new escrows may set, in basis points of the amount
+    pub min_collateral_bps: u16,
+    /// Buyers whose new escrows are created with no protocol fee
+    pub fee_exempt: Vec<Pubkey>,
+    /// Rounding policy new escrows snapshot at creation
+    pub rounding_policy: RoundingPolicy,
+    /// Incident switch allowing only buyer refunds; see `set_emergency_mode`
+    pub emergency_mode: bool,
+    /// Proposed successor to `authority`; default if there is none
+    pub pending_authority: Pubkey,
+    /// Relayers allowed to submit `confirm_delivery_signed`; empty allows any
+    pub relayer_allowlist: Vec<Pubkey>,
+    /// Most unsettled escrows a buyer may have open; 0 leaves it uncapped
+    pub max_active_per_buyer: u32,
+}
+
+impl Config {
+    pub const LEN: usize = 32 + 32 + 1 + 2 + 8 + 8 + 2 + (4 + Self::MAX_FEE_EXEMPT * 32) + 1 + 1 + 32
+        + (4 + Self::MAX_RELAYERS * 32) + 4;
+
+    pub const MAX_FEE_BPS: u16 = 1_000;
+
+    pub const MAX_FEE_EXEMPT: usize = 10;
+
+    pub const MAX_RELAYERS: usize = 10;
+
+    /// Fee a new escrow for `buyer` snapshots
+    pub fn fee_bps_for(&self, buyer: &Pubkey) -> u16 {
+        if self.fee_exempt.contains(buyer) {
+            0
+        } else {
+            self.fee_bps
+        }
+    }
+
+    /// Least collateral an escrow of `amount` may require
+    pub fn min_collateral_for(&self, amount: u64) -> u64 {
+        (amount as u128 * self.min_collateral_bps as u128 / 10_000) as u64
+    }
+}
+
+/// Marks a shipment commitment as used, seeded `[b"commitment", commitment]`.
+/// Never closed, so a commitment stays used for good.
+#[account]
+pub struct CommitmentRecord {
+    /// Escrow the commitment was submitted for
+    pub escrow: Pubkey,
+    pub bump: u8,
+}
+
+impl CommitmentRecord {
+    pub const LEN: usize = 32 + 1;
+}
+
+/// Settled escrow snapshot written by `mint_receipt`, seeded
+/// `[b"receipt", escrow]`. No instruction modifies or closes it.
+#[account]
+pub struct Receipt {
+    pub escrow: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub released_amount: u64,
+    pub fee_bps: u16,
+    pub final_state: EscrowState,
+    pub created_at: i64,
+    pub accepted_at: i64,
+    /// When the escrow completed, timed out, was resolved or was cancelled
+    pub settled_at: i64,
+    pub minted_at: i64,
+    pub bump: u8,
+}
+
+impl Receipt {
+    pub const LEN: usize = 32 + 32 + 32 + 32 + 8 + 8 + 2 + 1 + 8 + 8 + 8 + 8 + 1;
+}
+
+/// Standard escrow terms a marketplace publishes, seeded
+/// `[b"template", authority, name]`
+#[account]
+pub struct Template {
+    /// Marketplace that created it
+    pub authority: Pubkey,
+    pub name: String,
+    pub bump: u8,
+    pub terms: TemplateTerms,
+}
+
+impl Template {
+    pub const MAX_NAME_LEN: usize = 32;
+
+    pub const LEN: usize = 32 + (4 + Self::MAX_NAME_LEN) + 1 + TemplateTerms::LEN;
+}
+
+/// Terms an escrow created from a `Template` inherits. `fee_bps` sets the
+/// escrow's protocol fee; the rest mean what the `CreateParams` fields of
+/// the same name do.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TemplateTerms {
+    pub timeout_duration: i64,
+    /// Protocol fee the escrow charges, in basis points. It can't exceed the
+    /// config's fee for the buyer, so without a config it is 0.
+    pub fee_bps: u16,
+    pub arbiter: Option<Pubkey>,
+    pub timeout_beneficiary: TimeoutBeneficiary,
+    pub required_collateral: u64,
+    pub permissionless_timeout: bool,
+    pub reject_freezable: bool,
+    pub delivery_window: i64,
+    pub dispute_window: i64,
+    pub auto_close: bool,
+}
+
+impl TemplateTerms {
+    pub const LEN: usize = 8 + 2 + (1 + 32) + 1 + 8 + 1 + 1 + 8 + 8 + 1;
+}
+
+/// A seller's sealed bid on an escrow, seeded `[b"bid", escrow, seller]`
+#[account]
+pub struct Bid {
+    pub escrow: Pubkey,
+    pub seller: Pubkey,
+    /// sha256 of the price (u64 little-endian) followed by the salt
+    pub commitment: [u8; 32],
+    /// Revealed price; 0 until `reveal_bid`
+    pub price: u64,
+    pub revealed: bool,
+    pub bump: u8,
+}
+
+impl Bid {
+    pub const LEN: usize = 32 + 32 + 32 + 8 + 1 + 1;
+
+    /// Whether `price` and `salt` open the commitment
+    pub fn matches(&self, price: u64, salt: &[u8; 32]) -> bool {
+        solana_sha256_hasher::hashv(&[&price.to_le_bytes(), salt]).to_bytes() == self.commitment
+    }
+}
+
+/// A seller's standing consent to have `buyer`'s escrows opened already
+/// accepted by them
+#[account]
+pub struct SellerAuthorization {
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub bump: u8,
+}
+
+impl SellerAuthorization {
+    pub const LEN: usize = 32 + 32 + 1;
+}
+
+/// Counts a buyer's escrows that haven't settled, seeded `[b"buyer", buyer]`,
+/// so the config can cap them
+#[account]
+pub struct BuyerAccount {
+    pub buyer: Pubkey,
+    pub active_count: u32,
+    pub bump: u8,
+}
+
+impl BuyerAccount {
+    pub const LEN: usize = 32 + 4 + 1;
 }
 
 #[error_code]
@@ -339,4 +6083,801 @@ pub enum EscrowError {
     UnauthorizedSeller,
     #[msg("Timeout has not been reached yet")]
     TimeoutNotReached,
-}
\ No newline at end of file
+    #[msg("Instruction does not match the escrow kind (standard vs swap)")]
+    WrongEscrowKind,
+    #[msg("Swap terms are invalid")]
+    InvalidSwapTerms,
+    #[msg("Swap offer has expired")]
+    SwapExpired,
+    #[msg("Swap vault received less than the buyer's minimum")]
+    SwapAmountTooLow,
+    #[msg("Escrow timeline has no room for another event")]
+    TimelineFull,
+    #[msg("Invalid milestone")]
+    InvalidMilestone,
+    #[msg("Milestone amounts must sum to the escrow amount")]
+    MilestoneSumMismatch,
+    #[msg("Milestone has already been released")]
+    MilestoneAlreadyReleased,
+    #[msg("Seller cannot be the buyer")]
+    SellerCannotBeBuyer,
+    #[msg("Vault balance is below the amount owed")]
+    VaultBalanceMismatch,
+    #[msg("Missing or invalid Ed25519 signature from the buyer")]
+    InvalidBuyerSignature,
+    #[msg("Disputes are not enabled for this escrow")]
+    DisputesDisabled,
+    #[msg("Only the buyer or seller can perform this action")]
+    NotAParty,
+    #[msg("Only the arbiter can perform this action")]
+    UnauthorizedArbiter,
+    #[msg("Split must be between 0 and 10000 basis points")]
+    InvalidSplit,
+    #[msg("Only the config authority can perform this action")]
+    UnauthorizedAuthority,
+    #[msg("Fee exceeds the maximum allowed")]
+    InvalidFee,
+    #[msg("The fee vault must be provided for escrows that charge a fee")]
+    FeeVaultRequired,
+    #[msg("No fees to withdraw")]
+    NoFeesToWithdraw,
+    #[msg("Config still has active escrows")]
+    ConfigHasActiveEscrows,
+    #[msg("Token account cannot be the escrow vault")]
+    InvalidTokenAccount,
+    #[msg("Auto-confirm window cannot be negative")]
+    InvalidAutoConfirm,
+    #[msg("The buyer's token account must be provided when the timeout favors the buyer")]
+    BuyerTokenAccountRequired,
+    #[msg("Escrow is already on the current layout")]
+    AlreadyMigrated,
+    #[msg("Vault is not a valid token account for this escrow")]
+    InvalidVault,
+    #[msg("Extension must be positive")]
+    InvalidExtension,
+    #[msg("Extension would push the timeout past its cap")]
+    ExtensionCapExceeded,
+    #[msg("Split amount must be between zero and the escrow amount")]
+    InvalidSplitAmount,
+    #[msg("Required collateral is below the config's minimum")]
+    CollateralTooLow,
+    #[msg("Minimum collateral must be between 0 and 10000 basis points")]
+    InvalidCollateral,
+    #[msg("This escrow requires collateral; accept it with accept_with_collateral")]
+    CollateralRequired,
+    #[msg("Only the buyer or seller can resolve this escrow's timeout")]
+    UnauthorizedResolver,
+    #[msg("Escrow has already passed its timeout")]
+    AlreadyExpired,
+    #[msg("Buyer is already fee-exempt")]
+    AlreadyFeeExempt,
+    #[msg("Buyer is not fee-exempt")]
+    NotFeeExempt,
+    #[msg("Fee-exempt list is full")]
+    FeeExemptListFull,
+    #[msg("Vault holds no tokens beyond the escrowed balance")]
+    NoExcessFunds,
+    #[msg("Description exceeds 200 bytes")]
+    DescriptionTooLong,
+    #[msg("Refund account must belong to the original depositor")]
+    RefundAccountMismatch,
+    #[msg("Buyer's delegated allowance is less than the deposit")]
+    InsufficientDelegation,
+    #[msg("Signature nonce does not match the escrow's current confirm nonce")]
+    StaleSignature,
+    #[msg("An escrow's fee can only be lowered")]
+    FeeTooHigh,
+    #[msg("Batch must be escrow, vault and payout account triples, at most 8 of them")]
+    InvalidBatch,
+    #[msg("Mint has a freeze authority that could lock the vault")]
+    MintIsFreezable,
+    #[msg("Delivery window cannot be negative")]
+    InvalidDeliveryWindow,
+    #[msg("The seller's delivery deadline has not passed yet")]
+    DeliveryDeadlineNotPassed,
+    #[msg("Delivery payload must be 1 to 256 bytes")]
+    InvalidDeliveryPayload,
+    #[msg("The seller has not delivered a payload")]
+    NoDeliveryPayload,
+    #[msg("Delivery payload does not match the expected hash")]
+    PayloadMismatch,
+    #[msg("Amount change must be nonzero and leave a nonzero escrow amount")]
+    InvalidAdjustment,
+    #[msg("Emergency mode is on: only buyer refunds are allowed")]
+    EmergencyMode,
+    #[msg("Signer is not the proposed config authority")]
+    NotPendingAuthority,
+    #[msg("Shipment commitment cannot be all zeros")]
+    InvalidCommitment,
+    #[msg("Shipment commitment was already used for another escrow")]
+    CommitmentReused,
+    #[msg("Vesting duration cannot be negative")]
+    InvalidVestingDuration,
+    #[msg("Nothing has vested since the last claim")]
+    NothingVested,
+    #[msg("Token account is for a different mint than the escrow")]
+    MintMismatch,
+    #[msg("Fulfilled amount must be positive and less than the escrow amount")]
+    InvalidFulfilledAmount,
+    #[msg("Relayer is not on the config's relayer allowlist")]
+    UnauthorizedRelayer,
+    #[msg("Relayer is already on the allowlist")]
+    AlreadyRelayer,
+    #[msg("Relayer is not on the allowlist")]
+    NotRelayer,
+    #[msg("Relayer allowlist is full")]
+    RelayerListFull,
+    #[msg("Accept lock cannot be negative")]
+    InvalidAcceptLock,
+    #[msg("Escrow cannot be accepted until its accept lock has passed")]
+    AcceptLockActive,
+    #[msg("New buyer must differ from the current buyer and the seller")]
+    InvalidNewBuyer,
+    #[msg("Dispute window cannot be negative")]
+    InvalidDisputeWindow,
+    #[msg("Escrows must be distinct and share buyer, depositor, mint and terms to merge")]
+    IncompatibleEscrows,
+    #[msg("Start time cannot be in the past")]
+    InvalidStartAt,
+    #[msg("Escrow cannot be accepted before its start time")]
+    NotYetStarted,
+    #[msg("Arithmetic overflow or underflow in an escrow amount or time")]
+    ArithmeticOverflow,
+    #[msg("Seller token account is not the payout account bound at acceptance")]
+    PayoutAccountMismatch,
+    #[msg("SLA tiers must number at most 3, with growing windows and shrinking shares of at most 10000 bps")]
+    InvalidSlaTiers,
+    #[msg("Accept batch takes at most 16 escrows")]
+    InvalidAcceptBatch,
+    #[msg("Buyer already has the most active escrows the config allows")]
+    TooManyActiveEscrows,
+    #[msg("The buyer's BuyerAccount must be provided")]
+    BuyerAccountRequired,
+    #[msg("Heartbeat interval cannot be negative")]
+    InvalidHeartbeatInterval,
+    #[msg("The seller missed a heartbeat")]
+    HeartbeatMissed,
+    #[msg("The seller hasn't missed a heartbeat")]
+    HeartbeatNotMissed,
+    #[msg("Referrer share needs a referrer and can't exceed the protocol fee")]
+    InvalidReferrerBps,
+    #[msg("The referrer's token account must be provided")]
+    ReferrerAccountRequired,
+    #[msg("Token account isn't owned by the escrow's referrer")]
+    ReferrerAccountMismatch,
+    #[msg("Withdraw delay cannot be negative")]
+    InvalidWithdrawDelay,
+    #[msg("The withdraw delay has not passed yet")]
+    WithdrawDelayNotElapsed,
+    #[msg("The seller contested the withdrawal")]
+    WithdrawalContested,
+    #[msg("The seller's token account must be provided")]
+    SellerTokenAccountRequired,
+    #[msg("Buyer inactivity timeout cannot be negative")]
+    InvalidBuyerInactivityTimeout,
+    #[msg("The seller has not marked delivery")]
+    NotDelivered,
+    #[msg("The buyer has not been inactive long enough")]
+    BuyerNotInactive,
+    #[msg("Evidence URI must be 1 to 200 bytes")]
+    InvalidEvidenceUri,
+    #[msg("The escrow's rent is already sponsored")]
+    RentAlreadySponsored,
+    #[msg("Rent must return to the escrow's rent payer")]
+    RentPayerMismatch,
+    #[msg("The escrow's amount has not been fully deposited yet")]
+    NotFullyFunded,
+    #[msg("Rent must return to the buyer, or to the sponsor who paid it")]
+    RentRecipientMismatch,
+    #[msg("Template names must be 1 to 32 bytes, with a fee within the protocol maximum")]
+    InvalidTemplate,
+    #[msg("Everything has vested to the seller; there is nothing left to cancel")]
+    FullyVested,
+    #[msg("Pass either a seller payout account or the seller's associated token account, not both")]
+    PayoutAccountConflict,
+    #[msg("Escrow is taking sealed bids; the buyer selects the seller")]
+    SealedBidding,
+    #[msg("Bids are committed before the reveal deadline and revealed after it")]
+    WrongBidPhase,
+    #[msg("Bid has not been revealed")]
+    BidNotRevealed,
+    #[msg("Price and salt don't match the bid commitment")]
+    BidMismatch,
+    #[msg("Escrow is counted in the config, which must be passed")]
+    ConfigRequired,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// All-zero account data: default keys, empty vectors, first enum variants
+    fn blank_escrow() -> Escrow {
+        Escrow::deserialize(&mut &[0u8; Escrow::LEN][..]).unwrap()
+    }
+
+    #[test]
+    fn timeline_stays_monotonic_when_the_clock_goes_backwards() {
+        let mut escrow = blank_escrow();
+        escrow.record(TimelineEvent::Created, 1_000).unwrap();
+        escrow.record(TimelineEvent::Accepted, 1_500).unwrap();
+        // Clock warped back past both earlier events
+        escrow.record(TimelineEvent::Completed, 900).unwrap();
+
+        assert_eq!(escrow.created_at(), 1_000);
+        assert_eq!(escrow.accepted_at(), 1_500);
+        assert_eq!(escrow.completed_at(), 1_500);
+
+        let clock = Clock { unix_timestamp: 1_200, ..Clock::default() };
+        assert_eq!(escrow.now(&clock), 1_500);
+    }
+
+    #[test]
+    fn timeline_keeps_forward_timestamps_unchanged() {
+        let mut escrow = blank_escrow();
+        escrow.record(TimelineEvent::Created, 1_000).unwrap();
+        escrow.record(TimelineEvent::Cancelled, 2_000).unwrap();
+
+        assert_eq!(escrow.cancelled_at(), 2_000);
+        let clock = Clock { unix_timestamp: 3_000, ..Clock::default() };
+        assert_eq!(escrow.now(&clock), 3_000);
+    }
+
+    #[test]
+    fn payload_matches_only_its_own_sha256() {
+        let mut escrow = blank_escrow();
+        escrow.delivery_payload = b"license-key-0042".to_vec();
+        let hash = solana_sha256_hasher::hash(b"license-key-0042").to_bytes();
+
+        assert!(escrow.payload_matches(&hash));
+        assert!(!escrow.payload_matches(&[0u8; 32]));
+        escrow.delivery_payload = b"license-key-0043".to_vec();
+        assert!(!escrow.payload_matches(&hash));
+    }
+
+    #[test]
+    fn vests_linearly_over_the_schedule() {
+        let mut escrow = blank_escrow();
+        escrow.amount = 1_000_000;
+        escrow.vesting_start = 10_000;
+        escrow.vesting_duration = 400;
+
+        assert_eq!(escrow.vested_amount(9_000).unwrap(), 0);
+        assert_eq!(escrow.vested_amount(10_100).unwrap(), 250_000);
+        assert_eq!(escrow.vested_amount(10_300).unwrap(), 750_000);
+        assert_eq!(escrow.vested_amount(10_400).unwrap(), 1_000_000);
+        assert_eq!(escrow.vested_amount(99_999).unwrap(), 1_000_000);
+    }
+
+    #[test]
+    fn vesting_counts_claims_toward_the_schedule() {
+        let mut escrow = blank_escrow();
+        escrow.amount = 1_000_000;
+        escrow.vesting_start = 10_000;
+        escrow.vesting_duration = 400;
+
+        // Claimed at 25%
+        escrow.claimed_amount = 250_000;
+        escrow.released_amount = 250_000;
+        assert_eq!(escrow.vested_amount(10_300).unwrap() - escrow.claimed_amount, 500_000);
+
+        // Claimed at 75%; the rest unlocks at the end
+        escrow.claimed_amount = 750_000;
+        escrow.released_amount = 750_000;
+        assert_eq!(escrow.vested_amount(10_400).unwrap() - escrow.claimed_amount, 250_000);
+    }
+
+    #[test]
+    fn cancelling_vesting_leaves_the_vested_part_to_the_seller() {
+        let mut escrow = blank_escrow();
+        escrow.amount = 1_000_000;
+        escrow.vesting_start = 10_000;
+        escrow.vesting_duration = 400;
+        escrow.claimed_amount = 250_000;
+        escrow.released_amount = 250_000;
+
+        // At 60%, 350k has vested since the claim and 400k hasn't
+        let unvested = escrow.unvested_amount(10_240).unwrap();
+        assert_eq!(unvested, 400_000);
+
+        // As `cancel_escrow` leaves it: the vested rest is all still claimable
+        escrow.released_amount += unvested;
+        escrow.vesting_duration = 0;
+        assert_eq!(escrow.remaining_amount().unwrap(), 350_000);
+        assert_eq!(escrow.vested_amount(10_240).unwrap() - escrow.claimed_amount, 350_000);
+        assert_eq!(escrow.vested_amount(99_999).unwrap() - escrow.claimed_amount, 350_000);
+        assert_eq!(escrow.unvested_amount(99_999).unwrap(), 0);
+    }
+
+    #[test]
+    fn vesting_after_milestones_only_covers_the_unreleased_remainder() {
+        let mut escrow = blank_escrow();
+        escrow.amount = 1_000_000;
+        escrow.released_amount = 600_000;
+        escrow.vesting_duration = 3;
+
+        assert_eq!(escrow.vested_amount(1).unwrap(), 133_333);
+        assert_eq!(escrow.vested_amount(3).unwrap(), 400_000);
+    }
+
+    #[cfg(feature = "verbose-logs")]
+    #[test]
+    fn verbose_logs_read_token_balances() {
+        use anchor_lang::solana_program::program_pack::Pack;
+
+        let token_account = anchor_spl::token::spl_token::state::Account {
+            mint: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            amount: 42,
+            state: anchor_spl::token::spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        };
+        let mut data = vec![0u8; anchor_spl::token::spl_token::state::Account::LEN];
+        token_account.pack_into_slice(&mut data);
+        let (key, owner) = (Pubkey::new_unique(), anchor_spl::token::ID);
+        let mut lamports = 0;
+        let info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0);
+
+        let (other_key, mut other_lamports, mut empty) = (Pubkey::new_unique(), 0, Vec::new());
+        let other = AccountInfo::new(&other_key, false, true, &mut other_lamports, &mut empty, &owner, false, 0);
+
+        assert_eq!(token_balance(&info), Some(42));
+        assert_eq!(token_balance(&other), None);
+        log_balances("before", &info, &other);
+    }
+
+    #[test]
+    fn pda_seeds_keep_the_creating_buyer_after_a_rights_transfer() {
+        let mut escrow = blank_escrow();
+        let (creator, purchaser) = (Pubkey::new_unique(), Pubkey::new_unique());
+        escrow.buyer = creator;
+        assert_eq!(*escrow.seed_buyer(), creator);
+
+        // As `transfer_escrow_rights` does
+        escrow.original_buyer = *escrow.seed_buyer();
+        escrow.buyer = purchaser;
+        assert_eq!(*escrow.seed_buyer(), creator);
+
+        // A second sale still derives from the creator
+        escrow.original_buyer = *escrow.seed_buyer();
+        escrow.buyer = Pubkey::new_unique();
+        assert_eq!(*escrow.seed_buyer(), creator);
+    }
+
+    #[test]
+    fn refunds_follow_the_claim_but_the_funder_stays_recorded() {
+        let mut escrow = blank_escrow();
+        let (creator, purchaser) = (Pubkey::new_unique(), Pubkey::new_unique());
+        escrow.buyer = creator;
+        escrow.original_depositor = creator;
+        assert_eq!(escrow.depositor(), creator);
+
+        // As `transfer_escrow_rights` does
+        escrow.original_buyer = *escrow.seed_buyer();
+        escrow.buyer = purchaser;
+        assert_eq!(escrow.depositor(), purchaser);
+        assert_eq!(escrow.original_depositor, creator);
+    }
+
+    #[test]
+    fn accept_lock_holds_off_sellers_until_it_passes() {
+        let mut escrow = blank_escrow();
+        escrow.record(TimelineEvent::Created, 1_000).unwrap();
+        escrow.timeout_at = 10_000;
+        escrow.accept_lock = 60;
+        let seller = Pubkey::new_unique();
+
+        assert!(matches!(
+            escrow.can_accept(&seller, 1_059),
+            Err(EscrowError::AcceptLockActive)
+        ));
+        assert!(escrow.can_accept(&seller, 1_060).is_ok());
+    }
+
+    #[test]
+    fn merging_requires_matching_terms() {
+        let escrow = blank_escrow();
+        let mut other = blank_escrow();
+        assert!(escrow.can_merge(&other));
+
+        other.timeout_beneficiary = TimeoutBeneficiary::Buyer;
+        assert!(!escrow.can_merge(&other));
+
+        other = blank_escrow();
+        other.buyer = Pubkey::new_unique();
+        assert!(!escrow.can_merge(&other));
+    }
+
+    #[test]
+    fn scheduled_escrows_open_for_acceptance_at_their_start() {
+        let mut escrow = blank_escrow();
+        escrow.record(TimelineEvent::Created, 1_000).unwrap();
+        escrow.start_at = 5_000;
+        escrow.timeout_at = 9_000;
+        let seller = Pubkey::new_unique();
+
+        assert!(matches!(
+            escrow.can_accept(&seller, 4_999),
+            Err(EscrowError::NotYetStarted)
+        ));
+        assert!(escrow.can_accept(&seller, 5_000).is_ok());
+    }
+
+    #[test]
+    fn route_program_pdas_may_own_seller_payouts() {
+        let mut escrow = blank_escrow();
+        let seller = Pubkey::new_unique();
+        let route_program = Pubkey::new_unique();
+        let (route, _) =
+            Pubkey::find_program_address(&[PAYOUT_ROUTE_SEED, seller.as_ref()], &route_program);
+
+        assert!(escrow.is_payout_owner(&seller, &seller));
+        assert!(!escrow.is_payout_owner(&seller, &route));
+
+        escrow.route_to_program = route_program;
+        assert!(escrow.is_payout_owner(&seller, &seller));
+        assert!(escrow.is_payout_owner(&seller, &route));
+        // Another seller's PDA, or the program itself, doesn't qualify
+        assert!(!escrow.is_payout_owner(&Pubkey::new_unique(), &route));
+        assert!(!escrow.is_payout_owner(&seller, &route_program));
+    }
+
+    #[test]
+    fn multisig_treasuries_may_own_seller_payouts() {
+        let mut escrow = blank_escrow();
+        let multisig = Pubkey::new_unique();
+        let treasury = Pubkey::new_unique();
+
+        escrow.seller_treasury = treasury;
+        assert!(!escrow.is_payout_owner(&multisig, &treasury));
+
+        escrow.seller_is_multisig = true;
+        assert!(escrow.is_payout_owner(&multisig, &treasury));
+        assert!(escrow.is_payout_owner(&multisig, &multisig));
+        assert!(!escrow.is_payout_owner(&multisig, &Pubkey::new_unique()));
+    }
+
+    #[test]
+    fn referral_is_a_capped_slice_of_the_fee() {
+        let mut escrow = blank_escrow();
+        escrow.fee_bps = 300;
+        escrow.referrer_bps = 100;
+        assert_eq!(escrow.referral_of(1_000_000).unwrap(), 10_000);
+
+        // Lowering the fee below the slice hands the referrer all of it
+        escrow.fee_bps = 50;
+        assert_eq!(escrow.referral_of(1_000_000).unwrap(), 5_000);
+        escrow.fee_bps = 0;
+        assert_eq!(escrow.referral_of(1_000_000).unwrap(), 0);
+    }
+
+    #[test]
+    fn buyers_go_inactive_a_timeout_after_delivery_or_their_last_action() {
+        let mut escrow = blank_escrow();
+        escrow.state = EscrowState::Accepted;
+        assert_eq!(escrow.buyer_inactive_at().unwrap(), None);
+
+        escrow.buyer_inactivity_timeout = 600;
+        assert!(matches!(escrow.buyer_inactive_at(), Err(EscrowError::NotDelivered)));
+
+        escrow.delivered_at = 1_000;
+        assert_eq!(escrow.buyer_inactive_at().unwrap(), Some(1_600));
+
+        // Acting after the delivery restarts the clock, acting before it doesn't
+        escrow.buyer_active_at = 900;
+        assert_eq!(escrow.buyer_inactive_at().unwrap(), Some(1_600));
+        escrow.buyer_active_at = 1_200;
+        assert_eq!(escrow.buyer_inactive_at().unwrap(), Some(1_800));
+    }
+
+    #[test]
+    fn withdrawals_execute_a_delay_after_an_uncontested_request() {
+        let mut escrow = blank_escrow();
+        escrow.state = EscrowState::Accepted;
+        escrow.withdraw_delay = 300;
+        assert_eq!(escrow.withdrawal_executable_at().unwrap(), None);
+
+        escrow.withdrawal_requested_at = 1_000;
+        assert_eq!(escrow.withdrawal_executable_at().unwrap(), Some(1_300));
+
+        // Marking delivery or disputing contests it
+        escrow.delivered_at = 1_100;
+        assert_eq!(escrow.withdrawal_executable_at().unwrap(), None);
+        escrow.delivered_at = 0;
+        escrow.state = EscrowState::Disputed;
+        assert_eq!(escrow.withdrawal_executable_at().unwrap(), None);
+    }
+
+    #[test]
+    fn settlement_breakdown_accounts_for_every_held_token() {
+        let mut escrow = blank_escrow();
+        escrow.state = EscrowState::Accepted;
+        escrow.record(TimelineEvent::Created, 1_000).unwrap();
+        escrow.record(TimelineEvent::Accepted, 2_000).unwrap();
+        escrow.amount = 1_000_000;
+        escrow.fulfilled_amount = 600_000;
+        escrow.fee_bps = 300;
+        escrow.referrer_bps = 100;
+        escrow.required_collateral = 50_000;
+        escrow.sla_tiers = vec![SlaTier { window: 100, keep_bps: 10_000 }, SlaTier { window: 200, keep_bps: 6_000 }];
+
+        let breakdown = escrow.settlement_breakdown(2_150).unwrap();
+        assert_eq!(
+            breakdown,
+            SettlementBreakdown {
+                seller_payout: 582_000,
+                protocol_fee: 12_000,
+                referrer_fee: 6_000,
+                buyer_refund: 400_000,
+                collateral_returned: 30_000,
+                collateral_penalty: 20_000,
+            }
+        );
+        let total = breakdown.seller_payout
+            + breakdown.protocol_fee
+            + breakdown.referrer_fee
+            + breakdown.buyer_refund
+            + breakdown.collateral_returned
+            + breakdown.collateral_penalty;
+        assert_eq!(total, escrow.held_amount().unwrap());
+    }
+
+    #[test]
+    fn heartbeats_are_due_an_interval_after_the_last_one() {
+        let mut escrow = blank_escrow();
+        escrow.record(TimelineEvent::Created, 1_000).unwrap();
+        escrow.heartbeat_interval = 500;
+        assert_eq!(escrow.heartbeat_due_at().unwrap(), None);
+
+        escrow.state = EscrowState::Accepted;
+        escrow.record(TimelineEvent::Accepted, 2_000).unwrap();
+        assert_eq!(escrow.heartbeat_due_at().unwrap(), Some(2_500));
+
+        escrow.last_heartbeat_at = 2_400;
+        assert_eq!(escrow.heartbeat_due_at().unwrap(), Some(2_900));
+
+        // Marking delivery ends the obligation
+        escrow.delivered_at = 2_600;
+        assert_eq!(escrow.heartbeat_due_at().unwrap(), None);
+
+        escrow.delivered_at = 0;
+        escrow.heartbeat_interval = i64::MAX;
+        assert!(matches!(escrow.heartbeat_due_at(), Err(EscrowError::ArithmeticOverflow)));
+    }
+
+    #[test]
+    fn sla_tiers_step_down_the_returned_collateral() {
+        let mut escrow = blank_escrow();
+        escrow.required_collateral = 1_000;
+        escrow.record(TimelineEvent::Accepted, 1_000).unwrap();
+        assert_eq!(escrow.collateral_kept(1_000_000).unwrap(), 1_000);
+
+        escrow.sla_tiers = vec![
+            SlaTier { window: 100, keep_bps: 10_000 },
+            SlaTier { window: 200, keep_bps: 5_000 },
+        ];
+        for (delivered_at, kept) in [(1_100, 1_000), (1_101, 500), (1_200, 500), (1_201, 0)] {
+            escrow.delivered_at = delivered_at;
+            assert_eq!(escrow.collateral_kept(i64::MAX).unwrap(), kept);
+            assert_eq!(escrow.collateral_penalty(i64::MAX).unwrap(), 1_000 - kept);
+        }
+
+        // Unmarked deliveries are timed by the settlement
+        escrow.delivered_at = 0;
+        assert_eq!(escrow.collateral_kept(1_050).unwrap(), 1_000);
+        assert_eq!(escrow.collateral_kept(1_150).unwrap(), 500);
+        assert_eq!(escrow.collateral_kept(5_000).unwrap(), 0);
+    }
+
+    #[test]
+    fn dispute_window_releases_to_seller_after_delivery() {
+        let mut escrow = blank_escrow();
+        escrow.timeout_at = 10_000;
+        escrow.timeout_beneficiary = TimeoutBeneficiary::Buyer;
+        escrow.dispute_window = 600;
+
+        // The clock only starts once the seller marks delivery
+        assert_eq!(escrow.release_deadline(), 10_000);
+        assert_eq!(escrow.timeout_payee(10_000), TimeoutBeneficiary::Buyer);
+
+        escrow.delivered_at = 2_000;
+        assert_eq!(escrow.release_deadline(), 2_600);
+        assert_eq!(escrow.timeout_payee(2_599), TimeoutBeneficiary::Buyer);
+        assert_eq!(escrow.timeout_payee(2_600), TimeoutBeneficiary::Seller);
+    }
+
+    #[test]
+    fn partial_acceptance_leaves_only_the_fulfilled_share_after_the_refund() {
+        let mut escrow = blank_escrow();
+        escrow.amount = 100;
+        assert_eq!(escrow.unfulfilled_amount().unwrap(), 0);
+
+        escrow.fulfilled_amount = 70;
+        assert_eq!(escrow.unfulfilled_amount().unwrap(), 30);
+
+        // Refunded at confirmation
+        escrow.released_amount += escrow.unfulfilled_amount().unwrap();
+        assert_eq!(escrow.unfulfilled_amount().unwrap(), 0);
+        assert_eq!(escrow.remaining_amount().unwrap(), 70);
+    }
+
+    // 1_005 * 250 bps = 25.125 and 1_005 * 5_000 bps = 502.5: neither
+    // divides evenly, and the second lands exactly on the half
+
+    #[test]
+    fn floor_to_seller_leaves_the_leftover_unit_with_the_seller() {
+        let policy = RoundingPolicy::FloorToSeller;
+        assert_eq!(policy.bps_of(1_005, 250, false).unwrap(), 25);
+        assert_eq!(policy.bps_of(1_005, 5_000, true).unwrap(), 503);
+    }
+
+    #[test]
+    fn floor_to_buyer_leaves_the_leftover_unit_with_the_buyer() {
+        let policy = RoundingPolicy::FloorToBuyer;
+        assert_eq!(policy.bps_of(1_005, 250, false).unwrap(), 26);
+        assert_eq!(policy.bps_of(1_005, 5_000, true).unwrap(), 502);
+    }
+
+    #[test]
+    fn bankers_rounding_rounds_halves_to_even() {
+        let policy = RoundingPolicy::BankersRounding;
+        assert_eq!(policy.bps_of(1_005, 250, false).unwrap(), 25);
+        assert_eq!(policy.bps_of(1_005, 5_000, true).unwrap(), 502);
+        assert_eq!(policy.bps_of(1_007, 5_000, true).unwrap(), 504);
+        assert_eq!(policy.bps_of(1_003, 7_500, false).unwrap(), 752);
+    }
+
+    #[test]
+    fn every_policy_is_exact_when_the_share_divides_evenly() {
+        for policy in [
+            RoundingPolicy::FloorToSeller,
+            RoundingPolicy::FloorToBuyer,
+            RoundingPolicy::BankersRounding,
+        ] {
+            assert_eq!(policy.bps_of(1_000_000, 100, false).unwrap(), 10_000);
+            assert_eq!(policy.bps_of(1_000, 10_000, true).unwrap(), 1_000);
+            assert_eq!(policy.bps_of(u64::MAX, 10_000, true).unwrap(), u64::MAX);
+        }
+    }
+
+    #[test]
+    fn expanded_description_fits_the_reallocated_space() {
+        let mut escrow = blank_escrow();
+        let reserved = Escrow::space_for(&CreateParams::default());
+        assert_eq!(escrow.space_with_description(0), reserved);
+        assert_eq!(escrow.space_with_description(Escrow::MAX_DESCRIPTION_LEN), reserved);
+
+        escrow.description = "a".repeat(Escrow::MAX_DESCRIPTION_LEN);
+        let mut data = Vec::new();
+        escrow.try_serialize(&mut data).unwrap();
+        let full = data.len();
+
+        escrow.description = "b".repeat(500);
+        data.clear();
+        escrow.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), full + 300);
+        assert!(data.len() <= escrow.space_with_description(500));
+    }
+
+    /// An escrow created with `params`, with every field that can still grow
+    /// after creation at its largest
+    fn fullest_escrow(params: &CreateParams) -> Escrow {
+        let mut escrow = blank_escrow();
+        escrow.description = "d".repeat(Escrow::MAX_DESCRIPTION_LEN);
+        escrow.milestones = params
+            .milestones
+            .iter()
+            .map(|milestone| Milestone {
+                label: milestone.label.clone(),
+                amount: milestone.amount,
+                released: true,
+            })
+            .collect();
+        escrow.sla_tiers = params.sla_tiers.clone();
+        for _ in 0..Escrow::MAX_TIMELINE_ENTRIES {
+            escrow.record(TimelineEvent::Created, 0).unwrap();
+        }
+        escrow.delivery_payload = vec![1; Escrow::MAX_DELIVERY_PAYLOAD_LEN];
+        escrow.decline_reason = Some(DeclineReason::Other);
+        escrow.evidence_uri = "e".repeat(Escrow::MAX_EVIDENCE_URI_LEN);
+        escrow
+    }
+
+    #[test]
+    fn deferred_funding_blocks_acceptance_until_fully_deposited() {
+        let mut escrow = blank_escrow();
+        escrow.buyer = Pubkey::new_unique();
+        escrow.amount = 1_000;
+        escrow.unfunded_amount = 1_000;
+        escrow.timeout_at = 10_000;
+        let seller = Pubkey::new_unique();
+
+        assert_eq!(escrow.held_amount().unwrap(), 0);
+        assert!(matches!(escrow.can_accept(&seller, 0), Err(EscrowError::NotFullyFunded)));
+
+        escrow.unfunded_amount = 400;
+        assert_eq!(escrow.held_amount().unwrap(), 600);
+        assert!(matches!(escrow.can_accept(&seller, 0), Err(EscrowError::NotFullyFunded)));
+
+        escrow.unfunded_amount = 0;
+        assert_eq!(escrow.held_amount().unwrap(), 1_000);
+        assert!(escrow.can_accept(&seller, 0).is_ok());
+    }
+
+    #[test]
+    fn space_for_fits_every_feature_combination_exactly() {
+        let milestone = MilestoneInput { label: "m".repeat(Milestone::MAX_LABEL_LEN), amount: 1 };
+        let tier = SlaTier { window: 1, keep_bps: 10_000 };
+        for milestones in [0, 1, Escrow::MAX_MILESTONES] {
+            for sla_tiers in [0, 2, Escrow::MAX_SLA_TIERS] {
+                let params = CreateParams {
+                    milestones: vec![milestone.clone(); milestones],
+                    sla_tiers: vec![tier; sla_tiers],
+                    ..Default::default()
+                };
+                let mut data = Vec::new();
+                fullest_escrow(&params).try_serialize(&mut data).unwrap();
+                assert_eq!(data.len(), Escrow::space_for(&params), "{} milestones, {} tiers", milestones, sla_tiers);
+            }
+        }
+
+        // The fullest layout is the old fixed size, and even the leanest
+        // can't be mistaken for a v1 account by `migrate_v2`
+        let fullest = CreateParams {
+            milestones: vec![milestone; Escrow::MAX_MILESTONES],
+            sla_tiers: vec![tier; Escrow::MAX_SLA_TIERS],
+            ..Default::default()
+        };
+        assert_eq!(Escrow::space_for(&fullest), 8 + Escrow::LEN);
+        assert!(Escrow::space_for(&CreateParams::default()) > 8 + Escrow::LEN_V1);
+    }
+
+    #[test]
+    fn sealed_bids_open_only_with_their_price_and_salt() {
+        let salt = [7u8; 32];
+        let mut bid = Bid {
+            escrow: Pubkey::new_unique(),
+            seller: Pubkey::new_unique(),
+            commitment: [0; 32],
+            price: 0,
+            revealed: false,
+            bump: 255,
+        };
+        bid.commitment = solana_sha256_hasher::hashv(&[&900_000u64.to_le_bytes(), &salt]).to_bytes();
+        assert!(bid.matches(900_000, &salt));
+        assert!(!bid.matches(900_001, &salt));
+        assert!(!bid.matches(900_000, &[8u8; 32]));
+
+        // No direct acceptance while the auction runs
+        let mut escrow = blank_escrow();
+        escrow.timeout_at = 10_000;
+        escrow.bid_reveal_at = 5_000;
+        assert!(matches!(escrow.can_accept(&bid.seller, 0), Err(EscrowError::SealedBidding)));
+        escrow.bid_reveal_at = 0;
+        assert!(escrow.can_accept(&bid.seller, 0).is_ok());
+    }
+
+    #[test]
+    fn template_len_fits_the_longest_name() {
+        let template = Template {
+            authority: Pubkey::new_unique(),
+            name: "t".repeat(Template::MAX_NAME_LEN),
+            bump: 255,
+            terms: TemplateTerms {
+                timeout_duration: 86_400,
+                fee_bps: Config::MAX_FEE_BPS,
+                arbiter: Some(Pubkey::new_unique()),
+                timeout_beneficiary: TimeoutBeneficiary::Seller,
+                required_collateral: 1,
+                permissionless_timeout: true,
+                reject_freezable: true,
+                delivery_window: 1,
+                dispute_window: 1,
+                auto_close: true,
+            },
+        };
+        let mut data = Vec::new();
+        template.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), 8 + Template::LEN);
+    }
+}
@@ -0,0 +1,151 @@
+//! Boundary cases for the checked amount and time math: each helper must
+//! either return the exact value or `ArithmeticOverflow`, never wrap or panic.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::AnchorDeserialize;
+use solana_escrow_engine::{Escrow, EscrowError, EscrowState, RoundingPolicy, TimelineEvent};
+
+const POLICIES: [RoundingPolicy; 3] = [
+    RoundingPolicy::FloorToSeller,
+    RoundingPolicy::FloorToBuyer,
+    RoundingPolicy::BankersRounding,
+];
+
+fn blank_escrow() -> Escrow {
+    Escrow::deserialize(&mut &[0u8; Escrow::LEN][..]).unwrap()
+}
+
+#[test]
+fn bps_of_handles_max_amounts_and_max_bps() {
+    for policy in POLICIES {
+        assert_eq!(policy.bps_of(u64::MAX, 10_000, true).unwrap(), u64::MAX);
+        assert_eq!(policy.bps_of(u64::MAX, 10_000, false).unwrap(), u64::MAX);
+        assert_eq!(policy.bps_of(u64::MAX, 0, true).unwrap(), 0);
+        assert_eq!(policy.bps_of(0, u16::MAX, true).unwrap(), 0);
+        // Past 100% the share can no longer fit a u64
+        assert!(matches!(
+            policy.bps_of(u64::MAX, u16::MAX, true),
+            Err(EscrowError::ArithmeticOverflow)
+        ));
+    }
+}
+
+#[test]
+fn bps_of_rounding_up_never_exceeds_the_amount() {
+    let amount = u64::MAX - 1;
+    for policy in POLICIES {
+        for bps in [1, 4_999, 5_000, 5_001, 9_999] {
+            for to_seller in [true, false] {
+                let share = policy.bps_of(amount, bps, to_seller).unwrap();
+                let exact = amount as u128 * bps as u128 / 10_000;
+                assert!(share as u128 == exact || share as u128 == exact + 1);
+                assert!(share < amount);
+            }
+        }
+    }
+}
+
+#[test]
+fn remaining_amount_rejects_over_release() {
+    let mut escrow = blank_escrow();
+    escrow.amount = u64::MAX;
+    escrow.released_amount = u64::MAX;
+    assert_eq!(escrow.remaining_amount().unwrap(), 0);
+
+    escrow.amount = 10;
+    escrow.released_amount = 11;
+    assert!(matches!(escrow.remaining_amount(), Err(EscrowError::ArithmeticOverflow)));
+    assert!(matches!(escrow.held_amount(), Err(EscrowError::ArithmeticOverflow)));
+    assert!(escrow.vested_amount(0).is_err());
+}
+
+#[test]
+fn held_amount_rejects_collateral_past_u64_max() {
+    let mut escrow = blank_escrow();
+    escrow.state = EscrowState::Accepted;
+    escrow.amount = u64::MAX;
+    escrow.required_collateral = 1;
+    assert!(matches!(escrow.held_amount(), Err(EscrowError::ArithmeticOverflow)));
+
+    // Collateral only counts once deposited, so a Created escrow still fits
+    escrow.state = EscrowState::Created;
+    assert_eq!(escrow.held_amount().unwrap(), u64::MAX);
+}
+
+#[test]
+fn vesting_without_a_duration_is_fully_vested() {
+    let mut escrow = blank_escrow();
+    escrow.amount = 1_000;
+    escrow.vesting_start = 500;
+
+    // Zero denominator: nothing to divide, everything is vested
+    escrow.vesting_duration = 0;
+    assert_eq!(escrow.vested_amount(0).unwrap(), 1_000);
+    assert_eq!(escrow.vested_amount(i64::MAX).unwrap(), 1_000);
+
+    // A negative duration can't be created, but still must not panic
+    escrow.vesting_duration = -1;
+    assert_eq!(escrow.vested_amount(600).unwrap(), 1_000);
+}
+
+#[test]
+fn vesting_max_amounts_stays_exact() {
+    let mut escrow = blank_escrow();
+    escrow.amount = u64::MAX;
+    escrow.vesting_start = 0;
+    escrow.vesting_duration = i64::MAX;
+
+    assert_eq!(escrow.vested_amount(0).unwrap(), 0);
+    assert_eq!(escrow.vested_amount(i64::MAX / 2).unwrap(), u64::MAX / 2 - 1);
+    assert_eq!(escrow.vested_amount(i64::MAX).unwrap(), u64::MAX);
+
+    // Claimed plus remaining can't exceed what was deposited
+    escrow.claimed_amount = 1;
+    assert!(matches!(escrow.vested_amount(0), Err(EscrowError::ArithmeticOverflow)));
+}
+
+#[test]
+fn vesting_rejects_elapsed_time_that_overflows() {
+    let mut escrow = blank_escrow();
+    escrow.amount = 100;
+    escrow.vesting_start = i64::MIN;
+    escrow.vesting_duration = 10;
+    assert!(matches!(
+        escrow.vested_amount(i64::MAX),
+        Err(EscrowError::ArithmeticOverflow)
+    ));
+}
+
+#[test]
+fn windows_past_i64_max_never_elapse() {
+    let mut escrow = blank_escrow();
+    escrow.timeout_at = 10_000;
+    escrow.confirmation_requested_at = 5_000;
+    escrow.auto_confirm_after = i64::MAX;
+    escrow.delivered_at = 5_000;
+    escrow.dispute_window = i64::MAX;
+
+    assert_eq!(escrow.deemed_accepted_at(), None);
+    assert_eq!(escrow.release_deadline(), 10_000);
+}
+
+#[test]
+fn accept_lock_past_i64_max_is_an_overflow() {
+    let mut escrow = blank_escrow();
+    escrow.record(TimelineEvent::Created, 1_000).unwrap();
+    escrow.timeout_at = i64::MAX;
+    escrow.accept_lock = i64::MAX;
+    assert!(matches!(
+        escrow.can_accept(&Pubkey::new_unique(), 2_000),
+        Err(EscrowError::ArithmeticOverflow)
+    ));
+}
+
+#[test]
+fn expiry_warning_ignores_unrepresentable_lead_times() {
+    let mut escrow = blank_escrow();
+    escrow.timeout_at = i64::MAX;
+    escrow.warn_lead = i64::MAX;
+    assert!(!escrow.take_expiry_warning(i64::MIN));
+    assert!(escrow.take_expiry_warning(i64::MAX - 1));
+}
@@ -0,0 +1,118 @@
+//! Pins the byte layout documented in `events.rs`: discriminator, version
+//! byte and the shared header, then each event's own fields.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{Discriminator, Event};
+use solana_escrow_engine::events::{EVENT_SCHEMA_VERSION, HEADER_LEN};
+use solana_escrow_engine::{
+    ConfirmationRequested, DeclineReason, EscrowDeclined, EscrowExpiringSoon, EvidenceRequested,
+    EvidenceSubmitted,
+};
+
+struct Header {
+    escrow: Pubkey,
+    buyer: Pubkey,
+    seller: Pubkey,
+}
+
+impl Header {
+    fn new() -> Self {
+        Header {
+            escrow: Pubkey::new_unique(),
+            buyer: Pubkey::new_unique(),
+            seller: Pubkey::new_unique(),
+        }
+    }
+}
+
+/// Checks the header of `data` and returns the event's own fields
+fn body<'a>(data: &'a [u8], name: &str, discriminator: &[u8], header: &Header) -> &'a [u8] {
+    let expected = solana_sha256_hasher::hash(format!("event:{}", name).as_bytes()).to_bytes();
+    assert_eq!(&data[..8], &expected[..8], "{} discriminator", name);
+    assert_eq!(&data[..8], discriminator, "{} discriminator", name);
+    assert_eq!(data[8], EVENT_SCHEMA_VERSION, "{} version", name);
+    assert_eq!(&data[9..41], header.escrow.as_ref());
+    assert_eq!(&data[41..73], header.buyer.as_ref());
+    assert_eq!(&data[73..HEADER_LEN], header.seller.as_ref());
+    &data[HEADER_LEN..]
+}
+
+#[test]
+fn escrow_expiring_soon_layout() {
+    let header = Header::new();
+    let event = EscrowExpiringSoon {
+        schema_version: EVENT_SCHEMA_VERSION,
+        escrow: header.escrow,
+        buyer: header.buyer,
+        seller: header.seller,
+        timeout_at: -2,
+    };
+    let data = event.data();
+    let body = body(&data, "EscrowExpiringSoon", EscrowExpiringSoon::DISCRIMINATOR, &header);
+    assert_eq!(body, (-2i64).to_le_bytes());
+}
+
+#[test]
+fn escrow_declined_layout() {
+    let header = Header::new();
+    let event = EscrowDeclined {
+        schema_version: EVENT_SCHEMA_VERSION,
+        escrow: header.escrow,
+        buyer: header.buyer,
+        seller: header.seller,
+        reason: DeclineReason::PriceChanged,
+    };
+    let data = event.data();
+    let body = body(&data, "EscrowDeclined", EscrowDeclined::DISCRIMINATOR, &header);
+    assert_eq!(body, [1]);
+}
+
+#[test]
+fn confirmation_requested_layout() {
+    let header = Header::new();
+    let event = ConfirmationRequested {
+        schema_version: EVENT_SCHEMA_VERSION,
+        escrow: header.escrow,
+        buyer: header.buyer,
+        seller: header.seller,
+        release_deadline: 1_700_000_000,
+    };
+    let data = event.data();
+    let body = body(&data, "ConfirmationRequested", ConfirmationRequested::DISCRIMINATOR, &header);
+    assert_eq!(body, 1_700_000_000i64.to_le_bytes());
+}
+
+#[test]
+fn evidence_requested_layout() {
+    let header = Header::new();
+    let arbiter = Pubkey::new_unique();
+    let event = EvidenceRequested {
+        schema_version: EVENT_SCHEMA_VERSION,
+        escrow: header.escrow,
+        buyer: header.buyer,
+        seller: header.seller,
+        arbiter,
+    };
+    let data = event.data();
+    let body = body(&data, "EvidenceRequested", EvidenceRequested::DISCRIMINATOR, &header);
+    assert_eq!(body, arbiter.as_ref());
+}
+
+#[test]
+fn evidence_submitted_layout() {
+    let header = Header::new();
+    let submitter = Pubkey::new_unique();
+    let event = EvidenceSubmitted {
+        schema_version: EVENT_SCHEMA_VERSION,
+        escrow: header.escrow,
+        buyer: header.buyer,
+        seller: header.seller,
+        submitter,
+        evidence_uri: "ipfs://x".to_string(),
+    };
+    let data = event.data();
+    let body = body(&data, "EvidenceSubmitted", EvidenceSubmitted::DISCRIMINATOR, &header);
+    assert_eq!(&body[..32], submitter.as_ref());
+    assert_eq!(&body[32..36], 8u32.to_le_bytes());
+    assert_eq!(&body[36..], b"ipfs://x");
+}
@@ -0,0 +1,259 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { SolanaEscrowEngine } from "../target/types/solana_escrow_engine";
+import { EscrowPdas, wallet, deriveEscrow, createEscrow, expectError } from "./helpers";
+import { PublicKey, Keypair } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID, createMint, createAccount, mintTo } from "@solana/spl-token";
+
+// One negative case per guarded handler, each pinned to the exact EscrowError
+// so a refactor that drops or reorders a check fails loudly.
+describe("access control", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.SolanaEscrowEngine as Program<SolanaEscrowEngine>;
+  const connection = provider.connection;
+
+  const AMOUNT = new anchor.BN(1000000);
+  const TIMEOUT_DURATION = new anchor.BN(86400);
+
+  let mint: PublicKey;
+  let arbiter: Keypair;
+  let intruder: Keypair;
+  let intruderToken: PublicKey;
+
+  // Accepted escrow with an arbiter
+  let buyer: Keypair;
+  let buyerToken: PublicKey;
+  let seller: Keypair;
+  let sellerToken: PublicKey;
+  let accepted: EscrowPdas;
+
+  // Escrow still in Created
+  let openBuyer: Keypair;
+  let openBuyerToken: PublicKey;
+  let open: EscrowPdas;
+
+  before(async () => {
+    buyer = await wallet(connection);
+    seller = await wallet(connection);
+    openBuyer = await wallet(connection);
+    intruder = await wallet(connection);
+    arbiter = Keypair.generate();
+    await new Promise(resolve => setTimeout(resolve, 3000));
+
+    mint = await createMint(connection, buyer, provider.wallet.publicKey, null, 6);
+    buyerToken = await createAccount(connection, buyer, mint, buyer.publicKey);
+    sellerToken = await createAccount(connection, seller, mint, seller.publicKey);
+    openBuyerToken = await createAccount(connection, openBuyer, mint, openBuyer.publicKey);
+    intruderToken = await createAccount(connection, intruder, mint, intruder.publicKey);
+    await mintTo(connection, buyer, mint, buyerToken, provider.wallet.publicKey, AMOUNT.toNumber());
+    await mintTo(connection, openBuyer, mint, openBuyerToken, provider.wallet.publicKey, AMOUNT.toNumber());
+
+    accepted = deriveEscrow(program, buyer.publicKey, mint);
+    open = deriveEscrow(program, openBuyer.publicKey, mint);
+
+    await createEscrow(program, buyer, mint, buyerToken, accepted, { amount: AMOUNT, timeoutDuration: TIMEOUT_DURATION, description: "Access control", arbiter: arbiter.publicKey });
+    await program.methods
+      .acceptEscrow()
+      .accounts({ seller: seller.publicKey, escrow: accepted.escrow })
+      .signers([seller])
+      .rpc();
+
+    await createEscrow(program, openBuyer, mint, openBuyerToken, open, { amount: AMOUNT, timeoutDuration: TIMEOUT_DURATION, description: "Access control" });
+  });
+
+  it("confirm_delivery rejects a non-buyer", async () => {
+    await expectError(
+      program.methods
+        .confirmDelivery(new anchor.BN(0))
+        .accounts({
+          buyer: intruder.publicKey,
+          escrow: accepted.escrow,
+          vaultTokenAccount: accepted.vault,
+          sellerTokenAccount: sellerToken,
+          feeVault: null,
+          config: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([intruder])
+        .rpc(),
+      "UnauthorizedBuyer"
+    );
+  });
+
+  it("confirm_delivery rejects an escrow that hasn't been accepted", async () => {
+    await expectError(
+      program.methods
+        .confirmDelivery(new anchor.BN(0))
+        .accounts({
+          buyer: openBuyer.publicKey,
+          escrow: open.escrow,
+          vaultTokenAccount: open.vault,
+          sellerTokenAccount: sellerToken,
+          feeVault: null,
+          config: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([openBuyer])
+        .rpc(),
+      "InvalidState"
+    );
+  });
+
+  it("cancel_escrow rejects a non-buyer", async () => {
+    await expectError(
+      program.methods
+        .cancelEscrow()
+        .accounts({
+          buyer: intruder.publicKey,
+          escrow: open.escrow,
+          vaultTokenAccount: open.vault,
+          buyerTokenAccount: intruderToken,
+          config: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([intruder])
+        .rpc(),
+      "UnauthorizedBuyer"
+    );
+  });
+
+  it("cancel_escrow rejects an accepted escrow", async () => {
+    await expectError(
+      program.methods
+        .cancelEscrow()
+        .accounts({
+          buyer: buyer.publicKey,
+          escrow: accepted.escrow,
+          vaultTokenAccount: accepted.vault,
+          buyerTokenAccount: buyerToken,
+          config: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc(),
+      "InvalidState"
+    );
+  });
+
+  it("accept_escrow rejects an escrow that is no longer open", async () => {
+    await expectError(
+      program.methods
+        .acceptEscrow()
+        .accounts({ seller: intruder.publicKey, escrow: accepted.escrow })
+        .signers([intruder])
+        .rpc(),
+      "InvalidState"
+    );
+  });
+
+  it("accept_escrow rejects the buyer as seller", async () => {
+    await expectError(
+      program.methods
+        .acceptEscrow()
+        .accounts({ seller: openBuyer.publicKey, escrow: open.escrow })
+        .signers([openBuyer])
+        .rpc(),
+      "SellerCannotBeBuyer"
+    );
+  });
+
+  it("resolve_timeout rejects calls before the deadline", async () => {
+    await expectError(
+      program.methods
+        .resolveTimeout()
+        .accounts({
+          resolver: intruder.publicKey,
+          escrow: accepted.escrow,
+          vaultTokenAccount: accepted.vault,
+          sellerTokenAccount: sellerToken,
+          buyerTokenAccount: null,
+          feeVault: null,
+          config: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([intruder])
+        .rpc(),
+      "TimeoutNotReached"
+    );
+  });
+
+  it("release_milestone rejects a non-buyer", async () => {
+    await expectError(
+      program.methods
+        .releaseMilestone(0)
+        .accounts({
+          buyer: intruder.publicKey,
+          escrow: accepted.escrow,
+          vaultTokenAccount: accepted.vault,
+          sellerTokenAccount: sellerToken,
+          feeVault: null,
+          config: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([intruder])
+        .rpc(),
+      "UnauthorizedBuyer"
+    );
+  });
+
+  it("request_confirmation rejects a non-seller", async () => {
+    await expectError(
+      program.methods
+        .requestConfirmation()
+        .accounts({ seller: intruder.publicKey, escrow: accepted.escrow })
+        .signers([intruder])
+        .rpc(),
+      "UnauthorizedSeller"
+    );
+  });
+
+  it("open_dispute rejects a non-party", async () => {
+    await expectError(
+      program.methods
+        .openDispute()
+        .accounts({ party: intruder.publicKey, escrow: accepted.escrow })
+        .signers([intruder])
+        .rpc(),
+      "NotAParty"
+    );
+  });
+
+  it("open_dispute rejects an escrow that hasn't been accepted", async () => {
+    await expectError(
+      program.methods
+        .openDispute()
+        .accounts({ party: openBuyer.publicKey, escrow: open.escrow })
+        .signers([openBuyer])
+        .rpc(),
+      "InvalidState"
+    );
+  });
+
+  it("resolve_dispute rejects a non-arbiter", async () => {
+    await program.methods
+      .openDispute()
+      .accounts({ party: buyer.publicKey, escrow: accepted.escrow })
+      .signers([buyer])
+      .rpc();
+
+    await expectError(
+      program.methods
+        .resolveDispute(5000)
+        .accounts({
+          arbiter: intruder.publicKey,
+          escrow: accepted.escrow,
+          vaultTokenAccount: accepted.vault,
+          sellerTokenAccount: sellerToken,
+          buyerTokenAccount: buyerToken,
+          feeVault: null,
+          config: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([intruder])
+        .rpc(),
+      "UnauthorizedArbiter"
+    );
+  });
+});
@@ -0,0 +1,123 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { SolanaEscrowEngine } from "../target/types/solana_escrow_engine";
+import { createParams } from "./create_params";
+import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createMint,
+  createAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { expect } from "chai";
+
+// Compute-unit ceilings per instruction. These sit comfortably above the
+// measured baseline; raise one only when the extra cost is intentional, and
+// never past the 200k default per-instruction budget.
+const CU_LIMITS = {
+  createEscrow: 60_000,
+  acceptEscrow: 15_000,
+  confirmDelivery: 30_000,
+};
+
+describe("compute budget", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.SolanaEscrowEngine as Program<SolanaEscrowEngine>;
+  const connection = provider.connection;
+
+  let buyer: Keypair;
+  let seller: Keypair;
+  let mint: PublicKey;
+  let buyerTokenAccount: PublicKey;
+  let sellerTokenAccount: PublicKey;
+  let escrowPda: PublicKey;
+  let vaultPda: PublicKey;
+
+  // Compute units consumed by a confirmed transaction
+  async function unitsConsumed(signature: string): Promise<number> {
+    await connection.confirmTransaction(signature, "confirmed");
+    const tx = await connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    return tx.meta.computeUnitsConsumed;
+  }
+
+  before(async () => {
+    buyer = Keypair.generate();
+    seller = Keypair.generate();
+    await connection.requestAirdrop(buyer.publicKey, 2 * anchor.web3.LAMPORTS_PER_SOL);
+    await connection.requestAirdrop(seller.publicKey, 2 * anchor.web3.LAMPORTS_PER_SOL);
+    await new Promise(resolve => setTimeout(resolve, 3000));
+
+    mint = await createMint(connection, buyer, provider.wallet.publicKey, null, 6);
+    buyerTokenAccount = await createAccount(connection, buyer, mint, buyer.publicKey);
+    sellerTokenAccount = await createAccount(connection, seller, mint, seller.publicKey);
+    await mintTo(connection, buyer, mint, buyerTokenAccount, provider.wallet.publicKey, 1000000);
+
+    [escrowPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow"), buyer.publicKey.toBuffer(), mint.toBuffer()],
+      program.programId
+    );
+    [vaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), escrowPda.toBuffer()],
+      program.programId
+    );
+  });
+
+  it("create_escrow stays under its compute ceiling", async () => {
+    const sig = await program.methods
+      .createEscrow(createParams({ amount: new anchor.BN(1000000), timeoutDuration: new anchor.BN(86400), description: "Compute baseline" }))
+      .accounts({
+        buyer: buyer.publicKey,
+        escrow: escrowPda,
+        mint,
+        buyerTokenAccount,
+        vaultTokenAccount: vaultPda,
+        config: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .signers([buyer])
+      .rpc();
+
+    const units = await unitsConsumed(sig);
+    console.log("create_escrow CU:", units);
+    expect(units).to.be.below(CU_LIMITS.createEscrow);
+  });
+
+  it("accept_escrow stays under its compute ceiling", async () => {
+    const sig = await program.methods
+      .acceptEscrow()
+      .accounts({ seller: seller.publicKey, escrow: escrowPda })
+      .signers([seller])
+      .rpc();
+
+    const units = await unitsConsumed(sig);
+    console.log("accept_escrow CU:", units);
+    expect(units).to.be.below(CU_LIMITS.acceptEscrow);
+  });
+
+  it("confirm_delivery stays under its compute ceiling", async () => {
+    const sig = await program.methods
+      .confirmDelivery(new anchor.BN(0))
+      .accounts({
+        buyer: buyer.publicKey,
+        escrow: escrowPda,
+        vaultTokenAccount: vaultPda,
+        sellerTokenAccount,
+        feeVault: null,
+        config: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([buyer])
+      .rpc();
+
+    const units = await unitsConsumed(sig);
+    console.log("confirm_delivery CU:", units);
+    expect(units).to.be.below(CU_LIMITS.confirmDelivery);
+  });
+});
@@ -0,0 +1,38 @@
+import * as anchor from "@coral-xyz/anchor";
+import { IdlTypes } from "@coral-xyz/anchor";
+import { SolanaEscrowEngine } from "../target/types/solana_escrow_engine";
+
+export type CreateParams = IdlTypes<SolanaEscrowEngine>["createParams"];
+
+// Terms for createEscrow / createEscrowWithVault / createAndAccept with every
+// optional feature off, so a test only spells out what it exercises
+export function createParams(
+  terms: Pick<CreateParams, "amount" | "timeoutDuration"> & Partial<CreateParams>
+): CreateParams {
+  return {
+    description: "",
+    milestones: [],
+    arbiter: null,
+    warnLead: new anchor.BN(0),
+    timeoutBeneficiary: { seller: {} },
+    requiredCollateral: new anchor.BN(0),
+    permissionlessTimeout: true,
+    rejectFreezable: false,
+    deliveryWindow: new anchor.BN(0),
+    vestingDuration: new anchor.BN(0),
+    acceptLock: new anchor.BN(0),
+    disputeWindow: new anchor.BN(0),
+    startAt: new anchor.BN(0),
+    slaTiers: [],
+    priority: 0,
+    routeToProgram: null,
+    heartbeatInterval: new anchor.BN(0),
+    referrer: null,
+    referrerBps: 0,
+    withdrawDelay: new anchor.BN(0),
+    buyerInactivityTimeout: new anchor.BN(0),
+    deferredFunding: false,
+    autoClose: false,
+    ...terms,
+  };
+}
@@ -0,0 +1,71 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { SolanaEscrowEngine } from "../target/types/solana_escrow_engine";
+import { createParams } from "./create_params";
+import { Connection, PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { expect } from "chai";
+
+// Fixtures shared by the suites that set up their own escrows
+
+export type EscrowPdas = { escrow: PublicKey; vault: PublicKey };
+
+// A fresh keypair with an airdrop requested; callers wait for it to land
+export async function wallet(connection: Connection) {
+  const keypair = Keypair.generate();
+  await connection.requestAirdrop(keypair.publicKey, 2 * anchor.web3.LAMPORTS_PER_SOL);
+  return keypair;
+}
+
+// Top-level escrow and vault PDAs for `buyer` and `mint`
+export function deriveEscrow(
+  program: Program<SolanaEscrowEngine>,
+  buyer: PublicKey,
+  mint: PublicKey
+): EscrowPdas {
+  const [escrow] = PublicKey.findProgramAddressSync(
+    [Buffer.from("escrow"), buyer.toBuffer(), mint.toBuffer()],
+    program.programId
+  );
+  const [vault] = PublicKey.findProgramAddressSync(
+    [Buffer.from("vault"), escrow.toBuffer()],
+    program.programId
+  );
+  return { escrow, vault };
+}
+
+// Opens an escrow at `pdas` without the config
+export function createEscrow(
+  program: Program<SolanaEscrowEngine>,
+  owner: Keypair,
+  mint: PublicKey,
+  ownerToken: PublicKey,
+  pdas: EscrowPdas,
+  terms: Parameters<typeof createParams>[0]
+) {
+  return program.methods
+    .createEscrow(createParams(terms))
+    .accounts({
+      buyer: owner.publicKey,
+      escrow: pdas.escrow,
+      mint,
+      buyerTokenAccount: ownerToken,
+      vaultTokenAccount: pdas.vault,
+      config: null,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+    })
+    .signers([owner])
+    .rpc();
+}
+
+export async function expectError(tx: Promise<unknown>, code: string) {
+  try {
+    await tx;
+  } catch (error) {
+    expect(error.toString()).to.include(code);
+    return;
+  }
+  expect.fail(`Should have failed with ${code}`);
+}
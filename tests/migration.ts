@@ -0,0 +1,89 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { SolanaEscrowEngine } from "../target/types/solana_escrow_engine";
+import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID, createAccount, getAccount } from "@solana/spl-token";
+import { expect } from "chai";
+
+// Loaded by the test validator from tests/fixtures (see Anchor.toml): an
+// Accepted escrow in the v1 layout, written before `timeout_beneficiary`
+// existed, whose timeout has long passed.
+const V1_ESCROW = new PublicKey("7zGi3CSAZ3BD3fchWbzkt3LpZG6yVmHxCQ5tHf99GiMJ");
+const V1_VAULT = new PublicKey("8VXbo1FGB7y3k4QcGczJ81k4NWypo7hM9HwhhYNf6W3E");
+const V1_MINT = new PublicKey("87uad4UPygw7iaXtqsxDWRsYtMfgJHRJ8W6D4jchXPsx");
+const V1_SELLER = new PublicKey("4c65b81wHBFf8nyAXMhTcAbbUyvCQqEfHZqK9PwTe7Wp");
+const V1_AMOUNT = 1000000;
+
+describe("v2 migration", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.SolanaEscrowEngine as Program<SolanaEscrowEngine>;
+  const connection = provider.connection;
+
+  it("Migrates a v1 escrow and keeps the timeout in the seller's favor", async () => {
+    const before = await connection.getAccountInfo(V1_ESCROW);
+
+    await program.methods
+      .migrateV2()
+      .accounts({
+        payer: provider.wallet.publicKey,
+        escrow: V1_ESCROW,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const after = await connection.getAccountInfo(V1_ESCROW);
+    expect(after.data.length).to.be.greaterThan(before.data.length);
+    expect(after.lamports).to.be.at.least(
+      await connection.getMinimumBalanceForRentExemption(after.data.length)
+    );
+
+    const escrowData = await program.account.escrow.fetch(V1_ESCROW);
+    expect(escrowData.timeoutBeneficiary).to.deep.equal({ seller: {} });
+    expect(escrowData.state).to.deep.equal({ accepted: {} });
+    expect(escrowData.amount.toNumber()).to.equal(V1_AMOUNT);
+    expect(escrowData.vault.equals(V1_VAULT)).to.be.true;
+    expect(escrowData.originalDepositor.equals(escrowData.buyer)).to.be.true;
+
+    const resolver = Keypair.generate();
+    await connection.requestAirdrop(resolver.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+    await new Promise(resolve => setTimeout(resolve, 1000));
+    const sellerTokenAccount = await createAccount(connection, resolver, V1_MINT, V1_SELLER);
+
+    await program.methods
+      .resolveTimeout()
+      .accounts({
+        resolver: resolver.publicKey,
+        escrow: V1_ESCROW,
+        vaultTokenAccount: V1_VAULT,
+        sellerTokenAccount,
+        buyerTokenAccount: null,
+        feeVault: null,
+        config: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([resolver])
+      .rpc();
+
+    expect((await getAccount(connection, sellerTokenAccount)).amount).to.equal(BigInt(V1_AMOUNT));
+    const settled = await program.account.escrow.fetch(V1_ESCROW);
+    expect(settled.state).to.deep.equal({ timedOut: {} });
+  });
+
+  it("Rejects migrating an escrow already on the current layout", async () => {
+    try {
+      await program.methods
+        .migrateV2()
+        .accounts({
+          payer: provider.wallet.publicKey,
+          escrow: V1_ESCROW,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      expect.fail("Should have failed - already migrated");
+    } catch (error) {
+      expect(error.toString()).to.include("AlreadyMigrated");
+    }
+  });
+});
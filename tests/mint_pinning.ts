@@ -0,0 +1,295 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { SolanaEscrowEngine } from "../target/types/solana_escrow_engine";
+import { EscrowPdas, wallet, deriveEscrow, createEscrow, expectError } from "./helpers";
+import { PublicKey, Keypair } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID, createMint, createAccount, mintTo, getAccount } from "@solana/spl-token";
+import { expect } from "chai";
+
+// Every token account an instruction moves funds through must hold the
+// escrow's mint. Each case passes an otherwise valid account of a second mint
+// and pins the rejection to MintMismatch (or InvalidVault for a foreign vault).
+describe("mint pinning", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.SolanaEscrowEngine as Program<SolanaEscrowEngine>;
+  const connection = provider.connection;
+
+  const AMOUNT = new anchor.BN(1000000);
+  const TIMEOUT_DURATION = new anchor.BN(86400);
+
+  let mint: PublicKey;
+  let otherMint: PublicKey;
+
+  let buyer: Keypair;
+  let seller: Keypair;
+  let openBuyer: Keypair;
+  let buyerToken: PublicKey;
+  let sellerToken: PublicKey;
+  let openBuyerToken: PublicKey;
+  // Same owners, second mint
+  let buyerOtherToken: PublicKey;
+  let sellerOtherToken: PublicKey;
+  let openBuyerOtherToken: PublicKey;
+
+  // Accepted escrow on `mint`, an unaccepted one on `mint`, and the buyer's
+  // escrow on `otherMint`
+  let accepted: EscrowPdas;
+  let open: EscrowPdas;
+  let foreign: EscrowPdas;
+
+  before(async () => {
+    buyer = await wallet(connection);
+    seller = await wallet(connection);
+    openBuyer = await wallet(connection);
+    await new Promise(resolve => setTimeout(resolve, 3000));
+
+    mint = await createMint(connection, buyer, provider.wallet.publicKey, null, 6);
+    otherMint = await createMint(connection, buyer, provider.wallet.publicKey, null, 6);
+    buyerToken = await createAccount(connection, buyer, mint, buyer.publicKey);
+    sellerToken = await createAccount(connection, seller, mint, seller.publicKey);
+    openBuyerToken = await createAccount(connection, openBuyer, mint, openBuyer.publicKey);
+    buyerOtherToken = await createAccount(connection, buyer, otherMint, buyer.publicKey);
+    sellerOtherToken = await createAccount(connection, seller, otherMint, seller.publicKey);
+    openBuyerOtherToken = await createAccount(connection, openBuyer, otherMint, openBuyer.publicKey);
+    await mintTo(connection, buyer, mint, buyerToken, provider.wallet.publicKey, AMOUNT.toNumber());
+    await mintTo(connection, buyer, mint, openBuyerToken, provider.wallet.publicKey, AMOUNT.toNumber());
+    await mintTo(connection, buyer, otherMint, buyerOtherToken, provider.wallet.publicKey, AMOUNT.toNumber());
+
+    accepted = deriveEscrow(program, buyer.publicKey, mint);
+    open = deriveEscrow(program, openBuyer.publicKey, mint);
+    foreign = deriveEscrow(program, buyer.publicKey, otherMint);
+
+    await createEscrow(program, buyer, mint, buyerToken, accepted, { amount: AMOUNT, timeoutDuration: TIMEOUT_DURATION, description: "Mint pinning" });
+    await program.methods
+      .acceptEscrow()
+      .accounts({ seller: seller.publicKey, escrow: accepted.escrow })
+      .signers([seller])
+      .rpc();
+    await createEscrow(program, openBuyer, mint, openBuyerToken, open, { amount: AMOUNT, timeoutDuration: TIMEOUT_DURATION, description: "Mint pinning" });
+    await createEscrow(program, buyer, otherMint, buyerOtherToken, foreign, { amount: AMOUNT, timeoutDuration: TIMEOUT_DURATION, description: "Mint pinning" });
+  });
+
+  it("creates every vault with its escrow's mint", async () => {
+    for (const { escrow, vault } of [accepted, open, foreign]) {
+      const escrowData = await program.account.escrow.fetch(escrow);
+      expect(escrowData.vault.equals(vault)).to.be.true;
+      expect((await getAccount(connection, vault)).mint.equals(escrowData.mint)).to.be.true;
+    }
+  });
+
+  it("create_escrow rejects a deposit account of another mint", async () => {
+    await expectError(
+      createEscrow(program, openBuyer, otherMint, openBuyerToken, deriveEscrow(program, openBuyer.publicKey, otherMint), { amount: AMOUNT, timeoutDuration: TIMEOUT_DURATION, description: "Mint pinning" }),
+      "MintMismatch"
+    );
+  });
+
+  it("confirm_delivery rejects another escrow's vault", async () => {
+    await expectError(
+      program.methods
+        .confirmDelivery(new anchor.BN(0))
+        .accounts({
+          buyer: buyer.publicKey,
+          escrow: accepted.escrow,
+          vaultTokenAccount: foreign.vault,
+          sellerTokenAccount: sellerToken,
+          feeVault: null,
+          config: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc(),
+      "InvalidVault"
+    );
+  });
+
+  it("accept_with_collateral rejects a seller account of another mint", async () => {
+    await expectError(
+      program.methods
+        .acceptWithCollateral()
+        .accounts({
+          seller: seller.publicKey,
+          escrow: open.escrow,
+          vaultTokenAccount: open.vault,
+          sellerTokenAccount: sellerOtherToken,
+          config: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([seller])
+        .rpc(),
+      "MintMismatch"
+    );
+  });
+
+  it("confirm_delivery rejects a seller account of another mint", async () => {
+    await expectError(
+      program.methods
+        .confirmDelivery(new anchor.BN(0))
+        .accounts({
+          buyer: buyer.publicKey,
+          escrow: accepted.escrow,
+          vaultTokenAccount: accepted.vault,
+          sellerTokenAccount: sellerOtherToken,
+          feeVault: null,
+          config: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc(),
+      "MintMismatch"
+    );
+  });
+
+  it("release_milestone rejects a seller account of another mint", async () => {
+    await expectError(
+      program.methods
+        .releaseMilestone(0)
+        .accounts({
+          buyer: buyer.publicKey,
+          escrow: accepted.escrow,
+          vaultTokenAccount: accepted.vault,
+          sellerTokenAccount: sellerOtherToken,
+          feeVault: null,
+          config: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc(),
+      "MintMismatch"
+    );
+  });
+
+  it("claim_vested rejects a seller account of another mint", async () => {
+    await expectError(
+      program.methods
+        .claimVested()
+        .accounts({
+          seller: seller.publicKey,
+          escrow: accepted.escrow,
+          vaultTokenAccount: accepted.vault,
+          sellerTokenAccount: sellerOtherToken,
+          feeVault: null,
+          config: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([seller])
+        .rpc(),
+      "MintMismatch"
+    );
+  });
+
+  it("resolve_timeout rejects a seller account of another mint", async () => {
+    await expectError(
+      program.methods
+        .resolveTimeout()
+        .accounts({
+          resolver: seller.publicKey,
+          escrow: accepted.escrow,
+          vaultTokenAccount: accepted.vault,
+          sellerTokenAccount: sellerOtherToken,
+          buyerTokenAccount: null,
+          feeVault: null,
+          config: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([seller])
+        .rpc(),
+      "MintMismatch"
+    );
+  });
+
+  it("resolve_dispute rejects a buyer account of another mint", async () => {
+    const arbiter = Keypair.generate();
+    await expectError(
+      program.methods
+        .resolveDispute(5000)
+        .accounts({
+          arbiter: arbiter.publicKey,
+          escrow: accepted.escrow,
+          vaultTokenAccount: accepted.vault,
+          sellerTokenAccount: sellerToken,
+          buyerTokenAccount: buyerOtherToken,
+          feeVault: null,
+          config: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([arbiter])
+        .rpc(),
+      "MintMismatch"
+    );
+  });
+
+  it("claim_non_delivery rejects a buyer account of another mint", async () => {
+    await expectError(
+      program.methods
+        .claimNonDelivery()
+        .accounts({
+          buyer: buyer.publicKey,
+          escrow: accepted.escrow,
+          vaultTokenAccount: accepted.vault,
+          buyerTokenAccount: buyerOtherToken,
+          config: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc(),
+      "MintMismatch"
+    );
+  });
+
+  it("cancel_escrow rejects a refund account of another mint", async () => {
+    await expectError(
+      program.methods
+        .cancelEscrow()
+        .accounts({
+          buyer: openBuyer.publicKey,
+          escrow: open.escrow,
+          vaultTokenAccount: open.vault,
+          buyerTokenAccount: openBuyerOtherToken,
+          config: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([openBuyer])
+        .rpc(),
+      "MintMismatch"
+    );
+  });
+
+  it("deposit_additional and withdraw_partial reject a buyer account of another mint", async () => {
+    const accounts = {
+      buyer: openBuyer.publicKey,
+      escrow: open.escrow,
+      vaultTokenAccount: open.vault,
+      buyerTokenAccount: openBuyerOtherToken,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+    await expectError(
+      program.methods.depositAdditional(new anchor.BN(1)).accounts(accounts).signers([openBuyer]).rpc(),
+      "MintMismatch"
+    );
+    await expectError(
+      program.methods.withdrawPartial(new anchor.BN(1)).accounts(accounts).signers([openBuyer]).rpc(),
+      "MintMismatch"
+    );
+  });
+
+  it("recover_excess and close_escrow reject a buyer account of another mint", async () => {
+    const accounts = {
+      buyer: openBuyer.publicKey,
+      escrow: open.escrow,
+      vaultTokenAccount: open.vault,
+      buyerTokenAccount: openBuyerOtherToken,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+    await expectError(
+      program.methods.recoverExcess().accounts(accounts).signers([openBuyer]).rpc(),
+      "MintMismatch"
+    );
+    await expectError(
+      program.methods.closeEscrow().accounts(accounts).signers([openBuyer]).rpc(),
+      "MintMismatch"
+    );
+  });
+});